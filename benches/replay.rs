@@ -0,0 +1,55 @@
+//! Times replaying a generated log of 50k records for 1000 tasks
+//!
+//! Run with `cargo bench --bench replay`.
+use allesatt::engine::{try_new, MemStore, ReadWriteLogger};
+use std::fmt::Write;
+use std::io::{sink, Sink};
+use std::time::{Duration, Instant};
+use time::macros::{datetime, format_description};
+
+const TASKS: u64 = 1000;
+const RECORDS: u64 = 50_000;
+const RUNS: u32 = 5;
+
+/// Creates the tasks, then completes each of them once a day in turn
+fn generate_log() -> String {
+  let format = format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].0");
+  let mut log = String::new();
+  for task in 1..=TASKS {
+    writeln!(
+      log,
+      "create_task2: [\"Task {task}\", \"7days\", {task}, {task}]"
+    )
+    .unwrap();
+  }
+  let mut open: Vec<u64> = (1..=TASKS).collect();
+  for i in 0..RECORDS - TASKS {
+    let date =
+      datetime!(2020-01-01 10:00 UTC) + time::Duration::days((i / TASKS).try_into().unwrap());
+    let todo = &mut open[usize::try_from(i % TASKS).unwrap()];
+    writeln!(
+      log,
+      "complete_todo1: [{todo}, \"{}\"]",
+      date.format(&format).unwrap()
+    )
+    .unwrap();
+    // Each completion creates the next todo of its task
+    *todo = TASKS + 1 + i;
+  }
+  log
+}
+
+fn main() {
+  let log = generate_log();
+  let mut fastest = Duration::MAX;
+  for _ in 0..RUNS {
+    let start = Instant::now();
+    try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Sink, _>::new(log.as_bytes(), sink()),
+    )
+    .unwrap();
+    fastest = fastest.min(start.elapsed());
+  }
+  println!("replaying {RECORDS} records of {TASKS} tasks: {fastest:?} (fastest of {RUNS} runs)");
+}
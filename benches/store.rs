@@ -0,0 +1,102 @@
+//! Counts the allocations of listing todos from a store of 10k todos and times listing the open
+//! ones
+//!
+//! Run with `cargo bench --bench store`.
+use allesatt::engine::{MemStore, Store, TodoCompleted};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use time::macros::datetime;
+
+const TASKS: usize = 1000;
+const TODOS_PER_TASK: usize = 10;
+const RUNS: u32 = 5;
+
+/// Counts allocations and allocated bytes, passing them on to the system allocator
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout);
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Creates the tasks, each with one open todo and the others completed
+fn generate_store() -> MemStore {
+  let date = datetime!(2020-01-01 10:00 UTC);
+  let mut store = MemStore::new();
+  for task in 0..TASKS {
+    let task_id = store.create_task(format!("Task {task}"), date);
+    for _ in 1..TODOS_PER_TASK {
+      let todo_id = store.create_todo(&task_id, date);
+      store
+        .set_todo_completed(&todo_id, Some(TodoCompleted::new(date)))
+        .unwrap();
+    }
+    store.create_todo(&task_id, date);
+  }
+  store
+}
+
+/// Runs `f`, returning its result with the number of allocations and bytes it took
+fn count<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+  let (allocations, bytes) = (
+    ALLOCATIONS.load(Ordering::Relaxed),
+    BYTES.load(Ordering::Relaxed),
+  );
+  let result = f();
+  (
+    result,
+    ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+    BYTES.load(Ordering::Relaxed) - bytes,
+  )
+}
+
+/// Runs `f` `RUNS` times, returning the fastest run
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+  let mut fastest = Duration::MAX;
+  for _ in 0..RUNS {
+    let start = Instant::now();
+    black_box(f());
+    fastest = fastest.min(start.elapsed());
+  }
+  fastest
+}
+
+fn main() {
+  let store = generate_store();
+  let todos = TASKS * TODOS_PER_TASK;
+  let (collected, allocations, bytes) = count(|| store.get_todos(None, Some(true)).len());
+  println!(
+    "get_todos on {todos} todos: {collected} todos, {allocations} allocations, {bytes} bytes"
+  );
+  let (iterated, allocations, bytes) = count(|| store.iter_todos(None, Some(true)).count());
+  println!(
+    "iter_todos on {todos} todos: {iterated} todos, {allocations} allocations, {bytes} bytes"
+  );
+
+  let open_todos = time(|| store.open_todos().len());
+  println!("open_todos on {todos} todos: {open_todos:?} (fastest of {RUNS} runs)");
+  // What listing did before open_todos and the index of open todos: scan all todos per task
+  let scan = time(|| {
+    store
+      .get_tasks()
+      .into_iter()
+      .filter_map(|task| Some((store.iter_todos(Some(&task.id), Some(false)).next()?, task)))
+      .count()
+  });
+  println!("scanning for open todos per task on {todos} todos: {scan:?} (fastest of {RUNS} runs)");
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fs::{metadata, remove_file, rename, set_permissions, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Returns a path for a temporary file next to `path`, so that renaming it over `path` is atomic
+fn temp_path(path: &Path) -> PathBuf {
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Replaces the file at `path` with what `write` writes, without ever leaving it half-written
+///
+/// The content is written to a temporary file next to it, which is renamed over `path` only if
+/// `write` succeeds. An existing file's permissions are kept.
+pub fn write_atomically(
+  path: impl AsRef<Path>,
+  write: impl FnOnce(&mut BufWriter<&File>) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+  let path = path.as_ref();
+  let temp_path = temp_path(path);
+  let file = File::create(&temp_path)?;
+  let result = (|| {
+    if let Ok(metadata) = metadata(path) {
+      set_permissions(&temp_path, metadata.permissions())?;
+    }
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)?;
+    writer.flush()?;
+    file.sync_all()?;
+    rename(&temp_path, path)?;
+    Ok(())
+  })();
+  if result.is_err() {
+    let _ = remove_file(&temp_path);
+  }
+  result
+}
+
+// The test checks that Unix permissions are kept
+#[cfg(all(test, unix))]
+mod test {
+  use super::{temp_path, write_atomically};
+  use std::fs::{read_to_string, remove_file, set_permissions, write, Permissions};
+  use std::io::Write;
+  use std::os::unix::fs::PermissionsExt;
+
+  #[test]
+  fn keeps_original_on_failure() {
+    let path = std::env::temp_dir().join(format!("allesatt-atomic-{}", std::process::id()));
+    write(&path, "old\n").unwrap();
+    set_permissions(&path, Permissions::from_mode(0o600)).unwrap();
+
+    let result = write_atomically(&path, |file| {
+      writeln!(file, "partial")?;
+      Err("failed".into())
+    });
+    assert!(result.is_err());
+    assert_eq!(read_to_string(&path).unwrap(), "old\n");
+    assert!(!temp_path(&path).exists());
+
+    write_atomically(&path, |file| Ok(writeln!(file, "new")?)).unwrap();
+    assert_eq!(read_to_string(&path).unwrap(), "new\n");
+    assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    remove_file(path).unwrap();
+  }
+}
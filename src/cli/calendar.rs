@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::io::Write;
+use time::{Date, Duration};
+
+use crate::engine::Store;
+
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+/// Number of titles shown per day before summarizing the rest
+const MAX_TITLES: usize = 2;
+/// Narrower cells only show how many todos are due, as `day:count`
+const MIN_TITLE_WIDTH: usize = 8;
+
+/// Returns the width of the terminal stdout is connected to, or of a typical one
+pub fn terminal_width() -> usize {
+  let mut size = libc::winsize {
+    ws_row: 0,
+    ws_col: 0,
+    ws_xpixel: 0,
+    ws_ypixel: 0,
+  };
+  // SAFETY: TIOCGWINSZ only writes to the given winsize
+  if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+    && size.ws_col > 0
+  {
+    return size.ws_col.into();
+  }
+  env::var("COLUMNS")
+    .ok()
+    .and_then(|columns| columns.parse().ok())
+    .unwrap_or(80)
+}
+
+/// Writes a grid of the month starting at `first`, one week per row, with the open todos due on
+/// each day
+pub fn write_month<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  first: Date,
+  width: usize,
+) -> Result<(), Box<dyn Error>> {
+  let inner = (width / 7).clamp(4, 24) - 1;
+  let show_titles = inner >= MIN_TITLE_WIDTH;
+  let mut todos: Vec<_> = store
+    .open_todos()
+    .into_iter()
+    .filter(|(todo, _)| (todo.due.year(), todo.due.month()) == (first.year(), first.month()))
+    .collect();
+  todos.sort_by_key(|(todo, task)| (todo.due, &task.id));
+  let mut days: BTreeMap<Date, Vec<&str>> = BTreeMap::new();
+  for (todo, task) in todos {
+    days.entry(todo.due.date()).or_default().push(&task.title);
+  }
+
+  writeln!(output, "{} {}", first.month(), first.year())?;
+  write_row(output, inner, WEEKDAYS.map(String::from))?;
+  let mut week_start = first - Duration::days(first.weekday().number_days_from_monday().into());
+  while week_start.month() == first.month() || week_start < first {
+    let week: Vec<_> = (0..7)
+      .map(|offset| week_start + Duration::days(offset))
+      .map(|day| (day.month() == first.month()).then_some(day))
+      .collect();
+    let titles = |day: &Option<Date>| {
+      day
+        .and_then(|day| days.get(&day))
+        .map_or(&[][..], Vec::as_slice)
+    };
+    write_row(
+      output,
+      inner,
+      week.iter().map(|day| match (day, titles(day).len()) {
+        (None, _) => String::new(),
+        (Some(day), 0) => format!("{:2}", day.day()),
+        (Some(day), count) if !show_titles => format!("{:2}:{count}", day.day()),
+        (Some(day), _) => format!("{:2}", day.day()),
+      }),
+    )?;
+    let most = week.iter().map(|day| titles(day).len()).max().unwrap_or(0);
+    if show_titles {
+      for line in 0..most.min(MAX_TITLES) {
+        write_row(
+          output,
+          inner,
+          week.iter().map(|day| match titles(day) {
+            titles if line + 1 == MAX_TITLES && titles.len() > MAX_TITLES => {
+              format!("+{} more", titles.len() - line)
+            }
+            titles => titles
+              .get(line)
+              .map_or_else(String::new, |title| (*title).to_owned()),
+          }),
+        )?;
+      }
+    }
+    week_start += Duration::WEEK;
+  }
+  Ok(())
+}
+
+/// Writes one line of the grid, cutting off cells at `inner` characters
+fn write_row<W: Write>(
+  output: &mut W,
+  inner: usize,
+  cells: impl IntoIterator<Item = String>,
+) -> Result<(), Box<dyn Error>> {
+  let line = cells
+    .into_iter()
+    .map(|cell| format!("{:inner$}", cell.chars().take(inner).collect::<String>()))
+    .collect::<Vec<_>>()
+    .join(" ");
+  writeln!(output, "{}", line.trim_end())?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::write_month;
+  use crate::engine::{MemStore, Store};
+  use time::macros::{date, datetime};
+
+  #[test]
+  fn writes_month_grid() {
+    let mut store = MemStore::new();
+    for (title, due) in [
+      ("Sweep the floor", datetime!(2024-02-05 10:00 UTC)),
+      ("Water plants", datetime!(2024-02-05 12:00 UTC)),
+      ("Dust", datetime!(2024-02-05 14:00 UTC)),
+      ("Mop", datetime!(2024-02-29 10:00 UTC)),
+      ("Next month", datetime!(2024-03-01 10:00 UTC)),
+    ] {
+      let task_id = store.create_task(title.into(), due);
+      store.create_todo(&task_id, due);
+    }
+
+    let mut output = Vec::new();
+    write_month(&store, &mut output, date!(2024 - 02 - 01), 70).unwrap();
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      "February 2024
+Mo        Tu        We        Th        Fr        Sa        Su
+                               1         2         3         4
+ 5         6         7         8         9        10        11
+Sweep the
++2 more
+12        13        14        15        16        17        18
+19        20        21        22        23        24        25
+26        27        28        29
+                              Mop
+"
+    );
+
+    let mut output = Vec::new();
+    write_month(&store, &mut output, date!(2024 - 02 - 01), 42).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("\n 5:3 "));
+    assert!(!output.contains("Mop"));
+  }
+}
@@ -0,0 +1,373 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+use time_tz::{timezones, Tz};
+
+use super::{parse_every, parse_time_of_day, Cmd};
+
+/// Defaults read from `$XDG_CONFIG_HOME/allesatt/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+  pub file: Option<String>,
+  pub holidays: Option<String>,
+  /// File to append completions with a note to, one line each
+  pub journal: Option<String>,
+  /// Show a desktop notification about overdue todos when listing them in a terminal by default
+  pub notify_overdue: bool,
+  every: Option<String>,
+  snap_completions_to: Option<String>,
+  max_due: Option<usize>,
+  max_not_due: Option<usize>,
+  soon: Option<String>,
+  sample: bool,
+  timezone: Option<String>,
+  lock_timeout: Option<String>,
+  min_later: Option<String>,
+  max_later: Option<String>,
+  max_deferral: Option<String>,
+  hooks: HashMap<String, String>,
+  presets: HashMap<String, Preset>,
+}
+
+/// Defaults for tasks added with `add --preset`, overridden by options given on the command line
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct Preset {
+  every: Option<String>,
+  yearly: bool,
+  fixed: bool,
+  effort: Option<String>,
+  times: Option<u32>,
+  url: Option<String>,
+}
+
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Commands that can have a hook run after them
+const HOOKS: [&str; 3] = ["do", "later", "pause"];
+
+fn path() -> Option<PathBuf> {
+  env::var_os("XDG_CONFIG_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    .map(|dir| dir.join("allesatt").join("config.toml"))
+}
+
+/// Parses a duration that was already validated when loading the configuration
+fn parse_duration(duration: Option<&str>) -> Option<Duration> {
+  duration
+    .and_then(|duration| duration.parse::<humantime::Duration>().ok())
+    .map(Into::into)
+}
+
+impl Config {
+  pub fn load() -> Result<Self, Box<dyn Error>> {
+    let Some(path) = path() else {
+      return Ok(Self::default());
+    };
+    match read_to_string(&path) {
+      Ok(content) => Self::parse(&content).map_err(|e| format!("{}: {e}", path.display()).into()),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(format!("{}: {e}", path.display()).into()),
+    }
+  }
+
+  pub(super) fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+    let config: Self = toml::from_str(content)?;
+    if let Some(every) = &config.every {
+      parse_every(every)?;
+    }
+    if let Some(soon) = &config.soon {
+      soon.parse::<humantime::Duration>()?;
+    }
+    if let Some(timeout) = &config.lock_timeout {
+      timeout.parse::<humantime::Duration>()?;
+    }
+    for later in [&config.min_later, &config.max_later, &config.max_deferral]
+      .into_iter()
+      .flatten()
+    {
+      later.parse::<humantime::Duration>()?;
+    }
+    for (name, preset) in &config.presets {
+      if let Some(every) = &preset.every {
+        parse_every(every).map_err(|e| format!("Preset {name}: {e}"))?;
+      }
+      if let Some(effort) = &preset.effort {
+        effort
+          .parse::<humantime::Duration>()
+          .map_err(|e| format!("Preset {name}: {e}"))?;
+      }
+    }
+    if let Some(time) = &config.snap_completions_to {
+      parse_time_of_day(time)?;
+    }
+    if let Some(name) = config
+      .hooks
+      .keys()
+      .find(|name| !HOOKS.contains(&name.as_str()))
+    {
+      return Err(format!("Unknown hook {name}, expected one of {}", HOOKS.join(", ")).into());
+    }
+    if let Some(timezone) = &config.timezone {
+      timezones::get_by_name(timezone).ok_or_else(|| format!("Unknown timezone {timezone}"))?;
+    }
+    Ok(config)
+  }
+
+  /// Timezone to keep the local time of day in when adding whole days
+  pub fn timezone(&self) -> Option<&'static Tz> {
+    self.timezone.as_deref().and_then(timezones::get_by_name)
+  }
+
+  /// How long to wait for another process to unlock the log file
+  pub fn lock_timeout(&self) -> Duration {
+    self
+      .lock_timeout
+      .as_ref()
+      .and_then(|timeout| timeout.parse::<humantime::Duration>().ok())
+      .map_or(DEFAULT_LOCK_TIMEOUT, Into::into)
+  }
+
+  /// Least time the later command pushes a todo back by
+  pub fn min_later(&self) -> Option<Duration> {
+    parse_duration(self.min_later.as_deref())
+  }
+
+  /// Most time a single later command pushes a todo back by
+  pub fn max_later(&self) -> Option<Duration> {
+    parse_duration(self.max_later.as_deref())
+  }
+
+  /// Most time the later command pushes a todo back by in total
+  pub fn max_deferral(&self) -> Option<Duration> {
+    parse_duration(self.max_deferral.as_deref())
+  }
+
+  /// Shell command template to run after the given command
+  pub fn hook(&self, name: &str) -> Option<&str> {
+    self.hooks.get(name).map(String::as_str)
+  }
+
+  /// Fills in defaults for everything not given on the command line
+  ///
+  /// For `add`, options given on the command line take precedence over the preset, which takes
+  /// precedence over the configured defaults.
+  pub fn apply(&self, mut cmd: Cmd) -> Result<Cmd, Box<dyn Error>> {
+    match &mut cmd {
+      Cmd::Add(args) => {
+        if let Some(name) = &args.preset {
+          let preset = self
+            .presets
+            .get(name)
+            .ok_or_else(|| format!("Unknown preset {name}"))?;
+          if args.every().is_none() && !args.yearly {
+            args.every = preset
+              .every
+              .as_deref()
+              .and_then(|every| parse_every(every).ok());
+            args.yearly = preset.yearly;
+          }
+          args.fixed |= preset.fixed;
+          if args.effort.is_none() {
+            args.effort = preset
+              .effort
+              .as_ref()
+              .and_then(|effort| effort.parse().ok());
+          }
+          args.times = args.times.or(preset.times);
+          args.url = args.url.take().or_else(|| preset.url.clone());
+        }
+        if args.every().is_none() && !args.yearly {
+          args.every = self
+            .every
+            .as_deref()
+            .and_then(|every| parse_every(every).ok());
+        }
+      }
+      Cmd::Do { snap_to, .. } if snap_to.is_none() => {
+        *snap_to = self
+          .snap_completions_to
+          .as_deref()
+          .and_then(|time| parse_time_of_day(time).ok());
+      }
+      Cmd::List(args) => {
+        args.max_due = args.max_due.or(self.max_due);
+        args.max_not_due = args.max_not_due.or(self.max_not_due);
+        if args.soon.is_none() {
+          args.soon = self.soon.as_ref().and_then(|soon| soon.parse().ok());
+        }
+        args.sample |= self.sample && !args.most_overdue && !args.by_urgency;
+      }
+      _ => (),
+    }
+    Ok(cmd)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{AddArgs, Cmd, ListArgs};
+  use super::Config;
+  use std::time::Duration;
+  use time::macros::time;
+  use time_tz::TimeZone;
+
+  #[test]
+  fn applies_defaults() {
+    let config = Config::parse(
+      "file = \"log\"\nevery = \"7days\"\nmax-due = 10\nsoon = \"3days\"\nsample = true\n",
+    )
+    .unwrap();
+    assert_eq!(config.file.as_deref(), Some("log"));
+
+    let Cmd::Add(args) = config.apply(Cmd::Add(AddArgs::default())).unwrap() else {
+      unreachable!()
+    };
+    assert_eq!(args.every.unwrap().to_string(), "7days");
+    let Cmd::Add(args) = config
+      .apply(Cmd::Add(AddArgs {
+        every: Some("1day".parse().unwrap()),
+        ..AddArgs::default()
+      }))
+      .unwrap()
+    else {
+      unreachable!()
+    };
+    assert_eq!(args.every.unwrap().to_string(), "1day");
+
+    let Cmd::List(args) = config.apply(Cmd::List(ListArgs::default())).unwrap() else {
+      unreachable!()
+    };
+    assert_eq!(args.max_due, Some(10));
+    assert_eq!(args.max_not_due, None);
+    assert_eq!(args.soon.unwrap().to_string(), "3days");
+    assert!(args.sample);
+    let Cmd::List(args) = config
+      .apply(Cmd::List(ListArgs {
+        most_overdue: true,
+        ..ListArgs::default()
+      }))
+      .unwrap()
+    else {
+      unreachable!()
+    };
+    assert!(!args.sample);
+  }
+
+  #[test]
+  fn applies_presets() {
+    let config = Config::parse(
+      "every = \"7days\"\n[presets.plant]\nevery = \"3/week\"\neffort = \"5m\"\nfixed = true\n",
+    )
+    .unwrap();
+    let add = |args| {
+      let Cmd::Add(args) = config.apply(Cmd::Add(args))? else {
+        unreachable!()
+      };
+      Ok::<_, Box<dyn std::error::Error>>(args)
+    };
+    let args = add(AddArgs {
+      preset: Some("plant".into()),
+      ..AddArgs::default()
+    })
+    .unwrap();
+    assert_eq!(args.every.unwrap().to_string(), "2days 8h");
+    assert_eq!(args.effort.unwrap().to_string(), "5m");
+    assert!(args.fixed);
+    let args = add(AddArgs {
+      preset: Some("plant".into()),
+      every: Some("1day".parse().unwrap()),
+      ..AddArgs::default()
+    })
+    .unwrap();
+    assert_eq!(args.every.unwrap().to_string(), "1day");
+    let args = add(AddArgs {
+      preset: Some("plant".into()),
+      yearly: true,
+      ..AddArgs::default()
+    })
+    .unwrap();
+    assert!(args.every.is_none());
+    assert!(add(AddArgs {
+      preset: Some("tree".into()),
+      ..AddArgs::default()
+    })
+    .is_err());
+    assert!(Config::parse("[presets.plant]\nevery = \"often\"\n").is_err());
+    assert!(Config::parse("[presets.plant]\ntag = \"green\"\n").is_err());
+  }
+
+  #[test]
+  fn snaps_completions() {
+    let config = Config::parse("snap-completions-to = \"12:00\"\n").unwrap();
+    let Cmd::Do { snap_to, .. } = config
+      .apply(Cmd::Do {
+        ids: vec!["1".parse().unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      })
+      .unwrap()
+    else {
+      unreachable!()
+    };
+    assert_eq!(snap_to, Some(time!(12:00)));
+    assert!(Config::parse("snap-completions-to = \"noon\"\n").is_err());
+  }
+
+  #[test]
+  fn rejects_invalid_interval() {
+    assert!(Config::parse("every = \"often\"\n").is_err());
+  }
+
+  #[test]
+  fn reads_hooks() {
+    let config = Config::parse("[hooks]\ndo = \"notify-send {title}\"\n").unwrap();
+    assert_eq!(config.hook("do"), Some("notify-send {title}"));
+    assert_eq!(config.hook("pause"), None);
+    assert!(Config::parse("[hooks]\nrename = \"true\"\n").is_err());
+  }
+
+  #[test]
+  fn reads_timezone() {
+    let config = Config::parse("timezone = \"Europe/Berlin\"\n").unwrap();
+    assert_eq!(config.timezone().unwrap().name(), "Europe/Berlin");
+    assert!(Config::parse("timezone = \"Mars/Olympus\"\n").is_err());
+  }
+
+  #[test]
+  fn reads_lock_timeout() {
+    assert_eq!(Config::default().lock_timeout(), Duration::from_secs(5));
+    let config = Config::parse("lock-timeout = \"1m\"\n").unwrap();
+    assert_eq!(config.lock_timeout(), Duration::from_mins(1));
+    assert!(Config::parse("lock-timeout = \"soon\"\n").is_err());
+  }
+
+  #[test]
+  fn reads_min_later() {
+    assert_eq!(Config::default().min_later(), None);
+    let config = Config::parse("min-later = \"2h\"\n").unwrap();
+    assert_eq!(config.min_later(), Some(Duration::from_hours(2)));
+    assert!(Config::parse("min-later = \"a bit\"\n").is_err());
+  }
+
+  #[test]
+  fn reads_later_caps() {
+    assert_eq!(Config::default().max_later(), None);
+    let config = Config::parse("max-later = \"3days\"\nmax-deferral = \"2weeks\"\n").unwrap();
+    assert_eq!(config.max_later(), Some(Duration::from_hours(72)));
+    assert_eq!(config.max_deferral(), Some(Duration::from_hours(14 * 24)));
+    assert!(Config::parse("max-deferral = \"forever\"\n").is_err());
+  }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+
+use super::DAY_FORMAT;
+use crate::engine::{get_todos, Limits, Store, TodoCompleted, DEFAULT_SOON};
+
+/// Writes open todos and completions as a Markdown checklist
+///
+/// Without `all`, only the todos `List` shows by default and the last completion of each task
+/// are included.
+pub fn write_markdown<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  all: bool,
+) -> Result<(), Box<dyn Error>> {
+  let (todos, _, _) = get_todos(store, all, None, &Limits::default(), DEFAULT_SOON, None);
+  writeln!(output, "## To do")?;
+  writeln!(output)?;
+  for (todo, task) in todos {
+    let due = todo.due.format(&DAY_FORMAT)?;
+    writeln!(output, "- [ ] {} (due {due})", task.title)?;
+  }
+
+  let mut completions: Vec<_> = store
+    .iter_todos(None, Some(true))
+    .map(|todo| (&todo.task, todo.completed.as_ref().unwrap()))
+    .collect();
+  completions.sort_by_key(|(_, completed)| completed.date);
+  if !all {
+    let last: HashMap<_, &TodoCompleted> = completions.into_iter().collect();
+    completions = last.into_iter().collect();
+    completions.sort_by_key(|(_, completed)| completed.date);
+  }
+  writeln!(output)?;
+  writeln!(output, "## Done")?;
+  writeln!(output)?;
+  for (task_id, completed) in completions {
+    let title = &store.get_task(task_id).ok_or("Task not found")?.title;
+    let done = completed.date.format(&DAY_FORMAT)?;
+    writeln!(output, "- [x] {title} (done {done})")?;
+  }
+  Ok(())
+}
+
+/// Writes open todos as an OPML outline for outliners, with their due dates as attributes
+///
+/// Without `all`, only the todos `List` shows by default are included. There are no categories
+/// to group tasks by, so the outline is flat.
+pub fn write_opml<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  all: bool,
+) -> Result<(), Box<dyn Error>> {
+  let (todos, _, _) = get_todos(store, all, None, &Limits::default(), DEFAULT_SOON, None);
+  writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+  writeln!(output, r#"<opml version="2.0">"#)?;
+  writeln!(output, "  <head>")?;
+  writeln!(output, "    <title>allesatt</title>")?;
+  writeln!(output, "  </head>")?;
+  writeln!(output, "  <body>")?;
+  for (todo, task) in todos {
+    let due = todo.due.format(&DAY_FORMAT)?;
+    writeln!(
+      output,
+      r#"    <outline text="{}" task="{}" due="{due}"/>"#,
+      escape_xml(&task.title),
+      task.id
+    )?;
+  }
+  writeln!(output, "  </body>")?;
+  writeln!(output, "</opml>")?;
+  Ok(())
+}
+
+/// Escapes text for use in an XML attribute value
+fn escape_xml(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod test {
+  use super::{write_markdown, write_opml};
+  use crate::engine::{MemStore, Store, TodoCompleted};
+  use time::macros::datetime;
+
+  #[test]
+  fn writes_checklist() {
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Sweep".into(), datetime!(2024-01-01 0:00 UTC));
+    for day in [1, 8] {
+      let date = datetime!(2024-01-01 10:00 UTC).replace_day(day).unwrap();
+      let todo_id = store.create_todo(&task_id, date);
+      store
+        .set_todo_completed(&todo_id, Some(TodoCompleted::new(date)))
+        .unwrap();
+    }
+    store.create_todo(&task_id, datetime!(2024-01-15 10:00 UTC));
+
+    let mut output = Vec::new();
+    write_markdown(&store, &mut output, false).unwrap();
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      "## To do\n\n- [ ] Sweep (due 2024-01-15)\n\n## Done\n\n- [x] Sweep (done 2024-01-08)\n"
+    );
+
+    let mut output = Vec::new();
+    write_markdown(&store, &mut output, true).unwrap();
+    assert!(String::from_utf8(output)
+      .unwrap()
+      .ends_with("- [x] Sweep (done 2024-01-01)\n- [x] Sweep (done 2024-01-08)\n"));
+  }
+  #[test]
+  fn writes_outline() {
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Sweep & mop".into(), datetime!(2024-01-01 0:00 UTC));
+    store.create_todo(&task_id, datetime!(2024-01-15 10:00 UTC));
+
+    let mut output = Vec::new();
+    write_opml(&store, &mut output, false).unwrap();
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>allesatt</title>
+  </head>
+  <body>
+    <outline text="Sweep &amp; mop" task="1" due="2024-01-15"/>
+  </body>
+</opml>
+"#
+    );
+  }
+}
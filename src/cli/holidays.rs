@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::read_to_string;
+use time::macros::format_description;
+use time::Date;
+
+use super::parse_day;
+
+/// Reads holidays from a file with one YYYY-MM-DD per line or from an iCalendar file
+pub fn read_holidays(path: &str) -> Result<BTreeSet<Date>, Box<dyn Error>> {
+  let content = read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+  parse_holidays(&content).map_err(|e| format!("{path}: {e}").into())
+}
+
+/// Parses a list of days, or takes the start day of every event in an iCalendar file
+fn parse_holidays(content: &str) -> Result<BTreeSet<Date>, String> {
+  let lines = content.lines().map(str::trim);
+  if content.trim_start().starts_with("BEGIN:VCALENDAR") {
+    lines
+      .filter_map(|line| line.strip_prefix("DTSTART"))
+      .map(|value| {
+        let value = value.rsplit(':').next().unwrap_or_default();
+        Date::parse(
+          value.get(..8).unwrap_or(value),
+          format_description!("[year][month][day]"),
+        )
+        .map_err(|e| format!("{value}: {e}"))
+      })
+      .collect()
+  } else {
+    lines
+      .filter(|line| !line.is_empty() && !line.starts_with('#'))
+      .map(|line| parse_day(line).map_err(|e| format!("{line}: {e}")))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::parse_holidays;
+  use time::macros::date;
+
+  #[test]
+  fn parses_holidays() {
+    let expected = [date!(2024 - 12 - 25), date!(2024 - 12 - 26)].into();
+    assert_eq!(
+      parse_holidays("# Christmas\n2024-12-25\n\n2024-12-26\n").unwrap(),
+      expected
+    );
+    let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20241225\r\nSUMMARY:Christmas\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nDTSTART:20241226T000000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    assert_eq!(parse_holidays(ics).unwrap(), expected);
+    assert!(parse_holidays("2024-13-01\n").is_err());
+  }
+}
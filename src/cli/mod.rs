@@ -1,440 +1,4719 @@
 use crate::engine::{
-  get_todos, try_new as try_new_engine, Allesatt, ReadWriteLogger, Store, Task, TaskId,
-  TodoCompleted, TodoDate, TodoId,
+  convert_log, get_todos, is_due, prune, remaining_completions, renumber, try_from_snapshot,
+  try_new_with_calendar, Allesatt, Calendar, Inconsistency, IntervalKind, Limits, LogFormat,
+  OpenTodoError, ReadWriteLogger, Store, Task, TaskId, Todo, TodoCompleted, TodoDate, TodoId,
+  DEFAULT_SOON, LOG_COMMANDS,
 };
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use humantime::Duration as HumanDuration;
+use regex::{NoExpand, Regex};
+use serde::{de::DeserializeOwned, Serialize};
 use std::borrow::{Borrow, BorrowMut};
+use std::collections::HashSet;
 use std::error::Error;
-use std::fs::OpenOptions;
-use std::io::{self, stderr, stdin, stdout, Stdout, Write};
-use time::format_description::FormatItem;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{
+  self, stderr, stdin, stdout, BufRead, BufReader, ErrorKind, Read, Seek, Stdout, Write,
+};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use time::format_description::{well_known::Rfc3339, FormatItem};
 use time::macros::format_description;
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime, Time, UtcOffset};
+use time_tz::{OffsetDateTimeExt, Tz};
+
+mod atomic_write;
+mod calendar;
+mod config;
+mod export;
+mod holidays;
+mod task_ref;
+
+use atomic_write::write_atomically;
+use calendar::{terminal_width, write_month};
+use config::Config;
+use export::{write_markdown, write_opml};
+use holidays::read_holidays;
+use task_ref::{parse_alias, TaskRef};
 
 const DAY_FORMAT: &[FormatItem<'static>] = format_description!("[year]-[month]-[day]");
+const DAY_TIME_FORMAT: &[FormatItem<'static>] =
+  format_description!("[year]-[month]-[day] [hour]:[minute]");
+const REPLAY_PROGRESS_EVERY: usize = 10_000;
+const DEFAULT_EVERY: Duration = Duration::from_hours(30 * 24);
+/// Shown by `list` in a terminal if there are no tasks at all
+const EMPTY_HINT: &str = "No tasks yet. Add one with `allesatt add <description>`";
 
 #[derive(Debug, Parser)]
 #[command(name = "Allesatt", author, version, about)]
 struct Opts {
-  #[arg(long, short, default_value = "-")]
+  #[arg(long, short)]
   /// File to read from and write to. If missing or -, will use stdout and stdin.
-  file: String,
+  file: Option<String>,
+
+  #[arg(long, short)]
+  /// File to write the output of read-only commands to instead of stdout. Doesn't work with open
+  output: Option<String>,
+
+  #[arg(long)]
+  /// File with days on which nothing becomes due, one YYYY-MM-DD per line or in iCalendar format
+  holidays: Option<String>,
+
+  #[arg(long, value_parser = parse_day)]
+  /// List todos as they were open at the end of this day (YYYY-MM-DD). Only works with list, is
+  /// read-only and approximate: only later completions are left out, not other changes.
+  as_of: Option<Date>,
+
+  #[arg(long)]
+  /// File to keep a snapshot of the state in, so that only log records added since are replayed.
+  /// Only works with --file. The log is still read, and if it changed other than by adding
+  /// records (judged by a hash of the records), it is replayed fully and the snapshot is written
+  /// anew.
+  snapshot: Option<String>,
+
+  #[arg(long)]
+  /// Print list and done as `<task id> <due> <state> <title>` lines for scripts. Unlike the
+  /// normal output, this format will stay the same in future versions. Due is in RFC 3339 (UTC)
+  /// or - if there is none, state is one of overdue, open, blocked, paused and done.
+  porcelain: bool,
+
+  #[arg(long, default_value = "v1")]
+  /// Format to write log records in, v1 (JSON) or human (readable diffs). Both can be read.
+  log_format: LogFormat,
 
   #[command(subcommand)]
   cmd: Option<Cmd>,
 }
 
-#[derive(Debug, Subcommand)]
+#[derive(Clone, Debug, Subcommand)]
 enum Cmd {
   #[clap(visible_alias("ls"))]
   /// List tasks
-  List {
+  List(ListArgs),
+
+  /// Add a new task
+  Add(AddArgs),
+
+  /// Add tasks from a text file with one `<every> <description>` or `<description>` per line,
+  /// skipping empty lines and lines starting with #
+  AddBatch { file: String },
+
+  /// Clone a task
+  Clone { id: TaskRef, description: String },
+
+  /// Complete tasks
+  Do {
+    #[arg(required = true)]
+    ids: Vec<TaskRef>,
+    #[arg(long, value_parser = parse_time_of_day)]
+    /// Record the completion at this earlier time of day (HH:MM, in the configured timezone)
+    /// instead of now
+    snap_to: Option<Time>,
+    #[arg(long, short)]
+    /// Don't ask for confirmation if the task isn't due yet
+    yes: bool,
+    #[arg(long)]
+    /// Something worth remembering about this completion
+    note: Option<String>,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Record this many completions, spread evenly since the last one (or since the task became
+    /// due)
+    repeat: u32,
+    #[arg(long)]
+    /// Unpause the task if it is paused, recording the completion anyway
+    unpause: bool,
+    #[arg(long)]
+    /// Skip the task if it was already done within this time, e.g. when running periodically
+    once_per: Option<HumanDuration>,
+  },
+
+  /// Show completed tasks
+  Done {
+    id: Option<TaskRef>,
+    #[arg(long)]
+    /// Print as JSON, with the date each todo was due at, e.g. for analyzing how often tasks are
+    /// done on time
+    json: bool,
+    #[arg(long, value_parser = parse_day)]
+    /// Only show todos done on or after this day (YYYY-MM-DD, UTC)
+    since: Option<Date>,
+    #[arg(long, value_parser = parse_day)]
+    /// Only show todos done on or before this day (YYYY-MM-DD, UTC)
+    until: Option<Date>,
+    #[arg(skip)]
+    /// Print in the stable format, set by the global --porcelain
+    porcelain: bool,
+  },
+
+  /// Change the day a task was done on
+  Amend {
+    id: TaskRef,
+    #[arg(value_parser = parse_day)]
+    /// Day the task was recorded as done on (YYYY-MM-DD)
+    old: Date,
+    #[arg(value_parser = parse_day)]
+    /// Day the task was actually done on (YYYY-MM-DD)
+    new: Date,
+  },
+
+  /// Move a completion to another task, e.g. if it was recorded for the wrong one
+  Reattach {
+    id: TaskRef,
+    #[arg(value_parser = parse_day)]
+    /// Day the task was recorded as done on (YYYY-MM-DD)
+    day: Date,
+    /// Task that was actually done
+    to: TaskRef,
+  },
+
+  /// Show everything known about a task
+  Info {
+    id: TaskRef,
+    #[arg(long)]
+    /// Show averaged intervals to the minute instead of rounding them, e.g. to ~4 weeks
+    exact: bool,
+  },
+
+  /// Print a human-readable summary of what is due, e.g. for mailing it
+  Digest,
+
+  /// Print projected due dates of all tasks, assuming they are done on time
+  Schedule {
+    #[arg(long, value_parser = parse_day)]
+    /// Last day to project due dates for (YYYY-MM-DD)
+    until: Date,
+    #[arg(long, value_enum, default_value_t = ScheduleFormat::Csv)]
+    format: ScheduleFormat,
+  },
+
+  /// Count the todos that will be due in some days if none are done until then
+  Forecast {
+    days: u32,
+    #[arg(long, short)]
+    /// List the todos, too
+    verbose: bool,
+  },
+
+  /// Show the todos due in a month as a grid of weeks
+  Calendar {
+    #[arg(value_parser = parse_month)]
+    /// Month to show (YYYY-MM) [default: the current month]
+    month: Option<Date>,
+  },
+
+  /// Export todos and completions, e.g. for pasting them somewhere else
+  Export {
+    #[arg(long, value_enum, default_value_t = ExportFormat::Markdown)]
+    format: ExportFormat,
     #[arg(long)]
-    /// Show all todos (the default is to only show a few todos)
+    /// Include all todos and completions (the default is to include the todos shown by list and
+    /// the last completion of each task)
     all: bool,
   },
 
-  /// Add a new task
-  Add {
-    #[arg(long, default_value = "30days")]
-    every: HumanDuration,
-    description: String,
+  /// Open a task's link
+  Open { id: TaskRef },
+
+  /// Add a name to refer to a task by as @name
+  Alias {
+    id: TaskRef,
+    #[arg(value_parser = parse_alias)]
+    name: String,
   },
 
-  /// Clone a task
-  Clone { id: TaskId, description: String },
+  /// Exchange the ids of two tasks
+  Swap { a: TaskRef, b: TaskRef },
 
-  /// Complete a task
-  Do { id: TaskId },
+  /// Change the description of a task
+  Rename { id: TaskRef, description: String },
 
-  /// Show completed tasks
-  Done { id: Option<TaskId> },
+  /// Change the description of all tasks matching a pattern
+  ReplaceTitles {
+    pattern: String,
+    replacement: String,
+    #[arg(long)]
+    /// Interpret the pattern as a regular expression
+    regex: bool,
+    #[arg(long)]
+    /// Only show what would be renamed
+    dry_run: bool,
+  },
+
+  /// Set the estimated time needed for doing a task
+  Effort { id: TaskRef, effort: HumanDuration },
+
+  /// List a task as due this long before it actually is, e.g. for ordering things it needs
+  Lead { id: TaskRef, lead: HumanDuration },
+
+  /// Mark tasks as being due later
+  Later {
+    #[arg(required = true)]
+    ids: Vec<TaskRef>,
+  },
+
+  /// Take back the most recent later of a task, making it due when it was before
+  Unlater { id: TaskRef },
+
+  /// Record that some of the steps of a task are done, without completing it
+  Progress { id: TaskRef, done: u32, total: u32 },
+
+  /// Restart a task's interval as if it was just done, without recording a completion
+  Touch { id: TaskRef },
 
-  /// Mark a task as being due later
-  Later { id: TaskId },
+  /// Push all due tasks back by the same amount of time
+  DeferAll {
+    by: HumanDuration,
+    #[arg(long)]
+    /// Only push back tasks that are already overdue
+    only_overdue: bool,
+  },
+
+  /// Recalculate the due dates of all open todos with the current settings, e.g. after
+  /// configuring holidays
+  Recompute {
+    #[arg(long)]
+    /// Only show which due dates would change
+    dry_run: bool,
+  },
 
-  /// Mark a task as not needing doing currently
-  Pause { id: TaskId },
+  /// Mark tasks as not needing doing currently
+  Pause {
+    #[arg(required = true)]
+    ids: Vec<TaskRef>,
+  },
 
   /// Mark a task as needing doing again
-  Unpause { id: TaskId },
+  Unpause {
+    id: TaskRef,
+    #[arg(long, value_parser = parse_day)]
+    /// Make the task due on this day (YYYY-MM-DD) instead of now
+    at: Option<Date>,
+  },
+
+  /// Flag a task as blocked for an external reason, listing it separately until unblocked
+  Block {
+    id: TaskRef,
+    /// Why the task can't be done currently
+    reason: String,
+  },
+
+  /// Stop flagging a task as blocked
+  Unblock { id: TaskRef },
+
+  /// Make a task due its interval after another one was done, e.g. tires after an oil change
+  After {
+    id: TaskRef,
+    /// Task to count the interval from, omit to only count from the task itself again
+    anchor: Option<TaskRef>,
+  },
+
+  /// Make a task due its interval after it was last due rather than after it was done, so doing
+  /// it late doesn't move it later
+  FromDue {
+    id: TaskRef,
+    #[arg(long)]
+    /// Count from when the task was done again
+    off: bool,
+  },
+
+  /// Stop adapting a task's interval, keeping the current one
+  Learn { id: TaskRef },
+
+  /// Adapt a task's interval to when it is done again
+  Unlearn { id: TaskRef },
+
+  /// Check the log for inconsistencies, e.g. after editing it by hand
+  Check {
+    #[arg(long)]
+    /// Repair tasks with several open todos by discarding all but the earliest due one
+    fix: bool,
+  },
+
+  /// Print the log with fresh sequential ids, e.g. after removing lines from it
+  Renumber,
+
+  /// Print the log in the format given by --log-format
+  Convert,
+
+  /// Print the log without completions older than the given time, e.g. for privacy. The latest
+  /// completions of each task are kept, so that its interval stays about the same.
+  Prune {
+    #[arg(long)]
+    /// How long to keep completions for, e.g. 2years
+    keep: HumanDuration,
+  },
+
+  /// Print the version and the log commands this version understands
+  Version {
+    #[arg(long)]
+    /// Print as JSON, e.g. for checking compatibility before writing to a shared log
+    json: bool,
+  },
+}
+
+#[derive(Args, Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct ListArgs {
+  #[arg(long)]
+  /// Show all todos (the default is to only show a few todos)
+  all: bool,
+
+  #[arg(long, conflicts_with = "all")]
+  /// Only show as many due todos as fit into this total effort
+  budget: Option<HumanDuration>,
+
+  #[arg(long)]
+  /// Group todos by when they are due
+  grouped: bool,
+
+  #[arg(long)]
+  /// Maximum number of due todos to show
+  max_due: Option<usize>,
+
+  #[arg(long)]
+  /// Count todos due within this time as due rather than upcoming [default: 1day]
+  soon: Option<HumanDuration>,
+
+  #[arg(long)]
+  /// Maximum number of todos to show if not many are due
+  max_not_due: Option<usize>,
+
+  #[arg(long, conflicts_with_all = ["all", "budget"])]
+  /// Show a random selection of due todos if there are too many, rather than the most overdue
+  sample: bool,
+
+  #[arg(long, conflicts_with = "sample")]
+  /// Show the most overdue todos if there are too many, even if sampling is configured
+  most_overdue: bool,
+
+  #[arg(long, conflicts_with_all = ["budget", "sample", "grouped", "after", "limit"])]
+  /// Order due todos by how overdue they are relative to how often they are due, so that a daily
+  /// task a week overdue comes before a monthly one
+  by_urgency: bool,
+
+  #[arg(long, conflicts_with = "upcoming_only")]
+  /// Only show todos that are due
+  due_only: bool,
+
+  #[arg(long, conflicts_with = "budget")]
+  /// Only show todos that aren't due yet
+  upcoming_only: bool,
+
+  #[arg(long, conflicts_with_all = ["budget", "grouped"])]
+  /// Only show tasks with a greater id, in id order rather than due order
+  after: Option<TaskId>,
+
+  #[arg(long, conflicts_with_all = ["budget", "grouped"])]
+  /// Maximum number of tasks to show, in id order rather than due order
+  limit: Option<usize>,
+
+  #[arg(long)]
+  /// Show the time todos are due at (UTC), which is shown by default for tasks due more than once
+  /// a day
+  with_time: bool,
+
+  #[arg(long)]
+  /// Keep listing tasks done today, marked with ✓, instead of only showing when they are due next
+  done_today: bool,
+
+  #[arg(long)]
+  /// Start each line with the task's status: ! for overdue, · for due today, P for paused
+  status_col: bool,
+
+  #[arg(long, conflicts_with_all = ["grouped", "after", "limit"])]
+  /// Print one JSON object per todo and paused task, e.g. for processing with jq
+  ndjson: bool,
+
+  #[arg(skip)]
+  /// Show todos as they were open at this time, set by the global --as-of
+  as_of: Option<TodoDate>,
+
+  #[arg(skip)]
+  /// Print in the stable format, set by the global --porcelain
+  porcelain: bool,
+
+  #[arg(skip)]
+  /// Say how to add a task if there are none, set when listing to a terminal
+  hint_empty: bool,
+}
+
+#[derive(Args, Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct AddArgs {
+  #[arg(long, value_parser = parse_every)]
+  /// How often the task needs doing, as an interval or a frequency like 3/week (every 2days 8h),
+  /// [default: 30days]
+  every: Option<HumanDuration>,
+  #[arg(long, conflicts_with_all = ["every", "weekly", "biweekly", "monthly", "yearly"])]
+  /// Shorthand for --every 1day
+  daily: bool,
+  #[arg(long, conflicts_with_all = ["every", "biweekly", "monthly", "yearly"])]
+  /// Shorthand for --every 7days
+  weekly: bool,
+  #[arg(long, conflicts_with_all = ["every", "monthly", "yearly"])]
+  /// Shorthand for --every 14days
+  biweekly: bool,
+  #[arg(long, conflicts_with_all = ["every", "yearly"])]
+  /// Shorthand for --every 30days, use --yearly for a fixed day in the year
+  monthly: bool,
+  #[arg(long, conflicts_with = "every")]
+  /// Make the task due every year on the day it was added
+  yearly: bool,
+  #[arg(long, visible_alias = "no-learn", conflicts_with = "yearly")]
+  /// Always make the task due this interval after it was done, never adapting it (use unlearn to
+  /// adapt it again)
+  fixed: bool,
+  #[arg(long, conflicts_with = "yearly")]
+  /// Make the task due its interval after it was last due rather than after it was done
+  from_due: bool,
+  #[arg(long)]
+  /// Estimated time needed for doing the task
+  effort: Option<HumanDuration>,
+  #[arg(long)]
+  /// List the task as due this long before it actually is, e.g. for ordering things it needs
+  lead: Option<HumanDuration>,
+  #[arg(long)]
+  /// Pause the task after it has been done this many times
+  times: Option<u32>,
+  #[arg(long)]
+  /// Link to open with the open command
+  url: Option<String>,
+  #[arg(long)]
+  /// Preset from the configuration to take defaults from, overridden by the options given here
+  preset: Option<String>,
+  #[arg(long)]
+  /// Add the task even if another one has the same title
+  allow_duplicate: bool,
+  description: String,
+}
+
+impl ListArgs {
+  /// How many todos to get at most, see `get_todos`
+  ///
+  /// With --by-urgency, these are all due todos of the given number of tasks, since which of
+  /// them are the most urgent is only known after getting them.
+  fn limits(&self, tasks: usize) -> Limits {
+    let default_limits = Limits::default();
+    Limits {
+      due: if self.by_urgency {
+        tasks
+      } else {
+        self.max_due.unwrap_or(default_limits.due)
+      },
+      not_due: self.max_not_due.unwrap_or(default_limits.not_due),
+      sample: self.sample,
+    }
+  }
+}
+
+impl AddArgs {
+  /// The interval given with --every or one of the shorthands like --weekly
+  fn every(&self) -> Option<Duration> {
+    let days = [
+      (self.daily, 1),
+      (self.weekly, 7),
+      (self.biweekly, 14),
+      (self.monthly, 30),
+    ];
+    days
+      .into_iter()
+      .find(|(given, _)| *given)
+      .map(|(_, days)| Duration::from_hours(24 * days))
+      .or_else(|| self.every.map(Into::into))
+  }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ScheduleFormat {
+  Json,
+  Csv,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ExportFormat {
+  Markdown,
+  /// Outline for outliners, only with open todos
+  Opml,
+}
+
+fn parse_day(s: &str) -> Result<Date, String> {
+  Date::parse(s, DAY_FORMAT).map_err(|e| e.to_string())
+}
+
+/// Parses an interval, or a frequency like `3/week`, `3x per week` or `2 per month`
+///
+/// Frequencies are converted to the interval they imply, rounded to whole minutes.
+fn parse_every(s: &str) -> Result<HumanDuration, String> {
+  let Some((times, per)) = s
+    .split_once('/')
+    .or_else(|| s.split_once(" per "))
+    .or_else(|| s.split_once(" a "))
+  else {
+    return s
+      .parse()
+      .map_err(|e: humantime::DurationError| e.to_string());
+  };
+  let times = times.trim();
+  let times: u32 = times
+    .strip_suffix('x')
+    .or_else(|| times.strip_suffix(" times"))
+    .unwrap_or(times)
+    .trim()
+    .parse()
+    .map_err(|_| format!("Invalid frequency {s}, expected something like 3/week"))?;
+  if times == 0 {
+    return Err("Frequency must be positive".into());
+  }
+  let per = per.trim();
+  let per: Duration = if per.starts_with(|c: char| c.is_ascii_digit()) {
+    per.parse::<HumanDuration>()
+  } else {
+    format!("1{per}").parse()
+  }
+  .map_err(|e| e.to_string())?
+  .into();
+  let minutes = (per.as_secs_f64() / 60.0 / f64::from(times)).round();
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  Ok(Duration::from_mins(minutes as u64).into())
+}
+
+fn parse_month(s: &str) -> Result<Date, String> {
+  parse_day(&format!("{s}-01"))
+}
+
+fn parse_time_of_day(s: &str) -> Result<Time, String> {
+  Time::parse(s, &format_description!("[hour]:[minute]")).map_err(|e| e.to_string())
 }
 
 impl Cmd {
   pub const fn readonly(&self) -> bool {
-    matches!(self, Self::List { .. } | Self::Done { .. })
+    matches!(
+      self,
+      Self::List { .. }
+        | Self::Done { .. }
+        | Self::Info { .. }
+        | Self::Open { .. }
+        | Self::Digest
+        | Self::Schedule { .. }
+        | Self::Calendar { .. }
+        | Self::Forecast { .. }
+        | Self::Export { .. }
+        | Self::ReplaceTitles { dry_run: true, .. }
+        | Self::Recompute { dry_run: true }
+        | Self::Renumber
+        | Self::Convert
+        | Self::Prune { .. }
+        | Self::Check { fix: false }
+        | Self::Version { .. }
+    )
+  }
+
+  /// The name of the hook to run after this command and the task it is run for
+  fn hook(&self) -> Option<(&'static str, &TaskRef)> {
+    match self {
+      Self::Do { ids, .. } => Some(("do", ids.first()?)),
+      Self::Later { ids } => Some(("later", ids.first()?)),
+      Self::Pause { ids } => Some(("pause", ids.first()?)),
+      _ => None,
+    }
+  }
+
+  /// Splits a command given for several tasks into one command per task
+  fn per_task(&self) -> Option<Vec<(&TaskRef, Self)>> {
+    let ids = match self {
+      Self::Do { ids, .. } | Self::Later { ids } | Self::Pause { ids } if ids.len() > 1 => ids,
+      _ => return None,
+    };
+    let cmds = ids.iter().map(|id| {
+      let mut cmd = self.clone();
+      if let Self::Do { ids, .. } | Self::Later { ids } | Self::Pause { ids } = &mut cmd {
+        *ids = vec![id.clone()];
+      }
+      (id, cmd)
+    });
+    Some(cmds.collect())
   }
 }
 
-pub fn cli<S: Store>(store: S) -> Result<(), Box<dyn Error>> {
+pub fn cli<S: Store + Serialize + DeserializeOwned>(store: S) -> Result<(), Box<dyn Error>> {
   let opts = Opts::parse();
-  match opts.file.as_ref() {
-    "-" => handle_command(
-      opts.cmd,
-      try_new_engine(
-        store,
-        ReadWriteLogger::<_, Stdout, _>::new(stdin(), &mut stdout()),
-      )?,
-    ),
+  let config = Config::load()?;
+  let notify = config.notify_overdue && opts.cmd.is_none() && atty::is(atty::Stream::Stdout);
+  let mut cmd = config.apply(opts.cmd.unwrap_or_else(|| {
+    Cmd::List(ListArgs {
+      all: atty::isnt(atty::Stream::Stdout),
+      ..ListArgs::default()
+    })
+  }))?;
+  let to_terminal = opts.output.is_none() && atty::is(atty::Stream::Stdout);
+  apply_list_opts(&mut cmd, opts.as_of, opts.porcelain, to_terminal)?;
+  let file_name = opts
+    .file
+    .as_deref()
+    .or(config.file.as_deref())
+    .unwrap_or("-");
+  let calendar = Calendar {
+    timezone: config.timezone(),
+    holidays: opts
+      .holidays
+      .as_deref()
+      .or(config.holidays.as_deref())
+      .map(read_holidays)
+      .transpose()?
+      .unwrap_or_default(),
+    min_later: config.min_later().map(TryInto::try_into).transpose()?,
+    max_later: config.max_later().map(TryInto::try_into).transpose()?,
+    max_deferral: config.max_deferral().map(TryInto::try_into).transpose()?,
+  };
+  if let Cmd::Version { json } = cmd {
+    return opts.output.map_or_else(
+      || write_version(&mut stdout(), json),
+      |output_file| write_atomically(output_file, |file| write_version(file, json)),
+    );
+  }
+  if matches!(cmd, Cmd::Renumber | Cmd::Convert | Cmd::Prune { .. }) {
+    let source: Box<dyn Read> = match file_name {
+      "-" => Box::new(stdin()),
+      file_name => Box::new(File::open(file_name)?),
+    };
+    let write = |target: &mut dyn Write| match cmd {
+      Cmd::Convert => convert_log(source, target, opts.log_format),
+      Cmd::Prune { keep } => prune(store, source, target, OffsetDateTime::now_utc() - *keep),
+      _ => renumber(store, source, target),
+    };
+    return match opts.output {
+      Some(output_file) => write_atomically(output_file, |mut file| write(&mut file)),
+      None => write(&mut stdout()),
+    };
+  }
+  match file_name {
+    "-" if opts.snapshot.is_some() => Err("--snapshot only works with --file".into()),
+    "-" => Run {
+      cmd: &cmd,
+      config: &config,
+      output_file: opts.output.as_deref(),
+      notify: false,
+      show_progress: false,
+      write_snapshot: None,
+    }
+    .with(try_new_with_calendar(
+      store,
+      ReadWriteLogger::<_, Stdout, _>::new(stdin(), &mut stdout()).with_format(opts.log_format),
+      calendar,
+    )),
     file_name => {
       let file = OpenOptions::new().read(true).append(true).open(file_name)?;
-      let engine = try_new_engine(store, ReadWriteLogger::new(&file, &file))?;
-      handle_command(opts.cmd, engine)
+      lock_with_retry(&file, !cmd.readonly(), config.lock_timeout())?;
+      let show_progress = atty::is(atty::Stream::Stdout);
+      let new_logger = || file_logger(&file, opts.log_format, show_progress);
+      let snapshot = opts.snapshot.as_deref().map(open_snapshot).transpose()?;
+      // Readonly commands don't add to the log, so only write a snapshot if there is none yet
+      let write_snapshot = opts
+        .snapshot
+        .as_deref()
+        .filter(|_| !cmd.readonly() || matches!(snapshot, Some(None)));
+      let run = Run {
+        cmd: &cmd,
+        config: &config,
+        output_file: opts.output.as_deref(),
+        notify,
+        show_progress,
+        write_snapshot,
+      };
+      if let Some(snapshot) = snapshot.flatten() {
+        let logger = new_logger()?;
+        if let Some(engine) = try_from_snapshot::<S>(snapshot, logger, calendar.clone()).transpose()
+        {
+          return run.with(engine);
+        }
+        // The log changed other than by appending to it, so replay all of it and start over
+        (&file).rewind()?;
+        return Run {
+          write_snapshot: opts.snapshot.as_deref(),
+          ..run
+        }
+        .with(try_new_with_calendar(store, new_logger()?, calendar));
+      }
+      let logger = new_logger()?;
+      run.with(try_new_with_calendar(store, logger, calendar))
     }
   }
 }
-fn handle_command<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>>(
-  command: Option<Cmd>,
-  app: B,
+
+/// Applies the global options that are about listing todos
+fn apply_list_opts(
+  cmd: &mut Cmd,
+  as_of: Option<Date>,
+  porcelain: bool,
+  to_terminal: bool,
 ) -> Result<(), Box<dyn Error>> {
-  let cmd = command.unwrap_or_else(|| Cmd::List {
-    all: atty::isnt(atty::Stream::Stdout),
-  });
-  if cmd.readonly() {
-    handle_command_impl(&cmd, app, &mut stdout())
-  } else {
-    handle_command_impl(&cmd, app, &mut stderr())
+  if porcelain {
+    match cmd {
+      Cmd::Done { json: true, .. } => return Err("--porcelain doesn't work with --json".into()),
+      Cmd::Done { porcelain, .. } => *porcelain = true,
+      Cmd::List(args) if args.ndjson || args.after.is_some() || args.limit.is_some() => {
+        return Err("--porcelain doesn't work with --ndjson, --after or --limit".into());
+      }
+      Cmd::List(args) => args.porcelain = true,
+      _ => return Err("--porcelain only works with the list and done commands".into()),
+    }
+  }
+  let Cmd::List(args) = cmd else {
+    return match as_of {
+      Some(_) => Err("--as-of only works with the list command".into()),
+      None => Ok(()),
+    };
+  };
+  if let Some(as_of) = as_of {
+    args.as_of = Some(
+      as_of
+        .next_day()
+        .ok_or("Invalid date")?
+        .midnight()
+        .assume_utc(),
+    );
   }
+  args.hint_empty = to_terminal;
+  Ok(())
 }
 
-fn handle_command_impl<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  command: &Cmd,
-  app: B,
-  output: &mut W,
-) -> Result<(), Box<dyn Error>> {
-  match command {
-    Cmd::Add { description, every } => create_task(app, output, description, every),
-    Cmd::Clone { id, description } => clone_task(app, output, id, description),
-    Cmd::Do { id } => do_task(app, output, id),
-    Cmd::Done { id } => list_done_todos(app, output, id),
-    Cmd::Later { id } => task_later(app, output, id),
-    Cmd::List { all } => list_todos(app, output, *all),
-    Cmd::Pause { id } => pause_task(app, output, id),
-    Cmd::Unpause { id } => unpause_task(app, output, id),
+/// Reads the log from `file` and appends new records to it
+fn file_logger(
+  file: &File,
+  format: LogFormat,
+  show_progress: bool,
+) -> Result<ReadWriteLogger<&File, &File, &File>, Box<dyn Error>> {
+  let logger = ReadWriteLogger::new(file, file).with_format(format);
+  if !show_progress {
+    return Ok(logger);
   }
+  let total = file.metadata()?.len().max(1);
+  Ok(
+    logger.with_progress(REPLAY_PROGRESS_EVERY, move |lines, bytes| {
+      eprint!("\rreplaying {lines} lines ({}%)", bytes * 100 / total);
+    }),
+  )
 }
 
-fn list_todos<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
-  app: B,
-  output: &mut W,
-  all: bool,
-) -> Result<(), Box<dyn Error>> {
-  let (todos, paused_tasks, and_more) = get_todos(app.borrow().get_store(), all);
-  let Some(max_id_len) = todos
-    .iter()
-    .map(|(todo, _)| todo.task.to_string().len())
-    .chain(paused_tasks.iter().map(|task| task.id.to_string().len()))
-    .max()
-  else {
-    return Ok(());
-  };
-  for (todo, task) in &todos {
-    write_todo(output, max_id_len, task, &todo.due)?;
-  }
-  if and_more {
-    writeln!(output, "(and more)")?;
+/// Opens the snapshot file, if it was written before
+fn open_snapshot(path: &str) -> Result<Option<BufReader<File>>, Box<dyn Error>> {
+  match File::open(path) {
+    Ok(file) => Ok(Some(BufReader::new(file))),
+    Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e.into()),
   }
+}
 
-  if !paused_tasks.is_empty() {
-    if !todos.is_empty() {
-      writeln!(output)?;
+/// What to do with the engine once it is loaded from a log file
+struct Run<'a> {
+  cmd: &'a Cmd,
+  config: &'a Config,
+  output_file: Option<&'a str>,
+  notify: bool,
+  show_progress: bool,
+  /// Where to write a snapshot after handling the command
+  write_snapshot: Option<&'a str>,
+}
+
+impl Run<'_> {
+  fn with<S: Store + Serialize, A: Allesatt<Store = S>>(
+    self,
+    engine: Result<A, Box<dyn Error>>,
+  ) -> Result<(), Box<dyn Error>> {
+    if self.show_progress {
+      eprint!("\r\x1b[K");
     }
-    writeln!(output, "Paused tasks:")?;
-    for task in paused_tasks {
-      write_paused_task(output, max_id_len, task)?;
+    let mut engine = engine?;
+    if self.notify {
+      list_and_notify(self.cmd, self.config, self.output_file, &mut engine)?;
+    } else {
+      handle_command::<S, A, _>(self.cmd, self.config, self.output_file, &mut engine)?;
+    }
+    engine.flush_log()?;
+    if let Some(path) = self.write_snapshot {
+      write_atomically(path, |file| engine.write_snapshot(file))?;
     }
+    Ok(())
   }
-  Ok(())
 }
 
-fn list_done_todos<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
-  app: B,
-  output: &mut W,
-  id: &Option<TaskId>,
-) -> Result<(), Box<dyn Error>> {
-  let store = app.borrow().get_store();
-  let mut todos: Vec<_> = store
-    .get_todos(id.as_ref(), Some(true))
+/// Counts the open todos that were due before `today`, in the timezone of `calendar`
+#[cfg_attr(not(feature = "notifications"), allow(dead_code))]
+fn count_overdue<S: Store>(store: &S, calendar: &Calendar, today: Date) -> usize {
+  store
+    .open_todos()
     .into_iter()
-    .map(|todo| {
-      let task = store.get_task(&todo.task).unwrap();
-      (task, todo.completed.as_ref().unwrap().date)
-    })
-    .collect();
-  if let Some(max_id_len) = todos
-    .iter()
-    .map(|(task, _)| task.id.to_string().len())
-    .max()
-  {
-    todos.sort_unstable_by(|(_, completed1), (_, completed2)| completed1.cmp(completed2));
-    for (task, completed) in todos {
-      write_todo(output, max_id_len, task, &completed)?;
+    .filter(|(todo, _)| DueBucket::of(calendar.local_date(todo.due), today) == DueBucket::Overdue)
+    .count()
+}
+
+/// Runs the default list command, then shows a notification about overdue todos
+fn list_and_notify<S: Store, A: Allesatt<Store = S>>(
+  cmd: &Cmd,
+  config: &Config,
+  output_file: Option<&str>,
+  app: &mut A,
+) -> Result<(), Box<dyn Error>> {
+  handle_command::<S, A, _>(cmd, config, output_file, &mut *app)?;
+  notify_overdue(app.get_store(), app.calendar());
+  Ok(())
+}
+
+#[cfg(feature = "notifications")]
+fn notify_overdue<S: Store>(store: &S, calendar: &Calendar) {
+  let today = calendar.local_date(OffsetDateTime::now_utc());
+  let count = count_overdue(store, calendar, today);
+  if count == 0 {
+    return;
+  }
+  let result = notify_rust::Notification::new()
+    .summary("Allesatt")
+    .body(&format!("{count} todos overdue"))
+    .show();
+  if let Err(e) = result {
+    eprintln!("Warning: showing notification failed: {e}");
+  }
+}
+
+#[cfg(not(feature = "notifications"))]
+fn notify_overdue<S: Store>(_store: &S, _calendar: &Calendar) {
+  eprintln!("Warning: notify-overdue needs allesatt built with the notifications feature");
+}
+
+/// Locks the log file, retrying with exponential backoff while another process holds a lock
+fn lock_with_retry(file: &File, exclusive: bool, timeout: Duration) -> Result<(), Box<dyn Error>> {
+  let start = Instant::now();
+  let mut wait = Duration::from_millis(10);
+  loop {
+    let result = if exclusive {
+      file.try_lock()
+    } else {
+      file.try_lock_shared()
+    };
+    match result {
+      Ok(()) => return Ok(()),
+      Err(TryLockError::WouldBlock) => (),
+      Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    let remaining = timeout.saturating_sub(start.elapsed());
+    if remaining.is_zero() {
+      let timeout = humantime::format_duration(timeout);
+      return Err(format!("Log file is locked by another process, gave up after {timeout}").into());
     }
+    sleep(wait.min(remaining));
+    wait *= 2;
   }
-  Ok(())
 }
 
-fn create_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+fn handle_command<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>>(
+  cmd: &Cmd,
+  config: &Config,
+  output_file: Option<&str>,
   mut app: B,
-  output: &mut W,
-  description: &str,
-  due_every: &HumanDuration,
 ) -> Result<(), Box<dyn Error>> {
-  let (task_id, todo_id) = app
-    .borrow_mut()
-    .create_task(description.into(), Some(**due_every));
-  print_todo(app.borrow().get_store(), output, &task_id, &todo_id)
+  if let Some(cmds) = cmd.per_task() {
+    return for_each_task(&cmds, |cmd| {
+      handle_command::<S, A, _>(cmd, config, output_file, app.borrow_mut())
+    });
+  }
+  if !cmd.readonly() {
+    // A skipped completion doesn't run the hook or write to the journal either
+    if let Cmd::Do {
+      ids,
+      once_per: Some(once_per),
+      ..
+    } = cmd
+    {
+      let store = app.borrow().get_store();
+      let id = only_task(ids).resolve(store)?;
+      if skip_once_per(store, &id, (*once_per).into(), &mut stderr())? {
+        return Ok(());
+      }
+    }
+    if let Cmd::Do {
+      ids, yes: false, ..
+    } = cmd
+    {
+      let id = only_task(ids);
+      if atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stdout) {
+        let id = id.resolve(app.borrow().get_store())?;
+        if let Some(question) = early_completion_question(app.borrow().get_store(), &id)? {
+          eprint!("{question} [y/N] ");
+          let mut answer = String::new();
+          stdin().read_line(&mut answer)?;
+          if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            eprintln!("Not completed");
+            return Ok(());
+          }
+        }
+      }
+    }
+    if let Cmd::Add(args) = cmd {
+      check_duplicate_title(
+        app.borrow().get_store(),
+        args,
+        atty::is(atty::Stream::Stdout),
+      )?;
+    }
+    handle_command_impl::<S, A, _, _>(cmd, app.borrow_mut(), &mut stderr())?;
+    if let Some((template, id)) = cmd
+      .hook()
+      .and_then(|(name, id)| Some((config.hook(name)?, id)))
+    {
+      let store = app.borrow().get_store();
+      let result = id.resolve(store).and_then(|id| {
+        let title = store.get_task(&id).map_or("", |task| &task.title);
+        run_hook(template, &id, title)
+      });
+      if let Err(e) = result {
+        eprintln!("Warning: hook failed: {e}");
+      }
+    }
+    if let (
+      Cmd::Do {
+        ids, note: Some(_), ..
+      },
+      Some(journal),
+    ) = (cmd, &config.journal)
+    {
+      let store = app.borrow().get_store();
+      let result = only_task(ids)
+        .resolve(store)
+        .and_then(|id| append_to_journal(journal, store, &id));
+      if let Err(e) = result {
+        eprintln!("Warning: writing to journal failed: {e}");
+      }
+    }
+    Ok(())
+  } else if let Some(output_file) = output_file {
+    if matches!(cmd, Cmd::Open { .. }) {
+      // Replacing the file with no output would only empty it
+      return Err("open writes no output, so it doesn't work with --output".into());
+    }
+    write_atomically(output_file, |file| handle_command_impl(cmd, app, file))
+  } else {
+    handle_command_impl(cmd, app, &mut stdout())
+  }
 }
 
-fn clone_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  mut app: B,
-  output: &mut W,
+/// Asks whether to complete a task that isn't due for more than a day
+fn early_completion_question<S: Store>(
+  store: &S,
   id: &TaskId,
-  description: &str,
-) -> Result<(), Box<dyn Error>> {
-  let (task_id, todo_id) = app.borrow_mut().clone_task(id, description.into())?;
-  print_todo(app.borrow().get_store(), output, &task_id, &todo_id)
+) -> Result<Option<String>, Box<dyn Error>> {
+  // Paused tasks are handled by do_task
+  let Some(todo) = store.find_open_todo(id) else {
+    return Ok(None);
+  };
+  if todo.due <= OffsetDateTime::now_utc() + time::Duration::DAY {
+    return Ok(None);
+  }
+  let title = &store.get_task(id).ok_or("Task not found")?.title;
+  let due = todo.due.format(&DAY_FORMAT)?;
+  Ok(Some(format!(
+    "{title} isn't due until {due}; complete anyway?"
+  )))
 }
 
-fn print_todo<S: Store, W: Write>(
+/// Warns about adding a task with the title of an existing one, refusing to if `interactive`
+fn check_duplicate_title<S: Store>(
   store: &S,
-  output: &mut W,
-  task_id: &TaskId,
-  todo_id: &TodoId,
+  args: &AddArgs,
+  interactive: bool,
 ) -> Result<(), Box<dyn Error>> {
-  let task = store.get_task(task_id).unwrap();
-  let todo = store.get_todo(todo_id).unwrap();
-  write_todo(output, 0, task, &todo.due)
+  if args.allow_duplicate {
+    return Ok(());
+  }
+  let Some(task) = store.find_task_by_title(&args.description) else {
+    return Ok(());
+  };
+  if interactive {
+    return Err(
+      format!(
+        "Task {} is already called {}, use --allow-duplicate to add it anyway",
+        task.id, task.title
+      )
+      .into(),
+    );
+  }
+  eprintln!("Warning: task {} is already called {}", task.id, task.title);
+  Ok(())
 }
 
-fn write_todo(
-  output: &mut impl Write,
-  width: usize,
-  Task { id, title }: &Task,
-  date: &TodoDate,
+/// Quotes a value for use as a single word in `sh`
+fn shell_quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Replaces `{id}` and `{title}` in a hook template with the quoted values
+#[allow(clippy::literal_string_with_formatting_args)]
+fn hook_command(template: &str, id: &TaskId, title: &str) -> String {
+  template
+    .replace("{id}", &shell_quote(&id.to_string()))
+    .replace("{title}", &shell_quote(title))
+}
+
+fn run_hook(template: &str, id: &TaskId, title: &str) -> Result<(), Box<dyn Error>> {
+  let status = Command::new("sh")
+    .arg("-c")
+    .arg(hook_command(template, id, title))
+    .status()?;
+  if !status.success() {
+    return Err(status.to_string().into());
+  }
+  Ok(())
+}
+
+/// Appends the last completion of a task and its note to the journal file at `path`
+fn append_to_journal<S: Store>(path: &str, store: &S, id: &TaskId) -> Result<(), Box<dyn Error>> {
+  let title = &store.get_task(id).ok_or("Task not found")?.title;
+  let completed = store
+    .iter_todos(Some(id), Some(true))
+    .filter_map(|todo| todo.completed.as_ref())
+    .max_by_key(|completed| completed.date)
+    .ok_or("Task wasn't done")?;
+  let date = completed.date.format(&DAY_TIME_FORMAT)?;
+  let note = completed.note.as_deref().unwrap_or_default();
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  writeln!(file, "{date} {title}: {note}")?;
+  Ok(())
+}
+
+/// Returns the task of a command given for several tasks after splitting it with `Cmd::per_task`
+fn only_task(ids: &[TaskRef]) -> &TaskRef {
+  match ids {
+    [id] => id,
+    _ => unreachable!("Commands for several tasks are split by per_task"),
+  }
+}
+
+/// Runs a command split by `Cmd::per_task` for each task, not stopping at failures
+fn for_each_task(
+  cmds: &[(&TaskRef, Cmd)],
+  mut run: impl FnMut(&Cmd) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+  let total = cmds.len();
+  let failures: Vec<_> = cmds
+    .iter()
+    .filter_map(|(id, cmd)| Some(format!("{id}: {}", run(cmd).err()?)))
+    .collect();
+  if failures.is_empty() {
+    return Ok(());
+  }
+  Err(
+    format!(
+      "{} of {total} tasks failed: {}",
+      failures.len(),
+      failures.join("; ")
+    )
+    .into(),
+  )
+}
+
+fn handle_command_impl<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  command: &Cmd,
+  mut app: B,
+  output: &mut W,
+) -> Result<(), Box<dyn Error>> {
+  if let Some(cmds) = command.per_task() {
+    return for_each_task(&cmds, |cmd| {
+      handle_command_impl::<S, A, _, _>(cmd, app.borrow_mut(), output)
+    });
+  }
+  match command {
+    Cmd::Add(args) => create_task(app, output, args),
+    Cmd::AddBatch { file } => add_batch(app, output, BufReader::new(File::open(file)?)),
+    Cmd::Alias { id, name } => add_task_alias(app, output, id, name),
+    Cmd::Clone { id, description } => clone_task(app, output, id, description),
+    Cmd::DeferAll { by, only_overdue } => defer_all(app, output, by, *only_overdue),
+    Cmd::Digest => write_digest(app, output),
+    Cmd::Do {
+      ids,
+      snap_to,
+      repeat,
+      note,
+      unpause,
+      once_per,
+      ..
+    } => do_task(
+      app,
+      output,
+      only_task(ids),
+      *snap_to,
+      *repeat,
+      note.as_deref(),
+      *unpause,
+      once_per.map(Into::into),
+    ),
+    Cmd::Done {
+      id,
+      json,
+      since,
+      until,
+      porcelain,
+    } => {
+      let id = id
+        .as_ref()
+        .map(|id| id.resolve(app.borrow().get_store()))
+        .transpose()?;
+      let from = since.map(|since| since.midnight().assume_utc());
+      let to = until.map(|until| {
+        until
+          .with_hms_nano(23, 59, 59, 999_999_999)
+          .unwrap()
+          .assume_utc()
+      });
+      let format = DoneFormat::new(*json, *porcelain);
+      list_done_todos(app, output, id.as_ref(), format, (from, to))
+    }
+    Cmd::Info { id, exact } => write_task_info(app, output, id, *exact),
+    Cmd::Open { id } => open_task(app, id),
+    Cmd::Schedule { until, format } => write_schedule(app, output, *until, *format),
+    Cmd::Forecast { days, verbose } => write_forecast(app, output, *days, *verbose),
+    Cmd::Calendar { month } => {
+      let first = month.unwrap_or_else(|| OffsetDateTime::now_utc().date().replace_day(1).unwrap());
+      write_month(app.borrow().get_store(), output, first, terminal_width())
+    }
+    Cmd::Export {
+      format: ExportFormat::Markdown,
+      all,
+    } => write_markdown(app.borrow().get_store(), output, *all),
+    Cmd::Export {
+      format: ExportFormat::Opml,
+      all,
+    } => write_opml(app.borrow().get_store(), output, *all),
+    Cmd::Rename { id, description } => rename_task(app, output, id, description),
+    Cmd::Swap { a, b } => swap_tasks(app, output, a, b),
+    Cmd::ReplaceTitles {
+      pattern,
+      replacement,
+      regex,
+      dry_run,
+    } => replace_titles(app, output, pattern, replacement, *regex, *dry_run),
+    Cmd::Effort { id, effort } => set_task_effort(app, output, id, effort),
+    Cmd::Lead { id, lead } => set_task_lead_time(app, output, id, lead),
+    Cmd::Later { ids } => task_later(app, output, only_task(ids)),
+    Cmd::Unlater { id } => task_unlater(app, output, id),
+    Cmd::Touch { id } => touch_task(app, output, id),
+    Cmd::Progress { id, done, total } => set_progress(app, output, id, *done, *total),
+    Cmd::Recompute { dry_run } => recompute_due(app, output, *dry_run),
+    Cmd::Amend { id, old, new } => amend_completion(app, output, id, *old, *new),
+    Cmd::Reattach { id, day, to } => reattach_completion(app, output, id, *day, to),
+    Cmd::Learn { id } => learn_task(app, output, id, true),
+    Cmd::List(args) => list_todos(app, output, args),
+    Cmd::Pause { ids } => pause_task(app, output, only_task(ids)),
+    Cmd::Unpause { id, at } => unpause_task(app, output, id, *at),
+    Cmd::Block { id, reason } => block_task(app, output, id, Some(reason)),
+    Cmd::Unblock { id } => block_task(app, output, id, None),
+    Cmd::After { id, anchor } => task_after(app, output, id, anchor.as_ref()),
+    Cmd::FromDue { id, off } => task_from_due(app, output, id, !off),
+    Cmd::Unlearn { id } => learn_task(app, output, id, false),
+    Cmd::Renumber => Err("Renumbering needs the log before replaying it".into()),
+    Cmd::Convert => Err("Converting needs the log before replaying it".into()),
+    Cmd::Prune { .. } => Err("Pruning needs the log before replaying it".into()),
+    Cmd::Version { json } => write_version(output, *json),
+    Cmd::Check { fix } => check_log(app, output, *fix),
+  }
+}
+
+fn list_todos<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+  args: &ListArgs,
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  let with_time = |task: &Task| shows_time(app.borrow(), args, task);
+  if args.after.is_some() || args.limit.is_some() {
+    return list_tasks_page(store, output, args.after.as_ref(), args.limit, with_time);
+  }
+  let limits = args.limits(store.get_tasks().len());
+  let soon = args.soon.map_or(DEFAULT_SOON, Into::into);
+  let (mut todos, mut paused_tasks, mut and_more) = get_todos(
+    store,
+    args.all || args.upcoming_only,
+    args.budget.map(Into::into),
+    &limits,
+    soon,
+    args.as_of,
+  );
+  let mut blocked_tasks = get_blocked_tasks(store);
+  let now = args.as_of.unwrap_or_else(OffsetDateTime::now_utc);
+  let done_today = if args.done_today {
+    add_done_today(store, &mut todos, now)
+  } else {
+    HashSet::new()
+  };
+  if args.by_urgency {
+    let max_due = (!args.all).then(|| args.max_due.unwrap_or_else(|| Limits::default().due));
+    and_more |= sort_by_urgency(app.borrow(), &mut todos, now, soon, max_due);
+  }
+  if args.due_only || args.upcoming_only {
+    todos.retain(|(todo, task)| is_due(todo, task, now, soon) == args.due_only);
+    blocked_tasks.clear();
+    paused_tasks.clear();
+  }
+  if args.upcoming_only && !args.all && todos.len() > limits.not_due {
+    todos.truncate(limits.not_due);
+    and_more = true;
+  }
+  if args.ndjson {
+    return write_ndjson(store, output, &todos, &blocked_tasks, &paused_tasks);
+  }
+  if args.porcelain {
+    return write_porcelain(store, output, &todos, &blocked_tasks, &paused_tasks, now);
+  }
+  let Some(max_id_len) = todos
+    .iter()
+    .map(|(_, task)| task)
+    .chain(blocked_tasks.iter().chain(&paused_tasks))
+    .map(|task| task.id.to_string().len())
+    .max()
+  else {
+    if args.hint_empty && store.get_tasks().is_empty() {
+      writeln!(output, "{EMPTY_HINT}")?;
+    }
+    return Ok(());
+  };
+  let calendar = app.borrow().calendar();
+  let today = calendar.local_date(now);
+  let mut last_bucket = None;
+  for (todo, task) in &todos {
+    let bucket = DueBucket::of(calendar.local_date(todo.due), today);
+    if args.grouped {
+      write_bucket_heading(output, &mut last_bucket, bucket)?;
+    }
+    if args.status_col {
+      write!(output, "{} ", bucket.glyph())?;
+    }
+    write_todo(
+      output,
+      max_id_len,
+      task,
+      &todo.due,
+      remaining_completions(store, task),
+      todo.progress,
+      with_time(task),
+      done_today.contains(&task.id),
+    )?;
+  }
+  if and_more {
+    writeln!(output, "(and more)")?;
+  }
+
+  let separate = write_section(
+    output,
+    !todos.is_empty(),
+    args.status_col.then_some(' '),
+    "Blocked tasks:",
+    &blocked_tasks,
+    |output, task| write_blocked_task(output, max_id_len, task),
+  )?;
+  write_section(
+    output,
+    separate,
+    args.status_col.then_some('P'),
+    "Paused tasks:",
+    &paused_tasks,
+    |output, task| write_paused_task(output, max_id_len, task),
+  )?;
+  Ok(())
+}
+
+/// Orders the due todos by how long ago they were due relative to the interval of their task,
+/// most overdue first, keeping at most `max_due` of them and returning whether any were left out
+///
+/// Todos that aren't due stay after the due ones in their order.
+fn sort_by_urgency(
+  app: &impl Allesatt,
+  todos: &mut Vec<(&Todo, &Task)>,
+  now: TodoDate,
+  soon: Duration,
+  max_due: Option<usize>,
+) -> bool {
+  let urgency = |(todo, task): &(&Todo, &Task)| {
+    let interval = app
+      .task_stats(&task.id)
+      .map_or(time::Duration::DAY, |stats| stats.interval);
+    (now - todo.due) / interval
+  };
+  let (mut due, not_due): (Vec<_>, Vec<_>) = todos
+    .drain(..)
+    .partition(|(todo, task)| is_due(todo, task, now, soon));
+  due.sort_by(|a, b| urgency(b).total_cmp(&urgency(a)));
+  let and_more = max_due.is_some_and(|max_due| due.len() > max_due);
+  due.truncate(max_due.unwrap_or(usize::MAX));
+  todos.extend(due.into_iter().chain(not_due));
+  and_more
+}
+
+/// Whether to list when the todo of `task` is due at, not only on which day
+fn shows_time(app: &impl Allesatt, args: &ListArgs, task: &Task) -> bool {
+  args.with_time
+    || app
+      .task_stats(&task.id)
+      .is_some_and(|stats| stats.interval < Duration::from_hours(24))
+}
+
+/// Writes the heading of `bucket` unless it is the same as the last one
+fn write_bucket_heading(
+  output: &mut impl Write,
+  last_bucket: &mut Option<DueBucket>,
+  bucket: DueBucket,
+) -> io::Result<()> {
+  if *last_bucket != Some(bucket) {
+    if last_bucket.is_some() {
+      writeln!(output)?;
+    }
+    writeln!(output, "{}:", bucket.heading())?;
+    *last_bucket = Some(bucket);
+  }
+  Ok(())
+}
+
+/// Adds the open todos of tasks done on the day of `now` to `todos`, returning those tasks
+fn add_done_today<'a, S: Store>(
+  store: &'a S,
+  todos: &mut Vec<(&'a Todo, &'a Task)>,
+  now: OffsetDateTime,
+) -> HashSet<TaskId> {
+  let today = now.replace_time(Time::MIDNIGHT);
+  let done: HashSet<_> = store
+    .iter_completed_todos(None, Some(today), Some(now))
+    .map(|todo| todo.task.clone())
+    .collect();
+  for task_id in &done {
+    let (Some(todo), Some(task)) = (store.find_open_todo(task_id), store.get_task(task_id)) else {
+      continue;
+    };
+    if task.blocked.is_none() && !todos.iter().any(|(_, listed)| listed.id == *task_id) {
+      let pos =
+        todos.partition_point(|(other, listed)| (other.due, &listed.id) < (todo.due, task_id));
+      todos.insert(pos, (todo, task));
+    }
+  }
+  done
+}
+
+/// Returns the blocked tasks that have an open todo, ordered by task id
+fn get_blocked_tasks<S: Store>(store: &S) -> Vec<&Task> {
+  store
+    .open_todos()
+    .into_iter()
+    .map(|(_, task)| task)
+    .filter(|task| task.blocked.is_some())
+    .collect()
+}
+
+/// Writes a heading and the tasks below it, if there are any, returning whether anything was
+/// written so far
+///
+/// Each task is prefixed with `status` for the status column of `List`, if given.
+fn write_section<W: Write>(
+  output: &mut W,
+  separate: bool,
+  status: Option<char>,
+  heading: &str,
+  tasks: &[&Task],
+  write_task: impl Fn(&mut W, &Task) -> io::Result<()>,
+) -> io::Result<bool> {
+  if tasks.is_empty() {
+    return Ok(separate);
+  }
+  if separate {
+    writeln!(output)?;
+  }
+  writeln!(output, "{heading}")?;
+  for task in tasks {
+    if let Some(status) = status {
+      write!(output, "{status} ")?;
+    }
+    write_task(output, task)?;
+  }
+  Ok(true)
+}
+
+/// Writes one compact JSON object per line for each todo, blocked and paused task
+fn write_ndjson<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  todos: &[(&Todo, &Task)],
+  blocked_tasks: &[&Task],
+  paused_tasks: &[&Task],
+) -> Result<(), Box<dyn Error>> {
+  for (todo, task) in todos {
+    let line = serde_json::json!({
+      "task": task.id.to_string(),
+      "title": task.title,
+      "due": todo.due.format(&DAY_FORMAT)?,
+      "remaining": remaining_completions(store, task),
+    });
+    writeln!(output, "{line}")?;
+    output.flush()?;
+  }
+  for task in blocked_tasks {
+    let line = serde_json::json!({
+      "task": task.id.to_string(),
+      "title": task.title,
+      "blocked": task.blocked,
+    });
+    writeln!(output, "{line}")?;
+    output.flush()?;
+  }
+  for task in paused_tasks {
+    let line = serde_json::json!({
+      "task": task.id.to_string(),
+      "title": task.title,
+      "paused": true,
+    });
+    writeln!(output, "{line}")?;
+    output.flush()?;
+  }
+  Ok(())
+}
+
+/// Writes one `<task id> <due> <state> <title>` line per todo and task, see `Opts::porcelain`
+///
+/// This format is promised not to change, so scripts can rely on it. New information goes into
+/// the human output or `--ndjson` instead.
+fn write_porcelain<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  todos: &[(&Todo, &Task)],
+  blocked_tasks: &[&Task],
+  paused_tasks: &[&Task],
+  now: TodoDate,
+) -> Result<(), Box<dyn Error>> {
+  for (todo, task) in todos {
+    let state = if todo.due < now { "overdue" } else { "open" };
+    write_porcelain_line(output, task, Some(todo.due), state)?;
+  }
+  for task in blocked_tasks {
+    let due = store.find_open_todo(&task.id).map(|todo| todo.due);
+    write_porcelain_line(output, task, due, "blocked")?;
+  }
+  for task in paused_tasks {
+    write_porcelain_line(output, task, None, "paused")?;
+  }
+  Ok(())
+}
+
+fn write_porcelain_line(
+  output: &mut impl Write,
+  task: &Task,
+  due: Option<TodoDate>,
+  state: &str,
+) -> Result<(), Box<dyn Error>> {
+  let due = due.map_or_else(
+    || Ok(String::from("-")),
+    |due| {
+      due
+        .to_offset(UtcOffset::UTC)
+        .replace_nanosecond(0)?
+        .format(&Rfc3339)
+    },
+  )?;
+  writeln!(output, "{} {due} {state} {}", task.id, task.title)?;
+  Ok(())
+}
+
+fn write_schedule<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+  until: Date,
+  format: ScheduleFormat,
+) -> Result<(), Box<dyn Error>> {
+  let app = app.borrow();
+  let store = app.get_store();
+  let mut rows = vec![];
+  for task in store.get_tasks() {
+    let Some(todo) = store.find_open_todo(&task.id) else {
+      continue;
+    };
+    let mut remaining = remaining_completions(store, task).unwrap_or(u32::MAX);
+    let mut due = todo.due;
+    while due.date() <= until && remaining > 0 {
+      rows.push((due, task));
+      let next = app.guess_next_due(&task.id, due);
+      if next <= due {
+        break;
+      }
+      due = next;
+      remaining -= 1;
+    }
+  }
+  rows.sort_by_key(|(due, task)| (*due, &task.id));
+  let rows = rows
+    .into_iter()
+    .map(|(due, task)| Ok((task, due.format(&DAY_FORMAT)?)))
+    .collect::<Result<Vec<_>, time::error::Format>>()?;
+  match format {
+    ScheduleFormat::Json => {
+      let rows: Vec<_> = rows
+        .iter()
+        .map(|(task, due)| {
+          serde_json::json!({
+            "task": task.id.to_string(),
+            "title": task.title,
+            "due": due,
+          })
+        })
+        .collect();
+      serde_json::to_writer_pretty(&mut *output, &rows)?;
+      writeln!(output)?;
+    }
+    ScheduleFormat::Csv => {
+      writeln!(output, "task,title,due")?;
+      for (task, due) in rows {
+        writeln!(output, "{},{},{}", task.id, csv_field(&task.title), due)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Writes how many open todos are due in `days` days with their current due dates
+fn write_forecast<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+  days: u32,
+  verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  let until = OffsetDateTime::now_utc() + time::Duration::days(days.into());
+  let mut todos = store.open_todos();
+  todos.retain(|(todo, _)| todo.due <= until);
+  todos.sort_by_key(|(todo, task)| (todo.due, &task.id));
+  writeln!(output, "{} todos due in {days} days", todos.len())?;
+  if verbose {
+    let max_id_len = todos
+      .iter()
+      .map(|(_, task)| task.id.to_string().len())
+      .max()
+      .unwrap_or(0);
+    for (todo, task) in todos {
+      let remaining = remaining_completions(store, task);
+      write_todo(
+        output,
+        max_id_len,
+        task,
+        &todo.due,
+        remaining,
+        todo.progress,
+        false,
+        false,
+      )?;
+    }
+  }
+  Ok(())
+}
+
+fn check_log<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  fix: bool,
+) -> Result<(), Box<dyn Error>> {
+  if fix {
+    let Err(problems) = app.borrow().get_store().validate() else {
+      writeln!(output, "No problems found")?;
+      return Ok(());
+    };
+    for problem in problems {
+      let Inconsistency::SeveralOpenTodos(task_id, todo_ids) = problem else {
+        continue;
+      };
+      let store = app.borrow().get_store();
+      let keep = todo_ids
+        .iter()
+        .min_by_key(|todo_id| (store.get_todo(todo_id).map(|todo| todo.due), todo_id.get()))
+        .cloned();
+      for todo_id in todo_ids
+        .iter()
+        .filter(|&todo_id| Some(todo_id) != keep.as_ref())
+      {
+        app.borrow_mut().discard_todo(todo_id)?;
+        writeln!(output, "Discarded todo {todo_id} of task {task_id}")?;
+      }
+    }
+  }
+  let Err(problems) = app.borrow().get_store().validate() else {
+    writeln!(output, "No problems found")?;
+    return Ok(());
+  };
+  for problem in &problems {
+    writeln!(output, "{problem}")?;
+  }
+  Err(format!("Found {} problems", problems.len()).into())
+}
+
+fn write_version<W: Write>(output: &mut W, json: bool) -> Result<(), Box<dyn Error>> {
+  let version = env!("CARGO_PKG_VERSION");
+  if json {
+    let info = serde_json::json!({
+      "version": version,
+      "log_commands": LOG_COMMANDS,
+    });
+    serde_json::to_writer_pretty(&mut *output, &info)?;
+    writeln!(output)?;
+  } else {
+    writeln!(output, "allesatt {version}")?;
+    writeln!(output, "log commands: {}", LOG_COMMANDS.join(", "))?;
+  }
+  Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_owned()
+  }
+}
+
+/// Lists tasks by id, so that paging with `after` is stable when tasks are added
+fn list_tasks_page<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  after: Option<&TaskId>,
+  limit: Option<usize>,
+  with_time: impl Fn(&Task) -> bool,
+) -> Result<(), Box<dyn Error>> {
+  let tasks: Vec<_> = store
+    .get_tasks()
+    .into_iter()
+    .filter(|task| after.is_none_or(|after| task.id > *after))
+    .take(limit.unwrap_or(usize::MAX))
+    .collect();
+  let max_id_len = tasks
+    .iter()
+    .map(|task| task.id.to_string().len())
+    .max()
+    .unwrap_or(0);
+  for task in tasks {
+    if let Some(todo) = store.find_open_todo(&task.id) {
+      let remaining = remaining_completions(store, task);
+      write_todo(
+        output,
+        max_id_len,
+        task,
+        &todo.due,
+        remaining,
+        todo.progress,
+        with_time(task),
+        false,
+      )?;
+    } else {
+      write_paused_task(output, max_id_len, task)?;
+    }
+  }
+  Ok(())
+}
+
+/// How `Cmd::Done` prints completed todos
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DoneFormat {
+  Human,
+  Json,
+  /// The stable format of `--porcelain`, with the date each todo was due at
+  Porcelain,
+}
+
+impl DoneFormat {
+  const fn new(json: bool, porcelain: bool) -> Self {
+    match (json, porcelain) {
+      (true, _) => Self::Json,
+      (false, true) => Self::Porcelain,
+      (false, false) => Self::Human,
+    }
+  }
+}
+
+fn list_done_todos<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+  id: Option<&TaskId>,
+  format: DoneFormat,
+  (from, to): (Option<TodoDate>, Option<TodoDate>),
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  if format == DoneFormat::Porcelain {
+    let mut todos: Vec<_> = store.iter_completed_todos(id, from, to).collect();
+    todos.sort_unstable_by_key(|todo| todo.completed.as_ref().map(|completed| completed.date));
+    for todo in todos {
+      let task = store.get_task(&todo.task).ok_or("Task not found")?;
+      write_porcelain_line(output, task, Some(todo.due), "done")?;
+    }
+    return Ok(());
+  }
+  if format == DoneFormat::Json {
+    let mut todos: Vec<_> = store.iter_completed_todos(id, from, to).collect();
+    todos.sort_unstable_by_key(|todo| todo.completed.as_ref().map(|completed| completed.date));
+    let rows = todos
+      .into_iter()
+      .map(|todo| {
+        let completed = todo.completed.as_ref().unwrap();
+        Ok(serde_json::json!({
+          "task": todo.task.to_string(),
+          "title": store.get_task(&todo.task).ok_or("Task not found")?.title,
+          "completed": completed.date.format(&Rfc3339)?,
+          "due": todo.due.format(&Rfc3339)?,
+          "note": completed.note,
+        }))
+      })
+      .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    serde_json::to_writer_pretty(&mut *output, &rows)?;
+    writeln!(output)?;
+    return Ok(());
+  }
+  let mut todos: Vec<_> = store
+    .iter_completed_todos(id, from, to)
+    .map(|todo| {
+      let task = store.get_task(&todo.task).unwrap();
+      (task, todo.completed.as_ref().unwrap())
+    })
+    .collect();
+  if let Some(max_id_len) = todos
+    .iter()
+    .map(|(task, _)| task.id.to_string().len())
+    .max()
+  {
+    todos.sort_unstable_by_key(|(_, completed)| completed.date);
+    for (task, completed) in todos {
+      write_todo(
+        output,
+        max_id_len,
+        task,
+        &completed.date,
+        None,
+        None,
+        false,
+        false,
+      )?;
+      if let Some(note) = &completed.note {
+        writeln!(output, "{:max_id_len$}   {note}", "")?;
+      }
+    }
+  }
+  if let Some(variation) = id
+    .and_then(|id| app.borrow().task_stats(id))
+    .and_then(|stats| stats.variation)
+  {
+    writeln!(
+      output,
+      "consistency: {} (variation {variation:.2})",
+      consistency(variation)
+    )?;
+  }
+  Ok(())
+}
+
+/// Describes how regularly a task is done given the variation of its intervals
+fn consistency(variation: f64) -> &'static str {
+  match variation {
+    v if v < 0.25 => "high",
+    v if v < 0.5 => "medium",
+    _ => "low",
+  }
+}
+
+fn write_task_info<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+  id: &TaskRef,
+  exact: bool,
 ) -> Result<(), Box<dyn Error>> {
-  let date = date.format(&DAY_FORMAT)?;
-  writeln!(output, "{id:width$} {date} {title}")?;
+  let app = app.borrow();
+  let store = app.get_store();
+  let id = &id.resolve(store)?;
+  let task = store.get_task(id).ok_or("Task not found")?;
+  let now = OffsetDateTime::now_utc();
+  writeln!(output, "{} {}", task.id, task.title)?;
+  if !task.aliases.is_empty() {
+    let aliases: Vec<_> = task
+      .aliases
+      .iter()
+      .map(|alias| format!("@{alias}"))
+      .collect();
+    writeln!(output, "aliases:     {}", aliases.join(", "))?;
+  }
+  if let Some(url) = &task.url {
+    writeln!(output, "link:        {url}")?;
+  }
+  let age = (now.date() - task.created.date()).whole_days();
+  let created = task.created.format(&DAY_FORMAT)?;
+  writeln!(output, "age:         {age} days (since {created})")?;
+  if let Some(todo) = store.find_open_todo(id) {
+    let due = todo.due.format(&DAY_FORMAT)?;
+    writeln!(
+      output,
+      "due:         {due} ({})",
+      humanize_date(&todo.due, &now)
+    )?;
+  } else {
+    writeln!(output, "due:         paused")?;
+  }
+  let stats = app.task_stats(id);
+  if let Some(stats) = &stats {
+    // Averaged intervals aren't worth showing to the second
+    let minutes = u64::try_from(stats.interval.whole_minutes()).unwrap_or(0);
+    let interval = humantime::format_duration(Duration::from_secs(minutes * 60));
+    match stats.interval_kind {
+      IntervalKind::Fixed => writeln!(output, "interval:    {interval} (fixed)")?,
+      IntervalKind::Learned => writeln!(output, "interval:    {interval} (no longer adapted)")?,
+      IntervalKind::Calculated(samples) if !exact => writeln!(
+        output,
+        "interval:    {} (average of the last {samples} intervals)",
+        round_interval(stats.interval.unsigned_abs())
+      )?,
+      IntervalKind::Calculated(samples) => writeln!(
+        output,
+        "interval:    {interval} (average of the last {samples} intervals)"
+      )?,
+      IntervalKind::Yearly => writeln!(output, "interval:    yearly")?,
+      IntervalKind::Default => writeln!(output, "interval:    {interval} (default)")?,
+    }
+    if let Some(anchor) = stats
+      .after
+      .as_ref()
+      .and_then(|anchor| store.get_task(anchor))
+    {
+      writeln!(output, "after:       {} {}", anchor.id, anchor.title)?;
+    }
+    if stats.from_due {
+      writeln!(output, "counted:     from due date")?;
+    }
+  }
+  if !task.effort.is_zero() {
+    let effort = humantime::format_duration(task.effort);
+    writeln!(output, "effort:      {effort}")?;
+  }
+  if !task.lead_time.is_zero() {
+    let lead_time = humantime::format_duration(task.lead_time);
+    writeln!(output, "lead time:   {lead_time}")?;
+  }
+  let mut completed: Vec<_> = store
+    .iter_todos(Some(id), Some(true))
+    .filter_map(|todo| Some((todo.due, todo.completed.as_ref()?.date)))
+    .collect();
+  completed.sort_unstable_by_key(|&(_, date)| date);
+  writeln!(output, "completions: {}", completed.len())?;
+  if let Some(remaining) = remaining_completions(store, task) {
+    writeln!(output, "remaining:   {remaining}")?;
+  }
+  if let Some(&(_, last)) = completed.last() {
+    let streak = completed
+      .iter()
+      .rev()
+      .take_while(|(due, date)| date.date() <= due.date())
+      .count();
+    let last_done = last.format(&DAY_FORMAT)?;
+    writeln!(
+      output,
+      "last done:   {last_done} ({})",
+      humanize_date(&last, &now)
+    )?;
+    writeln!(output, "streak:      {streak} on time")?;
+  }
+  if let Some(variation) = stats.and_then(|stats| stats.variation) {
+    writeln!(
+      output,
+      "consistency: {} (variation {variation:.2})",
+      consistency(variation)
+    )?;
+  }
   Ok(())
 }
 
-fn write_paused_task(
-  output: &mut impl Write,
-  width: usize,
-  Task { id, title }: &Task,
-) -> io::Result<()> {
-  writeln!(output, "{id:width$} {title}")
-}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DueBucket {
+  Overdue,
+  Today,
+  ThisWeek,
+  Later,
+}
+
+impl DueBucket {
+  /// The bucket of a todo due on `day`, both days being in the configured timezone
+  fn of(day: Date, today: Date) -> Self {
+    if day < today {
+      Self::Overdue
+    } else if day == today {
+      Self::Today
+    } else if day < today + time::Duration::WEEK {
+      Self::ThisWeek
+    } else {
+      Self::Later
+    }
+  }
+
+  const fn heading(self) -> &'static str {
+    match self {
+      Self::Overdue => "Overdue",
+      Self::Today => "Today",
+      Self::ThisWeek => "This week",
+      Self::Later => "Later",
+    }
+  }
+
+  /// Marks the bucket in the status column of `List`
+  const fn glyph(self) -> char {
+    match self {
+      Self::Overdue => '!',
+      Self::Today => '·',
+      Self::ThisWeek | Self::Later => ' ',
+    }
+  }
+}
+
+fn humanize_date(date: &TodoDate, now: &OffsetDateTime) -> String {
+  match (date.date() - now.date()).whole_days() {
+    0 => "today".into(),
+    1 => "tomorrow".into(),
+    -1 => "yesterday".into(),
+    days if days > 0 => format!("in {days} days"),
+    days => format!("{} days ago", -days),
+  }
+}
+
+/// Rounds an interval to a single unit fitting its magnitude, like `~10 days` or `~1 month`
+fn round_interval(interval: Duration) -> String {
+  const MINUTE: f64 = 60.0;
+  const HOUR: f64 = 60.0 * MINUTE;
+  const DAY: f64 = 24.0 * HOUR;
+  let secs = interval.as_secs_f64();
+  let (count, unit) = match secs {
+    s if s < HOUR => (s / MINUTE, "minute"),
+    s if s < DAY => (s / HOUR, "hour"),
+    s if s < 14.0 * DAY => (s / DAY, "day"),
+    s if s < 30.0 * DAY => (s / DAY / 7.0, "week"),
+    s if s < 365.0 * DAY => (s / DAY / 30.44, "month"),
+    s => (s / DAY / 365.25, "year"),
+  };
+  let count = count.round().max(1.0);
+  if (count - 1.0).abs() < f64::EPSILON {
+    format!("~1 {unit}")
+  } else {
+    format!("~{count} {unit}s")
+  }
+}
+
+fn write_digest<S: Store, A: Allesatt<Store = S>, B: Borrow<A>, W: Write>(
+  app: B,
+  output: &mut W,
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  let calendar = app.borrow().calendar();
+  let now = OffsetDateTime::now_utc();
+  let today = calendar.local_date(now);
+  let mut overdue = Vec::new();
+  let mut due_today = Vec::new();
+  let mut due_this_week = Vec::new();
+  let mut paused = 0;
+  for task in store.get_tasks() {
+    let Some(todo) = store.find_open_todo(&task.id) else {
+      paused += 1;
+      continue;
+    };
+    match DueBucket::of(calendar.local_date(todo.due), today) {
+      DueBucket::Overdue => overdue.push((todo, task)),
+      DueBucket::Today => due_today.push((todo, task)),
+      DueBucket::ThisWeek => due_this_week.push((todo, task)),
+      DueBucket::Later => (),
+    }
+  }
+
+  writeln!(output, "Allesatt digest for {}", today.format(&DAY_FORMAT)?)?;
+  writeln!(output)?;
+  writeln!(
+    output,
+    "{} overdue, {} due today, {} due later this week, {paused} paused.",
+    overdue.len(),
+    due_today.len(),
+    due_this_week.len()
+  )?;
+  for (heading, mut todos) in [
+    ("Overdue", overdue),
+    ("Due today", due_today),
+    ("Due later this week", due_this_week),
+  ] {
+    if todos.is_empty() {
+      continue;
+    }
+    todos.sort_by_key(|(todo, task)| (todo.due, &task.id));
+    writeln!(output)?;
+    writeln!(output, "{heading}:")?;
+    for (todo, Task { id, title, .. }) in todos {
+      let when = humanize_date(&todo.due, &now);
+      writeln!(output, "  - {title} (#{id}), due {when}")?;
+    }
+  }
+  Ok(())
+}
+
+fn create_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  args: &AddArgs,
+) -> Result<(), Box<dyn Error>> {
+  let due_every = args.every().unwrap_or(DEFAULT_EVERY);
+  let (task_id, todo_id) = app.borrow_mut().create_task(
+    args.description.clone(),
+    Some(due_every),
+    Some(OffsetDateTime::now_utc()),
+  )?;
+  if let Some(effort) = args.effort {
+    app.borrow_mut().set_task_effort(&task_id, *effort)?;
+  }
+  if let Some(lead) = args.lead {
+    app.borrow_mut().set_task_lead_time(&task_id, *lead)?;
+  }
+  if args.times.is_some() {
+    app
+      .borrow_mut()
+      .set_task_max_completions(&task_id, args.times)?;
+  }
+  if args.url.is_some() {
+    app.borrow_mut().set_task_url(&task_id, args.url.clone())?;
+  }
+  if args.yearly {
+    let created = app.borrow().get_store().get_task(&task_id).unwrap().created;
+    app.borrow_mut().set_task_yearly(&task_id, Some(created))?;
+  }
+  if args.fixed {
+    app.borrow_mut().learn_task(&task_id)?;
+  }
+  if args.from_due {
+    app.borrow_mut().set_task_from_due(&task_id, true)?;
+  }
+  print_todo(app.borrow().get_store(), output, &task_id, &todo_id)
+}
+
+/// Adds a task for each line of `source`, see `Cmd::AddBatch`
+fn add_batch<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  source: impl BufRead,
+) -> Result<(), Box<dyn Error>> {
+  let mut total = 0;
+  let mut failures = vec![];
+  for (index, line) in source.lines().enumerate() {
+    let line = line?;
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let args = match line.split_once(char::is_whitespace) {
+      Some((every, description)) if parse_every(every).is_ok() => AddArgs {
+        every: parse_every(every).ok(),
+        description: description.trim_start().into(),
+        ..AddArgs::default()
+      },
+      _ => AddArgs {
+        description: line.into(),
+        ..AddArgs::default()
+      },
+    };
+    total += 1;
+    if let Err(e) = create_task::<S, A, _, _>(app.borrow_mut(), output, &args) {
+      failures.push(format!("line {}: {e}", index + 1));
+    }
+  }
+  if failures.is_empty() {
+    return Ok(());
+  }
+  Err(
+    format!(
+      "{} of {total} tasks failed: {}",
+      failures.len(),
+      failures.join("; ")
+    )
+    .into(),
+  )
+}
+
+fn open_task<S: Store, A: Allesatt<Store = S>, B: Borrow<A>>(
+  app: B,
+  id: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let task = app
+    .borrow()
+    .get_store()
+    .get_task(id)
+    .ok_or("Task not found")?;
+  open::that(task.url.as_ref().ok_or("Task has no link")?)?;
+  Ok(())
+}
+
+fn clone_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  description: &str,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let (task_id, todo_id) =
+    app
+      .borrow_mut()
+      .clone_task(id, description.into(), Some(OffsetDateTime::now_utc()))?;
+  print_todo(app.borrow().get_store(), output, &task_id, &todo_id)
+}
+
+fn print_todo<S: Store, W: Write>(
+  store: &S,
+  output: &mut W,
+  task_id: &TaskId,
+  todo_id: &TodoId,
+) -> Result<(), Box<dyn Error>> {
+  let task = store.get_task(task_id).unwrap();
+  let todo = store.get_todo(todo_id).unwrap();
+  write_todo(
+    output,
+    0,
+    task,
+    &todo.due,
+    remaining_completions(store, task),
+    todo.progress,
+    false,
+    false,
+  )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_todo(
+  output: &mut impl Write,
+  width: usize,
+  Task { id, title, url, .. }: &Task,
+  date: &TodoDate,
+  remaining: Option<u32>,
+  progress: Option<(u32, u32)>,
+  with_time: bool,
+  done_today: bool,
+) -> Result<(), Box<dyn Error>> {
+  let date = date.format(if with_time {
+    &DAY_TIME_FORMAT
+  } else {
+    &DAY_FORMAT
+  })?;
+  write!(output, "{id:width$} {date} {title}")?;
+  if url.is_some() {
+    write!(output, " (link)")?;
+  }
+  if let Some((done, total)) = progress {
+    write!(output, " ({done}/{total})")?;
+  }
+  if let Some(remaining) = remaining {
+    write!(output, " ({remaining} left)")?;
+  }
+  if done_today {
+    write!(output, " ✓")?;
+  }
+  writeln!(output)?;
+  Ok(())
+}
+
+fn write_paused_task(
+  output: &mut impl Write,
+  width: usize,
+  Task { id, title, .. }: &Task,
+) -> io::Result<()> {
+  writeln!(output, "{id:width$} {title}")
+}
+
+fn write_blocked_task(output: &mut impl Write, width: usize, task: &Task) -> io::Result<()> {
+  let Task { id, title, .. } = task;
+  let reason = task.blocked.as_deref().unwrap_or_default();
+  writeln!(output, "{id:width$} {title} ({reason})")
+}
+
+/// Tells whether `do --once-per` skips the task because it was done within `once_per`, writing
+/// why if so
+fn skip_once_per<S: Store>(
+  store: &S,
+  id: &TaskId,
+  once_per: Duration,
+  output: &mut impl Write,
+) -> Result<bool, Box<dyn Error>> {
+  if !store.completed_since(id, OffsetDateTime::now_utc() - once_per) {
+    return Ok(false);
+  }
+  let once_per = humantime::format_duration(once_per);
+  writeln!(
+    output,
+    "Task {id} was already done within {once_per}, skipping"
+  )?;
+  Ok(true)
+}
+
+/// The time of day `time` on the day of `now` in `timezone`, or in UTC without one
+///
+/// Fails if that is later than `now`, since completions can't be in the future.
+fn snap_to_time(
+  now: OffsetDateTime,
+  time: Time,
+  timezone: Option<&Tz>,
+) -> Result<OffsetDateTime, Box<dyn Error>> {
+  let snapped = timezone
+    .map_or(now, |timezone| now.to_timezone(timezone))
+    .replace_time(time);
+  if snapped > now {
+    return Err("--snap-to is later than now".into());
+  }
+  Ok(snapped)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  snap_to: Option<Time>,
+  repeat: u32,
+  note: Option<&str>,
+  unpause: bool,
+  once_per: Option<Duration>,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  if let Some(once_per) = once_per {
+    if skip_once_per(app.borrow().get_store(), id, once_per, output)? {
+      return Ok(());
+    }
+  }
+  let now = OffsetDateTime::now_utc();
+  let timezone = app.borrow().calendar().timezone;
+  let now = snap_to.map_or(Ok(now), |time| snap_to_time(now, time, timezone))?;
+  let store = app.borrow().get_store();
+  match store.get_open_todo(id) {
+    Ok(_) => (),
+    Err(OpenTodoError::Paused(_)) if unpause => {
+      app.borrow_mut().unpause_task(id, Some(now))?;
+    }
+    Err(OpenTodoError::Paused(_)) => {
+      return Err("Task is paused, use --unpause to complete it anyway".into());
+    }
+    Err(e) => return Err(e.into()),
+  }
+  let store = app.borrow().get_store();
+  let open_todo = store.get_open_todo(id)?;
+  let start = store
+    .iter_todos(Some(id), Some(true))
+    .filter_map(|todo| todo.completed.as_ref().map(|completed| completed.date))
+    .max()
+    .unwrap_or(open_todo.due)
+    .min(now);
+  for i in 1..=repeat {
+    let todo_id = app.borrow().get_store().get_open_todo(id)?.id.clone();
+    let completed = TodoCompleted::new(start + (now - start) * i / repeat)
+      .with_note(note.filter(|_| i == repeat).map(Into::into));
+    app.borrow_mut().complete_todo(&todo_id, completed)?;
+  }
+  let store = app.borrow().get_store();
+  if let Some(todo) = store.find_open_todo(id) {
+    print_todo(store, output, id, &todo.id)
+  } else {
+    write_paused_task(output, 0, store.get_task(id).unwrap())?;
+    Ok(())
+  }
+}
+
+fn pause_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().pause_task(id)?;
+  let store = app.borrow().get_store();
+  write_paused_task(output, 0, store.get_task(id).unwrap())?;
+  Ok(())
+}
+
+fn unpause_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  at: Option<Date>,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let at = at.map(|date| OffsetDateTime::now_utc().replace_date(date));
+  let todo_id = app.borrow_mut().unpause_task(id, at)?;
+  let store = app.borrow().get_store();
+  print_todo(store, output, id, &todo_id)
+}
+
+fn block_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  reason: Option<&String>,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().set_task_blocked(id, reason.cloned())?;
+  let store = app.borrow().get_store();
+  let task = store.get_task(id).unwrap();
+  match store.find_open_todo(id) {
+    Some(_) if reason.is_some() => write_blocked_task(output, 0, task)?,
+    Some(todo) => print_todo(store, output, id, &todo.id)?,
+    None => write_paused_task(output, 0, task)?,
+  }
+  Ok(())
+}
+
+fn task_after<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  anchor: Option<&TaskRef>,
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  let id = &id.resolve(store)?;
+  let anchor = anchor.map(|anchor| anchor.resolve(store)).transpose()?;
+  app.borrow_mut().set_task_after(id, anchor)?;
+  let store = app.borrow().get_store();
+  match store.find_open_todo(id) {
+    Some(todo) => print_todo(store, output, id, &todo.id)?,
+    None => write_paused_task(output, 0, store.get_task(id).unwrap())?,
+  }
+  Ok(())
+}
+
+fn task_from_due<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  from_due: bool,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().set_task_from_due(id, from_due)?;
+  let store = app.borrow().get_store();
+  match store.find_open_todo(id) {
+    Some(todo) => print_todo(store, output, id, &todo.id)?,
+    None => write_paused_task(output, 0, store.get_task(id).unwrap())?,
+  }
+  Ok(())
+}
+
+fn defer_all<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  by: &HumanDuration,
+  only_overdue: bool,
+) -> Result<(), Box<dyn Error>> {
+  let now = OffsetDateTime::now_utc();
+  let store = app.borrow().get_store();
+  let todo_ids: Vec<_> = store
+    .iter_todos(None, Some(false))
+    .filter(|todo| {
+      if only_overdue {
+        todo.due.date() < now.date()
+      } else {
+        todo.due <= now + time::Duration::DAY
+      }
+    })
+    .map(|todo| todo.id.clone())
+    .collect();
+  for todo_id in &todo_ids {
+    app.borrow_mut().postpone_todo(todo_id, **by)?;
+  }
+  writeln!(output, "Deferred {} todos by {by}", todo_ids.len())?;
+  Ok(())
+}
+
+fn recompute_due<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+  let changes: Vec<_> = app
+    .borrow()
+    .get_store()
+    .open_todos()
+    .into_iter()
+    .filter_map(|(todo, task)| {
+      let due = app.borrow().recompute_due(&task.id)?;
+      (due != todo.due).then(|| (todo.id.clone(), task, todo.due, due))
+    })
+    .map(|(todo_id, task, old, new)| {
+      writeln!(
+        output,
+        "{} {}: {} -> {}",
+        task.id,
+        task.title,
+        old.format(&DAY_FORMAT)?,
+        new.format(&DAY_FORMAT)?
+      )?;
+      Ok((todo_id, new))
+    })
+    .collect::<Result<_, Box<dyn Error>>>()?;
+  if !dry_run {
+    for (todo_id, due) in &changes {
+      app.borrow_mut().set_todo_due(todo_id, *due)?;
+    }
+  }
+  let verb = if dry_run { "Would change" } else { "Changed" };
+  writeln!(output, "{verb} {} due dates", changes.len())?;
+  Ok(())
+}
+
+fn add_task_alias<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  name: &str,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().add_task_alias(id, name.into())?;
+  let Task { id, title, .. } = app.borrow().get_store().get_task(id).unwrap();
+  writeln!(output, "{id} @{name} {title}")?;
+  Ok(())
+}
+
+fn set_progress<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  done: u32,
+  total: u32,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let todo_id = app.borrow().get_store().get_open_todo(id)?.id.clone();
+  app.borrow_mut().set_todo_progress(&todo_id, done, total)?;
+  print_todo(app.borrow().get_store(), output, id, &todo_id)
+}
+
+fn swap_tasks<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  a: &TaskRef,
+  b: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let a = a.resolve(app.borrow().get_store())?;
+  let b = b.resolve(app.borrow().get_store())?;
+  app.borrow_mut().swap_tasks(&a, &b)?;
+  let store = app.borrow().get_store();
+  for id in [&a, &b] {
+    let Task { id, title, .. } = store.get_task(id).unwrap();
+    writeln!(output, "{id} {title}")?;
+  }
+  Ok(())
+}
+
+fn rename_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  description: &str,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().rename_task(id, description.into())?;
+  let store = app.borrow().get_store();
+  write_paused_task(output, 0, store.get_task(id).unwrap())?;
+  Ok(())
+}
+
+fn replace_titles<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  pattern: &str,
+  replacement: &str,
+  regex: bool,
+  dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+  let pattern = if regex {
+    Regex::new(pattern)?
+  } else {
+    Regex::new(&regex::escape(pattern))?
+  };
+  let mut renames: Vec<_> = app
+    .borrow()
+    .get_store()
+    .get_tasks()
+    .into_iter()
+    .filter_map(|task| {
+      let title = if regex {
+        pattern.replace_all(&task.title, replacement)
+      } else {
+        pattern.replace_all(&task.title, NoExpand(replacement))
+      };
+      (title != task.title).then(|| (task.id.clone(), task.title.clone(), title.into_owned()))
+    })
+    .collect();
+  renames.sort_unstable_by(|(id1, ..), (id2, ..)| id1.cmp(id2));
+  for (id, old_title, new_title) in &renames {
+    if !dry_run {
+      app.borrow_mut().rename_task(id, new_title.clone())?;
+    }
+    writeln!(output, "{id} {old_title} -> {new_title}")?;
+  }
+  let verb = if dry_run { "Would rename" } else { "Renamed" };
+  writeln!(output, "{verb} {} tasks", renames.len())?;
+  Ok(())
+}
+
+fn set_task_effort<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  effort: &HumanDuration,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().set_task_effort(id, **effort)?;
+  let Task { id, title, .. } = app.borrow().get_store().get_task(id).unwrap();
+  writeln!(output, "{id} takes {effort} {title}")?;
+  Ok(())
+}
+
+fn set_task_lead_time<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  lead: &HumanDuration,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  app.borrow_mut().set_task_lead_time(id, **lead)?;
+  let Task { id, title, .. } = app.borrow().get_store().get_task(id).unwrap();
+  writeln!(output, "{id} is listed {lead} early {title}")?;
+  Ok(())
+}
+
+fn task_later<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let (todo, before) = {
+    let todo = app.borrow().get_store().get_open_todo(id)?;
+    (todo.id.clone(), todo.due)
+  };
+  app.borrow_mut().todo_later(&todo)?;
+  let store = app.borrow().get_store();
+  print_todo(store, output, id, &todo)?;
+  if store.get_todo(&todo).is_some_and(|todo| todo.due == before) {
+    writeln!(output, "Task {id} was already deferred as far as allowed")?;
+  }
+  Ok(())
+}
+
+fn task_unlater<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let todo = app.borrow().get_store().get_open_todo(id)?.id.clone();
+  app.borrow_mut().undo_todo_later(&todo)?;
+  let store = app.borrow().get_store();
+  print_todo(store, output, id, &todo)
+}
+
+fn touch_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let todo = app.borrow_mut().touch_task(id, OffsetDateTime::now_utc())?;
+  let store = app.borrow().get_store();
+  print_todo(store, output, id, &todo)
+}
+
+fn amend_completion<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  old: Date,
+  new: Date,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let (todo_id, date) = find_completion(app.borrow().get_store(), id, old)?;
+  app
+    .borrow_mut()
+    .amend_completion(&todo_id, date.replace_date(new))?;
+  let store = app.borrow().get_store();
+  if let Some(todo) = store.find_open_todo(id) {
+    print_todo(store, output, id, &todo.id)
+  } else {
+    write_paused_task(output, 0, store.get_task(id).unwrap())?;
+    Ok(())
+  }
+}
+
+/// Returns the only todo of a task completed on the given day with its completion date
+fn find_completion<S: Store>(
+  store: &S,
+  id: &TaskId,
+  day: Date,
+) -> Result<(TodoId, TodoDate), Box<dyn Error>> {
+  let todos: Vec<_> = store
+    .iter_todos(Some(id), Some(true))
+    .filter_map(|todo| Some((todo.id.clone(), todo.completed.as_ref()?.date)))
+    .filter(|(_, date)| date.date() == day)
+    .collect();
+  let day = day.format(&DAY_FORMAT)?;
+  match <[_; 1]>::try_from(todos) {
+    Ok([todo]) => Ok(todo),
+    Err(todos) if todos.is_empty() => Err(format!("Task wasn't done on {day}").into()),
+    Err(_) => Err(format!("Task was done several times on {day}").into()),
+  }
+}
+
+fn reattach_completion<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  day: Date,
+  to: &TaskRef,
+) -> Result<(), Box<dyn Error>> {
+  let store = app.borrow().get_store();
+  let id = &id.resolve(store)?;
+  let to = &to.resolve(store)?;
+  let (todo_id, _) = find_completion(store, id, day)?;
+  app.borrow_mut().reattach_todo(&todo_id, to)?;
+  let store = app.borrow().get_store();
+  for id in [id, to] {
+    match store.find_open_todo(id) {
+      Some(todo) => print_todo(store, output, id, &todo.id)?,
+      None => write_paused_task(output, 0, store.get_task(id).unwrap())?,
+    }
+  }
+  Ok(())
+}
+
+fn learn_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
+  mut app: B,
+  output: &mut W,
+  id: &TaskRef,
+  learn: bool,
+) -> Result<(), Box<dyn Error>> {
+  let id = &id.resolve(app.borrow().get_store())?;
+  let due_every = if learn {
+    app.borrow_mut().learn_task(id)?
+  } else {
+    app.borrow_mut().unlearn_task(id)?
+  };
+  let Task { id, title, .. } = app.borrow().get_store().get_task(id).unwrap();
+  let due_every = humantime::format_duration(due_every);
+  writeln!(output, "{id} every {due_every} {title}")?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    add_batch, append_to_journal, apply_list_opts, check_duplicate_title, count_overdue,
+    early_completion_question, handle_command, handle_command_impl, hook_command, lock_with_retry,
+    parse_every, round_interval, snap_to_time, AddArgs, Cmd, Config, ListArgs, Opts,
+    ScheduleFormat, TaskRef, DAY_FORMAT,
+  };
+  use crate::engine::{
+    try_new as try_new_engine, Allesatt, Calendar, MemStore, ReadWriteLogger, Store, TaskId,
+  };
+  use clap::Parser;
+  use regex::{escape, Regex};
+  use std::borrow::Borrow;
+  use std::fmt::Display;
+  use std::str::FromStr;
+  use time::macros::{date, datetime, format_description, time};
+  use time::{Duration, OffsetDateTime};
+
+  fn today_plus(days: i64) -> impl Display {
+    (OffsetDateTime::now_utc() + Duration::days(days))
+      .format(&DAY_FORMAT)
+      .unwrap()
+  }
+
+  /// The time `days` days ago, as log records write dates
+  fn log_date_ago(days: i64) -> String {
+    (OffsetDateTime::now_utc() - Duration::days(days))
+      .format(&format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]"
+      ))
+      .unwrap()
+  }
+
+  fn exec_command(cmd: impl Borrow<Cmd>, log_in: impl Borrow<str>) -> (String, String) {
+    let log_in = log_in.borrow();
+    let mut output = Vec::new();
+    let mut log_out: Vec<u8> = Vec::new();
+    handle_command_impl(
+      cmd.borrow(),
+      try_new_engine(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), &mut log_out),
+      )
+      .unwrap(),
+      &mut output,
+    )
+    .unwrap();
+    let log_out = std::str::from_utf8(&log_out).unwrap();
+    (
+      String::with_capacity(log_in.len() + log_out.len()) + log_in + log_out,
+      String::from_utf8(output).unwrap(),
+    )
+  }
+
+  #[test]
+  fn test_handle_command_impl() {
+    let (log_out, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      "",
+    );
+    assert_eq!(output, "");
+    assert_eq!(log_out, "");
+
+    let (log_out, output) = exec_command(
+      Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
+        description: "task".into(),
+        ..AddArgs::default()
+      }),
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
+    let now = format!("{}", today_plus(0)) + r"T[0-9]{2}:[0-9]{2}:[0-9]{2}\.[0-9]+";
+    let r = Regex::new(&format!(
+      "{}{now}{}",
+      escape(r#"create_task3: ["task", "30days", ""#),
+      escape("\", 1, 1]\n")
+    ))
+    .unwrap();
+    assert!(r.is_match(&log_out));
+
+    let (log_out, output) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} task\n", today_plus(30)));
+    let r = Regex::new(
+      &(r.to_string()
+        + &escape(r#"complete_todo1: [1, ""#)
+        + &now
+        + r#""\]
+"#),
+    )
+    .unwrap();
+    assert!(r.is_match(&log_out));
+
+    let (log_out, output) = exec_command(
+      Cmd::Later {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+      },
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} task\n", today_plus(30 + 6)));
+    let r = Regex::new(&(r.to_string() + "todo_later1: \\[2\\]\n")).unwrap();
+    assert!(r.is_match(&log_out));
+
+    let (log_out, output) = exec_command(
+      Cmd::Pause {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+      },
+      log_out,
+    );
+    assert_eq!(output, "1 task\n");
+    let r = Regex::new(&(r.to_string() + "pause_task1: \\[1\\]\n")).unwrap();
+    assert!(r.is_match(&log_out));
+
+    let (new_log_out, output) = exec_command(Cmd::List(ListArgs::default()), log_out.as_ref());
+    assert_eq!(output, "Paused tasks:\n1 task\n");
+    assert_eq!(new_log_out, log_out);
+
+    let (log_out, output) = exec_command(
+      Cmd::Unpause {
+        id: TaskRef::from_str("1").unwrap(),
+        at: None,
+      },
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
+    let r = Regex::new(&(r.to_string() + "unpause_task1: \\[1\\]\n")).unwrap();
+    assert!(r.is_match(&log_out));
+
+    let (new_log_out, output) = exec_command(Cmd::List(ListArgs::default()), log_out.as_ref());
+    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
+    assert_eq!(new_log_out, log_out);
+
+    let (log_out, _) = exec_command(
+      Cmd::Pause {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+      },
+      log_out,
+    );
+    let next_week = OffsetDateTime::now_utc() + Duration::days(7);
+    let (log_out, output) = exec_command(
+      Cmd::Unpause {
+        id: TaskRef::from_str("1").unwrap(),
+        at: Some(next_week.date()),
+      },
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} task\n", today_plus(7)));
+    assert!(log_out.contains(&format!("unpause_task2: [1, \"{}T", today_plus(7))));
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), log_out);
+    assert_eq!(output, format!("1 {} task\n", today_plus(7)));
+  }
+
+  #[test]
+  fn several_tasks() {
+    let log_out = (1..=2).fold(String::new(), |log_out, i| {
+      exec_command(
+        Cmd::Add(AddArgs {
+          every: Some("7days".parse().unwrap()),
+          description: format!("Task {i}"),
+          ..AddArgs::default()
+        }),
+        log_out,
+      )
+      .0
+    });
+    let ids = |ids: &[&str]| ids.iter().map(|id| id.parse().unwrap()).collect();
+    let mut new_log = Vec::new();
+    let mut output = Vec::new();
+    let result = handle_command_impl(
+      &Cmd::Do {
+        ids: ids(&["1", "7", "2"]),
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      try_new_engine(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log_out.as_bytes(), &mut new_log),
+      )
+      .unwrap(),
+      &mut output,
+    );
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "1 of 3 tasks failed: 7: Task not found"
+    );
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      format!("1 {0} Task 1\n2 {0} Task 2\n", today_plus(7))
+    );
+    let new_log = String::from_utf8(new_log).unwrap();
+    assert!(
+      Regex::new(r"^complete_todo1: \[1, .*\]\ncomplete_todo1: \[2, .*\]\n$")
+        .unwrap()
+        .is_match(&new_log)
+    );
+
+    let (log_out, output) = exec_command(
+      Cmd::Pause {
+        ids: ids(&["1", "2"]),
+      },
+      log_out,
+    );
+    assert_eq!(output, "1 Task 1\n2 Task 2\n");
+    assert!(log_out.ends_with("pause_task1: [1]\npause_task1: [2]\n"));
+  }
+
+  #[test]
+  fn repeated_completions() {
+    let week_ago = log_date_ago(7);
+    let log_in = format!(
+      r#"create_task1: ["Daily", {{"secs":86400,"nanos":0}}, 1, 1]
+complete_todo1: [1, "{week_ago}"]
+"#
+    );
+    let (log_out, output) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 7,
+        unpause: false,
+        once_per: None,
+      },
+      &*log_in,
+    );
+    assert_eq!(output, format!("1 {} Daily\n", today_plus(1)));
+    assert_eq!(log_out.matches("complete_todo1").count(), 8);
+    assert!(Opts::try_parse_from(["allesatt", "do", "--repeat", "0", "1"]).is_err());
+    for (i, line) in log_out.lines().skip(2).enumerate() {
+      assert!(line.starts_with(&format!(
+        "complete_todo1: [{}, \"{}T",
+        i + 2,
+        today_plus(i64::try_from(i).unwrap() - 6)
+      )));
+    }
+  }
+
+  #[test]
+  fn refuses_duplicate_titles_interactively() {
+    let (log_out, _) = exec_command(
+      Cmd::Add(AddArgs {
+        description: "Water plants".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_out.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let store = app.get_store();
+    let mut args = AddArgs {
+      description: "Water plants".into(),
+      ..AddArgs::default()
+    };
+    assert!(check_duplicate_title(store, &args, false).is_ok());
+    assert_eq!(
+      check_duplicate_title(store, &args, true)
+        .unwrap_err()
+        .to_string(),
+      "Task 1 is already called Water plants, use --allow-duplicate to add it anyway"
+    );
+    args.allow_duplicate = true;
+    assert!(check_duplicate_title(store, &args, true).is_ok());
+    args.description = "Water flowers".into();
+    args.allow_duplicate = false;
+    assert!(check_duplicate_title(store, &args, true).is_ok());
+  }
+
+  #[test]
+  fn asks_before_early_completion() {
+    let (log_out, _) = exec_command(
+      Cmd::Add(AddArgs {
+        every: Some("7days".parse().unwrap()),
+        description: "Water plants".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    let id = TaskId::from_str("1").unwrap();
+    let question = |log: &str| {
+      let app = try_new_engine(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+      )
+      .unwrap();
+      early_completion_question(app.get_store(), &id).unwrap()
+    };
+    assert_eq!(question(&log_out), None);
+
+    let (log_out, _) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      log_out,
+    );
+    assert_eq!(
+      question(&log_out),
+      Some(format!(
+        "Water plants isn't due until {}; complete anyway?",
+        today_plus(7)
+      ))
+    );
+  }
+
+  #[test]
+  fn retries_locked_log() {
+    let path = std::env::temp_dir().join(format!("allesatt-lock-{}", std::process::id()));
+    let holder = std::fs::File::create(&path).unwrap();
+    let file = std::fs::File::open(&path).unwrap();
+    let timeout = std::time::Duration::from_millis(50);
+    holder.lock().unwrap();
+    let result = lock_with_retry(&file, false, timeout);
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .contains("locked by another process"));
+    holder.unlock().unwrap();
+    holder.lock_shared().unwrap();
+    lock_with_retry(&file, false, timeout).unwrap();
+    assert!(lock_with_retry(&holder, true, timeout).is_err());
+    drop(file);
+    lock_with_retry(&holder, true, timeout).unwrap();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn appends_to_journal() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0", "New broom"]
+complete_todo1: [2, "2024-01-08T10:30:00.0", "Found a coin"]
+"#;
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let path = std::env::temp_dir().join(format!("allesatt-journal-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "2023-12-24 18:00 Gifts: All wrapped\n").unwrap();
+    append_to_journal(path, app.get_store(), &TaskId::from_str("1").unwrap()).unwrap();
+    assert_eq!(
+      std::fs::read_to_string(path).unwrap(),
+      "2023-12-24 18:00 Gifts: All wrapped\n2024-01-08 10:30 Sweep: Found a coin\n"
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn quotes_hook_arguments() {
+    assert_eq!(
+      hook_command(
+        "notify-send {title} --id {id}",
+        &TaskId::from_str("3").unwrap(),
+        "Bob's $(chores)"
+      ),
+      r"notify-send 'Bob'\''s $(chores)' --id '3'"
+    );
+  }
+
+  #[test]
+  fn task_info() {
+    let log_in = format!(
+      r#"create_task2: ["Water plants", "7days", 1, 1]
+complete_todo1: [1, "{}"]
+complete_todo1: [2, "{}"]
+complete_todo1: [3, "{}"]
+add_task_alias1: [1, "plants"]
+"#,
+      log_date_ago(22),
+      log_date_ago(14),
+      log_date_ago(8)
+    );
+    let (_, output) = exec_command(
+      Cmd::Info {
+        id: TaskRef::from_str("@plants").unwrap(),
+        exact: false,
+      },
+      &*log_in,
+    );
+    assert_eq!(
+      output,
+      format!(
+        "1 Water plants
+aliases:     @plants
+age:         22 days (since {})
+due:         {} (yesterday)
+interval:    ~7 days (average of the last 2 intervals)
+completions: 3
+last done:   {} (8 days ago)
+streak:      1 on time
+consistency: high (variation 0.14)
+",
+        today_plus(-22),
+        today_plus(-1),
+        today_plus(-8)
+      )
+    );
+  }
+
+  #[test]
+  fn add_fixed() {
+    let (log_out, _) = exec_command(
+      Cmd::Add(AddArgs {
+        every: Some("3days".parse().unwrap()),
+        fixed: true,
+        description: "Water plants".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    assert!(log_out.ends_with("learn_task1: [1]\n"));
+    let log_in = log_out
+      + r#"complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-11T10:00:00.0"]
+complete_todo1: [3, "2024-01-12T10:00:00.0"]
+"#;
+    let (_, output) = exec_command(
+      Cmd::Info {
+        id: TaskRef::from_str("1").unwrap(),
+        exact: false,
+      },
+      &*log_in,
+    );
+    assert!(output.contains("due:         2024-01-15"));
+    assert!(output.contains("interval:    3days (no longer adapted)"));
+
+    let opts = Opts::try_parse_from(["allesatt", "add", "--no-learn", "Water plants"]).unwrap();
+    assert!(matches!(
+      opts.cmd,
+      Some(Cmd::Add(AddArgs { fixed: true, .. }))
+    ));
+  }
+
+  #[test]
+  fn add_with_interval_shorthand() {
+    use std::time::Duration as StdDuration;
+    let add = |flag: &str| {
+      let opts = Opts::try_parse_from(["allesatt", "add", flag, "Water plants"])?;
+      let Some(Cmd::Add(args)) = opts.cmd else {
+        unreachable!()
+      };
+      Ok::<_, clap::Error>(args.every())
+    };
+    assert_eq!(add("--daily").unwrap(), Some(StdDuration::from_hours(24)));
+    assert_eq!(
+      add("--biweekly").unwrap(),
+      Some(StdDuration::from_hours(24 * 14))
+    );
+    assert_eq!(
+      add("--every=3days").unwrap(),
+      Some(StdDuration::from_hours(24 * 3))
+    );
+    assert!(
+      Opts::try_parse_from(["allesatt", "add", "--weekly", "--every", "3days", "x"]).is_err()
+    );
+    assert!(Opts::try_parse_from(["allesatt", "add", "--monthly", "--yearly", "x"]).is_err());
+
+    let (log_out, _) = exec_command(
+      Cmd::Add(AddArgs {
+        weekly: true,
+        description: "Water plants".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    assert!(log_out.contains(r#"["Water plants", "7days", "#));
+  }
+
+  #[test]
+  fn done_for_task() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-08T10:00:00.0", "New broom"]
+complete_todo1: [3, "2024-01-16T10:00:00.0"]
+"#;
+    let (_, output) = exec_command(
+      Cmd::Done {
+        id: Some(TaskRef::from_str("1").unwrap()),
+        json: false,
+        since: None,
+        until: None,
+        porcelain: false,
+      },
+      log_in,
+    );
+    assert_eq!(
+      output,
+      "1 2024-01-01 Sweep\n1 2024-01-08 Sweep\n    New broom\n1 2024-01-16 Sweep\nconsistency: high (variation 0.07)\n"
+    );
+
+    let (log_out, _) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: Some("Swept twice".into()),
+        repeat: 2,
+        unpause: false,
+        once_per: None,
+      },
+      log_in,
+    );
+    assert_eq!(log_out.matches("Swept twice").count(), 1);
+    assert!(log_out.trim_end().ends_with(", \"Swept twice\"]"));
+  }
+
+  #[test]
+  fn touch() {
+    let long_ago = log_date_ago(10);
+    let log_in = format!(
+      r#"create_task1: ["Daily", {{"secs":86400,"nanos":0}}, 1, 1]
+complete_todo1: [1, "{long_ago}"]
+"#
+    );
+    let (log_out, output) = exec_command(
+      Cmd::Touch {
+        id: TaskRef::from_str("1").unwrap(),
+      },
+      &*log_in,
+    );
+    assert_eq!(output, format!("1 {} Daily\n", today_plus(1)));
+    assert!(log_out.starts_with(&(log_in + "touch_task1: [1, \"")));
+
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), &*log_out);
+    assert_eq!(output, format!("1 {} Daily\n", today_plus(1)));
+    let (_, output) = exec_command(
+      Cmd::Done {
+        id: None,
+        json: false,
+        since: None,
+        until: None,
+        porcelain: false,
+      },
+      &*log_out,
+    );
+    assert_eq!(output.lines().count(), 1);
+  }
+
+  #[test]
+  fn parses_frequencies() {
+    let every = |s| parse_every(s).map(|every| every.to_string());
+    assert_eq!(every("3/week").unwrap(), "2days 8h");
+    assert_eq!(every("3x per week").unwrap(), "2days 8h");
+    assert_eq!(every("2 per month").unwrap(), "15days 5h 17m");
+    assert!(every("twice a week").is_err());
+    assert_eq!(every("1 times a day").unwrap(), "1day");
+    assert_eq!(every("4/2weeks").unwrap(), "3days 12h");
+    assert_eq!(every("10days").unwrap(), "10days");
+    assert!(every("0/week").is_err());
+    assert!(every("3/fortnight").is_err());
+  }
+
+  #[test]
+  fn amend_completion() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-08T10:00:00.0", "New broom"]
+complete_todo1: [3, "2024-01-15T10:00:00.0"]
+"#;
+    let (log_out, output) = exec_command(
+      Cmd::Amend {
+        id: TaskRef::from_str("1").unwrap(),
+        old: date!(2024 - 01 - 15),
+        new: date!(2024 - 01 - 22),
+      },
+      log_in,
+    );
+    assert_eq!(output, "1 2024-02-01 Sweep\n");
+    assert!(log_out.ends_with("amend_completion1: [3, \"2024-01-22T10:00:00.0\"]\n"));
+    let (_, output) = exec_command(
+      Cmd::Info {
+        id: TaskRef::from_str("1").unwrap(),
+        exact: true,
+      },
+      &*log_out,
+    );
+    assert!(output.contains("interval:    10days 12h (average of the last 2 intervals)"));
+  }
+
+  #[test]
+  fn do_paused_task() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+pause_task1: [1]
+"#;
+    let do_task = |unpause| Cmd::Do {
+      ids: vec![TaskRef::from_str("1").unwrap()],
+      snap_to: None,
+      yes: false,
+      note: None,
+      repeat: 1,
+      unpause,
+      once_per: None,
+    };
+    let result = handle_command_impl(
+      &do_task(false),
+      try_new_engine(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
+      )
+      .unwrap(),
+      &mut Vec::new(),
+    );
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Task is paused, use --unpause to complete it anyway"
+    );
+
+    let (log_out, output) = exec_command(do_task(true), log_in);
+    assert_eq!(output, format!("1 {} Sweep\n", today_plus(7)));
+    assert!(
+      Regex::new(r"unpause_task2: \[1, .*\]\ncomplete_todo1: \[2, .*\]\n$")
+        .unwrap()
+        .is_match(&log_out)
+    );
+  }
+
+  #[test]
+  fn hints_at_adding_first_task() {
+    let list = |upcoming_only| {
+      Cmd::List(ListArgs {
+        upcoming_only,
+        hint_empty: true,
+        ..ListArgs::default()
+      })
+    };
+    let (_, output) = exec_command(list(false), "");
+    assert_eq!(
+      output,
+      "No tasks yet. Add one with `allesatt add <description>`\n"
+    );
+    let (_, output) = exec_command(list(true), "create_task2: [\"T\", \"7days\", 1, 1]\n");
+    assert_eq!(output, "");
+  }
+
+  #[test]
+  fn block_task() {
+    let log_in = r#"create_task2: ["Mow lawn", "7days", 1, 1]
+create_task2: ["Sweep", "7days", 2, 2]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-02T10:00:00.0"]
+"#;
+    let (log_out, output) = exec_command(
+      Cmd::Block {
+        id: TaskRef::from_str("1").unwrap(),
+        reason: "Mower broken".into(),
+      },
+      log_in,
+    );
+    assert_eq!(output, "1 Mow lawn (Mower broken)\n");
+    assert!(log_out.ends_with("set_task_blocked1: [1, \"Mower broken\"]\n"));
+    let list = Cmd::List(ListArgs {
+      all: true,
+      ..ListArgs::default()
+    });
+    let (_, output) = exec_command(&list, &*log_out);
+    assert_eq!(
+      output,
+      "2 2024-01-09 Sweep\n\nBlocked tasks:\n1 Mow lawn (Mower broken)\n"
+    );
+
+    let (log_out, output) = exec_command(
+      Cmd::Unblock {
+        id: TaskRef::from_str("1").unwrap(),
+      },
+      log_out,
+    );
+    assert_eq!(output, "1 2024-01-08 Mow lawn\n");
+    assert!(log_out.ends_with("set_task_blocked1: [1, null]\n"));
+    let (_, output) = exec_command(&list, log_out);
+    assert_eq!(output, "1 2024-01-08 Mow lawn\n2 2024-01-09 Sweep\n");
+  }
+
+  #[test]
+  fn lists_tasks_done_today() {
+    let log_in = r#"create_task2: ["Mow lawn", "7days", 1, 1]
+create_task2: ["Sweep", "7days", 2, 2]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2023-12-30T10:00:00.0"]
+"#;
+    let list = |done_today| {
+      Cmd::List(ListArgs {
+        as_of: Some(date!(2024 - 01 - 01).midnight().assume_utc() + Duration::hours(12)),
+        max_not_due: Some(0),
+        done_today,
+        ..ListArgs::default()
+      })
+    };
+    let (_, output) = exec_command(list(false), log_in);
+    assert_eq!(output, "");
+    let (_, output) = exec_command(list(true), log_in);
+    assert_eq!(output, "1 2024-01-08 Mow lawn ✓\n");
+  }
+
+  #[test]
+  fn counts_from_due_date() {
+    let log_in = "create_task2: [\"Bins\", \"7days\", 1, 1]\n";
+    let (log_out, _) = exec_command(
+      Cmd::FromDue {
+        id: TaskRef::from_str("1").unwrap(),
+        off: false,
+      },
+      log_in,
+    );
+    assert!(log_out.ends_with("set_task_from_due1: [1, true]\n"));
+    let info = Cmd::Info {
+      id: TaskRef::from_str("1").unwrap(),
+      exact: false,
+    };
+    let (_, output) = exec_command(info, log_out);
+    assert!(output.contains("counted:     from due date\n"));
+  }
+
+  #[test]
+  fn task_after_other_task() {
+    let log_in = r#"create_task2: ["Change oil", "365days", 1, 1]
+create_task2: ["Rotate tires", "180days", 2, 2]
+complete_todo1: [2, "2024-01-01T10:00:00.0"]
+"#;
+    let (log_out, output) = exec_command(
+      Cmd::After {
+        id: TaskRef::from_str("2").unwrap(),
+        anchor: Some(TaskRef::from_str("1").unwrap()),
+      },
+      log_in,
+    );
+    assert_eq!(output, "2 2024-06-29 Rotate tires\n");
+    assert!(log_out.ends_with("set_task_after1: [2, 1]\n"));
+
+    let log_out = log_out + "complete_todo1: [1, \"2024-03-01T10:00:00.0\"]\n";
+    let list = Cmd::List(ListArgs {
+      all: true,
+      ..ListArgs::default()
+    });
+    let (_, output) = exec_command(list, log_out);
+    assert_eq!(
+      output,
+      "2 2024-08-28 Rotate tires\n1 2025-03-01 Change oil\n"
+    );
+  }
+
+  #[test]
+  fn do_once_per() {
+    let log_in = "create_task2: [\"Feed cat\", \"12h\", 1, 1]\n";
+    let do_task = Cmd::Do {
+      ids: vec![TaskRef::from_str("1").unwrap()],
+      snap_to: None,
+      yes: false,
+      note: None,
+      repeat: 1,
+      unpause: false,
+      once_per: Some("1day".parse().unwrap()),
+    };
+    let (log_out, _) = exec_command(&do_task, log_in);
+    assert_eq!(log_out.matches("complete_todo1").count(), 1);
+    let (log_out, output) = exec_command(&do_task, log_out);
+    assert_eq!(output, "Task 1 was already done within 1day, skipping\n");
+    assert_eq!(log_out.matches("complete_todo1").count(), 1);
+  }
+
+  #[test]
+  fn skipped_completion_runs_no_hook() {
+    fn handle<S: Store, A: Allesatt<Store = S>>(cmd: &Cmd, config: &Config, app: &mut A) {
+      handle_command::<S, A, _>(cmd, config, None, app).unwrap();
+    }
+    let dir = std::env::temp_dir();
+    let path = |name: &str| {
+      let path = dir.join(format!("allesatt-once-per-{name}-{}", std::process::id()));
+      path.to_str().unwrap().to_owned()
+    };
+    let (journal, hook_out) = (path("journal"), path("hook"));
+    let config = Config::parse(&format!(
+      "journal = {journal:?}\n[hooks]\ndo = \"echo {{id}} >> {hook_out}\"\n"
+    ))
+    .unwrap();
+    let log_in = "create_task2: [\"Feed cat\", \"12h\", 1, 1]\n";
+    let mut app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let do_task = Cmd::Do {
+      ids: vec![TaskRef::from_str("1").unwrap()],
+      snap_to: None,
+      yes: true,
+      note: Some("Wet food".into()),
+      repeat: 1,
+      unpause: false,
+      once_per: Some("1day".parse().unwrap()),
+    };
+    for _ in 0..2 {
+      handle(&do_task, &config, &mut app);
+    }
+    let lines = |path: &str| std::fs::read_to_string(path).unwrap().lines().count();
+    assert_eq!((lines(&journal), lines(&hook_out)), (1, 1));
+    std::fs::remove_file(journal).unwrap();
+    std::fs::remove_file(hook_out).unwrap();
+  }
+
+  #[test]
+  fn open_keeps_output_file() {
+    let path = std::env::temp_dir().join(format!("allesatt-open-{}", std::process::id()));
+    std::fs::write(&path, "Keep me\n").unwrap();
+    let log_in = "create_task2: [\"Feed cat\", \"12h\", 1, 1]\n";
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let open = Cmd::Open {
+      id: TaskRef::from_str("1").unwrap(),
+    };
+    assert!(handle_command(&open, &Config::default(), path.to_str(), app).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "Keep me\n");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn rounds_intervals() {
+    let hours = std::time::Duration::from_hours;
+    assert_eq!(
+      round_interval(std::time::Duration::from_mins(20)),
+      "~20 minutes"
+    );
+    assert_eq!(
+      round_interval(std::time::Duration::from_secs(10)),
+      "~1 minute"
+    );
+    assert_eq!(round_interval(hours(5)), "~5 hours");
+    assert_eq!(round_interval(hours(24 + 4)), "~1 day");
+    assert_eq!(round_interval(hours(10 * 24 + 3)), "~10 days");
+    assert_eq!(round_interval(hours(27 * 24)), "~4 weeks");
+    assert_eq!(round_interval(hours(31 * 24 + 4)), "~1 month");
+    assert_eq!(round_interval(hours(200 * 24)), "~7 months");
+    assert_eq!(round_interval(hours(800 * 24)), "~2 years");
+  }
+
+  #[test]
+  fn lists_status_column() {
+    let log_in = r#"create_task2: ["Mow lawn", "7days", 1, 1]
+create_task2: ["Sweep", "7days", 2, 2]
+create_task2: ["Descale kettle", "30days", 3, 3]
+create_task2: ["Dust", "7days", 4, 4]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-03T08:00:00.0"]
+complete_todo1: [3, "2024-01-05T10:00:00.0"]
+complete_todo1: [4, "2024-01-02T10:00:00.0"]
+pause_task1: [4]
+"#;
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        as_of: Some(date!(2024 - 01 - 10).midnight().assume_utc() + Duration::hours(12)),
+        status_col: true,
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert_eq!(
+      output,
+      "! 1 2024-01-08 Mow lawn
+· 2 2024-01-10 Sweep
+  3 2024-02-04 Descale kettle
+
+Paused tasks:
+P 4 Dust
+"
+    );
+  }
+
+  #[test]
+  fn porcelain_format() {
+    let log_in = r#"create_task2: ["Mow lawn", "7days", 1, 1]
+create_task2: ["Descale kettle", "30days", 2, 2]
+create_task2: ["Sweep", "7days", 3, 3]
+create_task2: ["Dust", "7days", 4, 4]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-05T10:00:00.0"]
+complete_todo1: [3, "2024-01-03T08:00:00.0"]
+complete_todo1: [4, "2024-01-02T10:00:00.0"]
+complete_todo1: [5, "2024-01-09T10:00:00.0"]
+set_task_blocked1: [3, "No broom"]
+pause_task1: [4]
+"#;
+    let mut list = Cmd::List(ListArgs {
+      all: true,
+      as_of: Some(date!(2024 - 01 - 20).midnight().assume_utc()),
+      grouped: true,
+      ..ListArgs::default()
+    });
+    apply_list_opts(&mut list, None, true, true).unwrap();
+    let (_, output) = exec_command(list, log_in);
+    assert_eq!(
+      output,
+      "1 2024-01-17T10:00:00Z overdue Mow lawn
+2 2024-02-04T10:00:00Z open Descale kettle
+3 2024-01-10T08:00:00Z blocked Sweep
+4 - paused Dust
+"
+    );
+
+    let mut done = Cmd::Done {
+      id: None,
+      json: false,
+      since: Some(date!(2024 - 01 - 09)),
+      until: None,
+      porcelain: false,
+    };
+    apply_list_opts(&mut done, None, true, true).unwrap();
+    let (_, output) = exec_command(done, log_in);
+    assert_eq!(output, "1 2024-01-08T10:00:00Z done Mow lawn\n");
+    assert!(apply_list_opts(&mut Cmd::Digest, None, true, true).is_err());
+  }
+
+  #[test]
+  fn later_and_unlater() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+"#;
+    let later = || Cmd::Later {
+      ids: vec![TaskRef::from_str("1").unwrap()],
+    };
+    let unlater = || Cmd::Unlater {
+      id: TaskRef::from_str("1").unwrap(),
+    };
+    let (log_out, first_later) = exec_command(later(), log_in);
+    let (log_out, _) = exec_command(later(), log_out);
+    let (log_out, output) = exec_command(unlater(), log_out);
+    assert!(log_out.ends_with("todo_later1: [2]\nundo_todo_later1: [2]\n"));
+    assert_eq!(output, first_later);
+    let (log_out, output) = exec_command(unlater(), log_out);
+    assert_eq!(output, "1 2024-01-08 Sweep\n");
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), &*log_out);
+    assert_eq!(output, "1 2024-01-08 Sweep\n");
+
+    let result = handle_command_impl(
+      &unlater(),
+      try_new_engine(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log_out.as_bytes(), Vec::new()),
+      )
+      .unwrap(),
+      &mut Vec::new(),
+    );
+    assert_eq!(result.unwrap_err().to_string(), "Todo 2 wasn't deferred");
+  }
+
+  #[test]
+  fn adds_batch() {
+    let mut log_out = Vec::new();
+    let mut output = Vec::new();
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(&b""[..], &mut log_out),
+    )
+    .unwrap();
+    let source =
+      "# Kitchen\n7days  Sweep floor\n\n2/week Water plants\nDescale kettle\n0days Nothing\n";
+    let result = add_batch(app, &mut output, source.as_bytes());
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "1 of 4 tasks failed: line 6: Interval must be positive"
+    );
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      format!(
+        "1 {today} Sweep floor\n2 {today} Water plants\n3 {today} Descale kettle\n",
+        today = today_plus(0)
+      )
+    );
+    let log_out = String::from_utf8(log_out).unwrap();
+    assert!(log_out.contains(r#"["Sweep floor", "7days", "#));
+    assert!(log_out.contains(r#"["Water plants", "3days 12h", "#));
+    assert!(log_out.contains(r#"["Descale kettle", "30days", "#));
+  }
+
+  #[test]
+  fn missing_and_paused_tasks() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+pause_task1: [1]
+"#;
+    let error = |cmd: Cmd| {
+      handle_command_impl(
+        &cmd,
+        try_new_engine(
+          MemStore::new(),
+          ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
+        )
+        .unwrap(),
+        &mut Vec::new(),
+      )
+      .unwrap_err()
+      .to_string()
+    };
+    let ids = |id| vec![TaskRef::from_str(id).unwrap()];
+    assert_eq!(
+      error(Cmd::Later { ids: ids("1") }),
+      "Task is paused, unpause it first"
+    );
+    assert_eq!(error(Cmd::Later { ids: ids("2") }), "Task not found");
+    assert_eq!(
+      error(Cmd::Pause { ids: ids("1") }),
+      "Task is paused, unpause it first"
+    );
+    assert_eq!(error(Cmd::Pause { ids: ids("2") }), "Task not found");
+    let do_task = |id| Cmd::Do {
+      ids: ids(id),
+      snap_to: None,
+      yes: false,
+      note: None,
+      repeat: 1,
+      unpause: false,
+      once_per: None,
+    };
+    assert_eq!(
+      error(do_task("1")),
+      "Task is paused, use --unpause to complete it anyway"
+    );
+    assert_eq!(error(do_task("2")), "Task not found");
+  }
+
+  #[test]
+  fn limited_completions() {
+    let (log_out, output) = exec_command(
+      Cmd::Add(AddArgs {
+        times: Some(2),
+        every: Some("1day".parse().unwrap()),
+        description: "Pills".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    assert_eq!(output, format!("1 {} Pills (2 left)\n", today_plus(0)));
+    assert!(log_out.ends_with("set_task_max_completions1: [1, 2]\n"));
+
+    let (log_out, output) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      log_out,
+    );
+    assert_eq!(output, format!("1 {} Pills (1 left)\n", today_plus(1)));
+
+    let (log_out, output) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      log_out,
+    );
+    assert_eq!(output, "1 Pills\n");
+
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      log_out,
+    );
+    assert_eq!(output, "Paused tasks:\n1 Pills\n");
+  }
+
+  #[test]
+  fn list_todos_grouped() {
+    let long_ago = log_date_ago(40);
+    let log_in = format!(
+      r#"create_task1: ["Overdue", {{"secs":2592000,"nanos":0}}, 1, 1]
+complete_todo1: [1, "{long_ago}"]
+create_task1: ["Today", {{"secs":2592000,"nanos":0}}, 2, 3]
+create_task1: ["Later", {{"secs":2592000,"nanos":0}}, 3, 4]
+complete_todo1: [4, "{long_ago}"]
+postpone_todo1: [5, {{"secs":2592000,"nanos":0}}]
+create_task1: ["Paused", {{"secs":2592000,"nanos":0}}, 4, 6]
+pause_task1: [4]
+"#
+    );
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        grouped: true,
+        ..ListArgs::default()
+      }),
+      &*log_in,
+    );
+    assert_eq!(
+      output,
+      format!(
+        "Overdue:\n1 {} Overdue\n\nToday:\n2 {} Today\n\nLater:\n3 {} Later\n\nPaused tasks:\n4 Paused\n",
+        today_plus(-10),
+        today_plus(0),
+        today_plus(30)
+      )
+    );
+  }
+
+  #[test]
+  fn list_todos_by_bucket() {
+    let long_ago = log_date_ago(40);
+    let log_in = format!(
+      r#"create_task2: ["Overdue", "30days", 1, 1]
+complete_todo1: [1, "{long_ago}"]
+create_task2: ["Today", "30days", 2, 3]
+create_task2: ["Later", "30days", 3, 4]
+complete_todo1: [4, "{long_ago}"]
+postpone_todo1: [5, {{"secs":2592000,"nanos":0}}]
+create_task2: ["Paused", "30days", 4, 6]
+pause_task1: [4]
+"#
+    );
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        due_only: true,
+        ..ListArgs::default()
+      }),
+      &*log_in,
+    );
+    assert_eq!(
+      output,
+      format!("1 {} Overdue\n2 {} Today\n", today_plus(-10), today_plus(0))
+    );
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        upcoming_only: true,
+        ..ListArgs::default()
+      }),
+      &*log_in,
+    );
+    assert_eq!(output, format!("3 {} Later\n", today_plus(30)));
+  }
+
+  #[test]
+  fn list_todos_due_soon() {
+    let log_in = r#"create_task2: ["In two days", "30days", 1, 1]
+postpone_todo1: [1, {"secs":172800,"nanos":0}]
+create_task2: ["In five days", "30days", 2, 2]
+postpone_todo1: [2, {"secs":432000,"nanos":0}]
+"#;
+    let list_due = |soon: Option<&str>| {
+      exec_command(
+        Cmd::List(ListArgs {
+          due_only: true,
+          soon: soon.map(|soon| soon.parse().unwrap()),
+          ..ListArgs::default()
+        }),
+        log_in,
+      )
+      .1
+    };
+    assert_eq!(list_due(None), "");
+    assert_eq!(
+      list_due(Some("3days")),
+      format!("1 {} In two days\n", today_plus(2))
+    );
+    assert_eq!(
+      list_due(Some("6days")),
+      format!(
+        "1 {} In two days\n2 {} In five days\n",
+        today_plus(2),
+        today_plus(5)
+      )
+    );
+  }
+
+  #[test]
+  fn lists_by_urgency() {
+    let log_in = r#"create_task2: ["Clean gutters", "30days", 1, 1]
+create_task2: ["Water plants", "2days", 2, 2]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-02-03T10:00:00.0"]
+"#;
+    let list = |by_urgency| {
+      Cmd::List(ListArgs {
+        as_of: Some(date!(2024 - 02 - 07).midnight().assume_utc() + Duration::hours(12)),
+        by_urgency,
+        ..ListArgs::default()
+      })
+    };
+    let (_, output) = exec_command(list(false), log_in);
+    assert_eq!(
+      output,
+      "1 2024-01-31 Clean gutters\n2 2024-02-05 Water plants\n"
+    );
+    let (_, output) = exec_command(list(true), log_in);
+    assert_eq!(
+      output,
+      "2 2024-02-05 Water plants\n1 2024-01-31 Clean gutters\n"
+    );
+  }
+
+  #[test]
+  fn list_most_overdue_or_sample() {
+    let log_in = (1..=7).fold(String::new(), |log, i| {
+      log + &format!("create_task2: [\"Task {i}\", \"1day\", {i}, {i}]\n")
+    }) + &(1..=7).fold(String::new(), |log, i| {
+      log + &format!("complete_todo1: [{i}, \"2024-01-0{i}T00:00:00\"]\n")
+    });
+    let all_lines: Vec<_> = (1..=7)
+      .map(|i| format!("{i} 2024-01-0{} Task {i}", i + 1))
+      .collect();
+    let list = |sample| {
+      exec_command(
+        Cmd::List(ListArgs {
+          sample,
+          ..ListArgs::default()
+        }),
+        log_in.as_ref(),
+      )
+      .1
+    };
+    assert_eq!(list(false), all_lines[..5].join("\n") + "\n(and more)\n");
+    let sampled = list(true);
+    let lines: Vec<_> = sampled.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[5], "(and more)");
+    let positions: Vec<_> = lines[..5]
+      .iter()
+      .map(|line| all_lines.iter().position(|l| l == line).unwrap())
+      .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+  }
+
+  #[test]
+  fn task_with_link() {
+    let (log_out, output) = exec_command(
+      Cmd::Add(AddArgs {
+        url: Some("https://example.org/list".into()),
+        every: Some("7days".parse().unwrap()),
+        description: "Shopping".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    assert_eq!(output, format!("1 {} Shopping (link)\n", today_plus(0)));
+    assert!(log_out.ends_with("set_task_url1: [1, \"https://example.org/list\"]\n"));
+  }
+
+  #[test]
+  fn list_tasks_after() {
+    let log_out = (1..=12).fold(String::new(), |log_out, i| {
+      exec_command(
+        Cmd::Add(AddArgs {
+          every: Some("7days".parse().unwrap()),
+          description: format!("Task {i}"),
+          ..AddArgs::default()
+        }),
+        log_out,
+      )
+      .0
+    });
+    let (log_out, _) = exec_command(
+      Cmd::Pause {
+        ids: vec![TaskRef::from_str("10").unwrap()],
+      },
+      log_out,
+    );
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        after: Some(TaskId::from_str("8").unwrap()),
+        limit: Some(3),
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
+    assert_eq!(
+      output,
+      format!(" 9 {0} Task 9\n10 Task 10\n11 {0} Task 11\n", today_plus(0))
+    );
+  }
+
+  #[test]
+  fn aliases() {
+    let log_out =
+      ["Take out trash", "Water plants"]
+        .into_iter()
+        .fold(String::new(), |log_out, description| {
+          exec_command(
+            Cmd::Add(AddArgs {
+              every: Some("7days".parse().unwrap()),
+              description: description.into(),
+              ..AddArgs::default()
+            }),
+            log_out,
+          )
+          .0
+        });
+    let (log_out, output) = exec_command(
+      Cmd::Alias {
+        id: TaskRef::from_str("1").unwrap(),
+        name: "trash".into(),
+      },
+      log_out,
+    );
+    assert_eq!(output, "1 @trash Take out trash\n");
+    assert!(log_out.ends_with("add_task_alias1: [1, \"trash\"]\n"));
+
+    let (_, output) = exec_command(
+      Cmd::Do {
+        ids: vec![TaskRef::from_str("@trash").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
+      },
+      &*log_out,
+    );
+    assert_eq!(output, format!("1 {} Take out trash\n", today_plus(7)));
 
-fn do_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  mut app: B,
-  output: &mut W,
-  id: &TaskId,
-) -> Result<(), Box<dyn Error>> {
-  let todo_id = app
-    .borrow()
-    .get_store()
-    .find_open_todo(id)
-    .ok_or("Task not found")?
-    .id
-    .clone();
-  app
-    .borrow_mut()
-    .complete_todo(&todo_id, TodoCompleted::new(OffsetDateTime::now_utc()))?;
-  let store = app.borrow().get_store();
-  let todo = store.find_open_todo(id).ok_or("Task not found")?;
-  print_todo(store, output, id, &todo.id)
-}
+    let mut app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_out.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    assert!(app
+      .add_task_alias(&TaskId::from_str("2").unwrap(), "trash".into())
+      .is_err());
+    assert!(TaskRef::from_str("@").is_err());
+  }
 
-fn pause_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  mut app: B,
-  output: &mut W,
-  id: &TaskId,
-) -> Result<(), Box<dyn Error>> {
-  app.borrow_mut().pause_task(id)?;
-  let store = app.borrow().get_store();
-  write_paused_task(output, 0, store.get_task(id).unwrap())?;
-  Ok(())
-}
+  #[test]
+  fn recompute_due() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+postpone_todo1: [2, {"secs":86400,"nanos":0}]
+create_task2: ["New", "7days", 2, 3]
+create_task2: ["Paused", "7days", 3, 4]
+complete_todo1: [4, "2024-01-01T10:00:00.0"]
+pause_task1: [3]
+"#;
+    let tomorrow = (OffsetDateTime::now_utc() + Duration::DAY)
+      .format(&DAY_FORMAT)
+      .unwrap();
+    let (log_out, output) = exec_command(Cmd::Recompute { dry_run: true }, log_in);
+    assert_eq!(log_out, log_in);
+    assert_eq!(
+      output,
+      format!("1 Sweep: {tomorrow} -> 2024-01-08\nWould change 1 due dates\n")
+    );
+    let (log_out, output) = exec_command(Cmd::Recompute { dry_run: false }, log_in);
+    assert!(output.ends_with("Changed 1 due dates\n"));
+    assert_eq!(
+      log_out,
+      String::from(log_in) + "set_todo_due1: [2, \"2024-01-08T10:00:00.0\"]\n"
+    );
+    let (_, output) = exec_command(Cmd::Recompute { dry_run: false }, log_out);
+    assert_eq!(output, "Changed 0 due dates\n");
+  }
 
-fn unpause_task<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  mut app: B,
-  output: &mut W,
-  id: &TaskId,
-) -> Result<(), Box<dyn Error>> {
-  let todo_id = app.borrow_mut().unpause_task(id)?;
-  let store = app.borrow().get_store();
-  print_todo(store, output, id, &todo_id)
-}
+  #[test]
+  fn swap_tasks() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+create_task2: ["Water plants", "3days", 2, 3]
+add_task_alias1: [2, "plants"]
+"#;
+    let (log_out, output) = exec_command(
+      Cmd::Swap {
+        a: TaskRef::from_str("1").unwrap(),
+        b: TaskRef::from_str("@plants").unwrap(),
+      },
+      log_in,
+    );
+    assert_eq!(output, "1 Water plants\n2 Sweep\n");
+    assert_eq!(log_out, String::from(log_in) + "swap_tasks1: [1, 2]\n");
 
-fn task_later<S: Store, A: Allesatt<Store = S>, B: BorrowMut<A> + Borrow<A>, W: Write>(
-  mut app: B,
-  output: &mut W,
-  id: &TaskId,
-) -> Result<(), Box<dyn Error>> {
-  let todo = app
-    .borrow()
-    .get_store()
-    .find_open_todo(id)
-    .ok_or("Task not found")?
-    .id
-    .clone();
-  app.borrow_mut().todo_later(&todo)?;
-  let store = app.borrow().get_store();
-  print_todo(store, output, id, &todo)
-}
+    let log_in = log_out + "complete_todo1: [2, \"2024-01-08T10:00:00.0\"]\n";
+    let (_, output) = exec_command(
+      Cmd::Done {
+        id: Some(TaskRef::from_str("2").unwrap()),
+        json: false,
+        since: None,
+        until: None,
+        porcelain: false,
+      },
+      &*log_in,
+    );
+    assert!(output.starts_with("2 2024-01-01 Sweep\n2 2024-01-08 Sweep\n"));
+    let (_, output) = exec_command(
+      Cmd::Info {
+        id: TaskRef::from_str("@plants").unwrap(),
+        exact: false,
+      },
+      &*log_in,
+    );
+    assert!(output.contains("Water plants"));
+    assert!(output.contains("interval:    3days"));
+  }
 
-#[cfg(test)]
-mod tests {
-  use super::{handle_command_impl, Cmd, DAY_FORMAT};
-  use crate::engine::{try_new as try_new_engine, MemStore, ReadWriteLogger, TaskId};
-  use regex::{escape, Regex};
-  use std::borrow::Borrow;
-  use std::fmt::Display;
-  use std::str::FromStr;
-  use time::{Duration, OffsetDateTime};
+  #[test]
+  fn list_ndjson() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+create_task2: ["Paused", "7days", 2, 3]
+pause_task1: [2]
+"#;
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ndjson: true,
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert_eq!(
+      output,
+      r#"{"due":"2024-01-08","remaining":null,"task":"1","title":"Sweep"}
+{"paused":true,"task":"2","title":"Paused"}
+"#
+    );
+  }
 
-  fn today_plus(days: i64) -> impl Display {
-    (OffsetDateTime::now_utc() + Duration::days(days))
-      .format(&DAY_FORMAT)
-      .unwrap()
+  #[test]
+  fn check_fix() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+unpause_task1: [1]
+"#;
+    let (log_out, output) = exec_command(Cmd::Check { fix: true }, log_in);
+    assert_eq!(output, "Discarded todo 3 of task 1\nNo problems found\n");
+    assert_eq!(log_out, String::from(log_in) + "discard_todo1: [3]\n");
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
+    assert_eq!(output, "1 2024-01-08 Sweep\n");
+    let (_, output) = exec_command(Cmd::Check { fix: false }, log_out);
+    assert_eq!(output, "No problems found\n");
   }
 
-  fn exec_command(cmd: impl Borrow<Cmd>, log_in: impl Borrow<str>) -> (String, String) {
-    let log_in = log_in.borrow();
-    let mut output = Vec::new();
-    let mut log_out: Vec<u8> = Vec::new();
-    handle_command_impl(
-      cmd.borrow(),
-      try_new_engine(
-        MemStore::new(),
-        ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), &mut log_out),
-      )
-      .unwrap(),
-      &mut output,
+  #[test]
+  fn counts_overdue() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+create_task2: ["Mop", "7days", 2, 3]
+complete_todo1: [3, "2024-01-05T10:00:00.0"]
+create_task2: ["Dust", "7days", 3, 5]
+"#;
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
     )
     .unwrap();
-    let log_out = std::str::from_utf8(&log_out).unwrap();
-    (
-      String::with_capacity(log_in.len() + log_out.len()) + log_in + log_out,
-      String::from_utf8(output).unwrap(),
+    assert_eq!(
+      count_overdue(app.get_store(), app.calendar(), date!(2024 - 01 - 10)),
+      1
+    );
+    assert_eq!(
+      count_overdue(app.get_store(), app.calendar(), date!(2024 - 01 - 13)),
+      2
+    );
+  }
+
+  #[test]
+  fn snaps_to_local_time() {
+    let berlin = time_tz::timezones::db::europe::BERLIN;
+    let now = datetime!(2024-07-01 21:30 UTC);
+    assert_eq!(
+      snap_to_time(now, time!(23:00), Some(berlin)).unwrap(),
+      datetime!(2024-07-01 21:00 UTC)
+    );
+    assert_eq!(
+      snap_to_time(now, time!(8:00), None).unwrap(),
+      datetime!(2024-07-01 8:00 UTC)
+    );
+    assert!(snap_to_time(now, time!(23:00), None).is_err());
+    assert!(snap_to_time(now, time!(23:45), Some(berlin)).is_err());
+  }
+
+  #[test]
+  fn buckets_by_local_date() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-02T23:30:00.0"]
+"#;
+    let app = try_new_engine(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log_in.as_bytes(), Vec::new()),
     )
+    .unwrap();
+    // Due at 23:30 UTC on the 9th, which is already the 10th in Berlin
+    let berlin = Calendar {
+      timezone: Some(time_tz::timezones::db::europe::BERLIN),
+      ..Calendar::default()
+    };
+    let today = date!(2024 - 01 - 10);
+    assert_eq!(
+      count_overdue(app.get_store(), &Calendar::default(), today),
+      1
+    );
+    assert_eq!(count_overdue(app.get_store(), &berlin, today), 0);
   }
 
   #[test]
-  fn test_handle_command_impl() {
-    let (log_out, output) = exec_command(Cmd::List { all: true }, "");
-    assert_eq!(output, "");
-    assert_eq!(log_out, "");
+  fn list_with_time() {
+    let log_in = r#"create_task2: ["Feed cat", "4h", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+create_task2: ["Sweep", "7days", 2, 3]
+complete_todo1: [3, "2024-01-01T10:00:00.0"]
+"#;
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert_eq!(output, "1 2024-01-01 14:00 Feed cat\n2 2024-01-08 Sweep\n");
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        with_time: true,
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert!(output.ends_with("2 2024-01-08 10:00 Sweep\n"));
+  }
+
+  #[test]
+  fn done_json() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-09T10:00:00.0", "Late"]
+"#;
+    let (_, output) = exec_command(
+      Cmd::Done {
+        id: None,
+        json: true,
+        since: None,
+        until: None,
+        porcelain: false,
+      },
+      log_in,
+    );
+    let rows: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(
+      rows[1],
+      serde_json::json!({
+        "task": "1",
+        "title": "Sweep",
+        "completed": "2024-01-09T10:00:00Z",
+        "due": "2024-01-08T10:00:00Z",
+        "note": "Late",
+      })
+    );
+    assert_eq!(rows[0]["note"], serde_json::Value::Null);
+  }
+
+  #[test]
+  fn done_between_days() {
+    let log_in = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-08T00:00:00.0"]
+complete_todo1: [3, "2024-01-16T23:59:00.0"]
+"#;
+    let done = |since, until| {
+      exec_command(
+        Cmd::Done {
+          id: None,
+          json: false,
+          since: Some(since),
+          until: Some(until),
+          porcelain: false,
+        },
+        log_in,
+      )
+      .1
+    };
+    assert_eq!(
+      done(date!(2024 - 01 - 08), date!(2024 - 01 - 16)),
+      "1 2024-01-08 Sweep\n1 2024-01-16 Sweep\n"
+    );
+    assert_eq!(
+      done(date!(2024 - 01 - 02), date!(2024 - 01 - 15)),
+      "1 2024-01-08 Sweep\n"
+    );
+    assert_eq!(done(date!(2024 - 01 - 09), date!(2024 - 01 - 15)), "");
+  }
 
+  #[test]
+  fn progress() {
+    let log_in = r#"create_task2: ["Deep clean kitchen", "30days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+"#;
     let (log_out, output) = exec_command(
-      Cmd::Add {
-        every: "30days".parse().unwrap(),
-        description: "task".into(),
+      Cmd::Progress {
+        id: TaskRef::from_str("1").unwrap(),
+        done: 3,
+        total: 5,
+      },
+      log_in,
+    );
+    assert_eq!(output, "1 2024-01-31 Deep clean kitchen (3/5)\n");
+    assert!(log_out.ends_with("set_todo_progress1: [2, 3, 5]\n"));
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
+    assert_eq!(output, "1 2024-01-31 Deep clean kitchen (3/5)\n");
+
+    let log_in = log_out + "complete_todo1: [2, \"2024-01-31T10:00:00.0\"]\n";
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert_eq!(output, "1 2024-03-01 Deep clean kitchen\n");
+  }
+
+  #[test]
+  fn replace_titles() {
+    let log_out = ["Buy BrandA soap", "Clean", "BrandA BrandA"]
+      .into_iter()
+      .fold(String::new(), |log_out, description| {
+        exec_command(
+          Cmd::Add(AddArgs {
+            every: Some("30days".parse().unwrap()),
+            description: description.into(),
+            ..AddArgs::default()
+          }),
+          log_out,
+        )
+        .0
+      });
+    let (new_log_out, output) = exec_command(
+      Cmd::ReplaceTitles {
+        pattern: "BrandA".into(),
+        replacement: "$1".into(),
+        regex: false,
+        dry_run: true,
+      },
+      &*log_out,
+    );
+    assert_eq!(
+      output,
+      "1 Buy BrandA soap -> Buy $1 soap\n3 BrandA BrandA -> $1 $1\nWould rename 2 tasks\n"
+    );
+    assert_eq!(new_log_out, log_out);
+
+    let (log_out, output) = exec_command(
+      Cmd::ReplaceTitles {
+        pattern: "Brand(A)".into(),
+        replacement: "Brand${1}A".into(),
+        regex: true,
+        dry_run: false,
       },
       log_out,
     );
-    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
     assert_eq!(
+      output,
+      "1 Buy BrandA soap -> Buy BrandAA soap\n3 BrandA BrandA -> BrandAA BrandAA\nRenamed 2 tasks\n"
+    );
+    assert!(log_out.ends_with(
+      "rename_task2: [1, \"Buy BrandA soap\", \"Buy BrandAA soap\"]\nrename_task2: [3, \"BrandA BrandA\", \"BrandAA BrandAA\"]\n"
+    ));
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
       log_out,
-      "create_task1: [\"task\", {\"secs\":2592000,\"nanos\":0}, 1, 1]\n"
+    );
+    assert_eq!(
+      output,
+      format!(
+        "1 {0} Buy BrandAA soap\n2 {0} Clean\n3 {0} BrandAA BrandAA\n",
+        today_plus(0)
+      )
+    );
+  }
+
+  #[test]
+  fn lists_task_early_with_lead_time() {
+    let (log_out, _) = exec_command(
+      Cmd::Add(AddArgs {
+        lead: Some("7days".parse().unwrap()),
+        description: "Replace filter".into(),
+        ..AddArgs::default()
+      }),
+      "",
+    );
+    assert!(log_out.ends_with("set_task_lead_time1: [1, \"7days\"]\n"));
+
+    let log_in = r#"create_task2: ["Replace filter", "30days", 1, 1]
+create_task2: ["Sweep", "30days", 2, 2]
+complete_todo1: [1, "2024-01-01T10:00:00.0"]
+complete_todo1: [2, "2024-01-01T10:00:00.0"]
+set_task_lead_time1: [1, "7days"]
+"#;
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        due_only: true,
+        as_of: Some(date!(2024 - 01 - 25).midnight().assume_utc()),
+        ..ListArgs::default()
+      }),
+      log_in,
+    );
+    assert_eq!(output, "1 2024-01-31 Replace filter\n");
+  }
+
+  #[test]
+  fn list_todos_with_budget() {
+    let log_out = (1..=3).fold(String::new(), |log_out, i| {
+      exec_command(
+        Cmd::Add(AddArgs {
+          effort: Some("1h".parse().unwrap()),
+          every: Some("30days".parse().unwrap()),
+          description: format!("Task {i}"),
+          ..AddArgs::default()
+        }),
+        log_out,
+      )
+      .0
+    });
+    assert!(log_out.contains("set_task_effort1: [3, {\"secs\":3600,\"nanos\":0}]\n"));
+
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        budget: Some("2h 30m".parse().unwrap()),
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
+    assert_eq!(
+      output,
+      format!("1 {0} Task 1\n2 {0} Task 2\n(and more)\n", today_plus(0))
     );
 
     let (log_out, output) = exec_command(
-      Cmd::Do {
-        id: TaskId::from_str("1").unwrap(),
+      Cmd::Effort {
+        id: TaskRef::from_str("2").unwrap(),
+        effort: "0s".parse().unwrap(),
       },
       log_out,
     );
-    assert_eq!(output, format!("1 {} task\n", today_plus(30)));
-    let r = Regex::new(
-      &(escape(
-        r#"create_task1: ["task", {"secs":2592000,"nanos":0}, 1, 1]
-complete_todo1: [1, ""#,
-      ) + &today_plus(0).to_string()
-        + r#"T[0-9]{2}:[0-9]{2}:[0-9]{2}\.[0-9]+"\]
-"#),
-    )
-    .unwrap();
-    assert!(r.is_match(&log_out));
+    assert_eq!(output, "2 takes 0s Task 2\n");
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        budget: Some("2h 30m".parse().unwrap()),
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
+    assert_eq!(
+      output,
+      format!("1 {0} Task 1\n2 {0} Task 2\n3 {0} Task 3\n", today_plus(0))
+    );
+  }
 
+  #[test]
+  fn schedule() {
+    let log_out = [("Daily", "1day", None), ("Sweep, mop", "2days", Some(2))]
+      .into_iter()
+      .fold(String::new(), |log_out, (description, every, times)| {
+        exec_command(
+          Cmd::Add(AddArgs {
+            every: Some(every.parse().unwrap()),
+            times,
+            description: description.into(),
+            ..AddArgs::default()
+          }),
+          log_out,
+        )
+        .0
+      });
+    let until = (OffsetDateTime::now_utc() + Duration::days(4)).date();
+    let (_, output) = exec_command(
+      Cmd::Schedule {
+        until,
+        format: ScheduleFormat::Csv,
+      },
+      &*log_out,
+    );
+    assert_eq!(
+      output,
+      format!(
+        "task,title,due\n1,Daily,{0}\n2,\"Sweep, mop\",{0}\n1,Daily,{1}\n1,Daily,{2}\n2,\"Sweep, mop\",{2}\n1,Daily,{3}\n1,Daily,{4}\n",
+        today_plus(0),
+        today_plus(1),
+        today_plus(2),
+        today_plus(3),
+        today_plus(4),
+      )
+    );
+
+    let (_, output) = exec_command(
+      Cmd::Schedule {
+        until: OffsetDateTime::now_utc().date(),
+        format: ScheduleFormat::Json,
+      },
+      &*log_out,
+    );
+    let rows: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows[1]["title"], "Sweep, mop");
+    assert_eq!(rows[1]["due"].to_string(), format!("\"{}\"", today_plus(0)));
+  }
+
+  #[test]
+  fn forecast() {
+    let now = log_date_ago(0);
+    let log_in = format!(
+      r#"create_task2: ["Soon", "3days", 1, 1]
+complete_todo1: [1, "{now}"]
+create_task2: ["Later", "30days", 2, 3]
+complete_todo1: [3, "{now}"]
+create_task2: ["Now", "3days", 3, 5]
+create_task2: ["Paused", "3days", 4, 6]
+pause_task1: [4]
+"#
+    );
     let (log_out, output) = exec_command(
-      Cmd::Later {
-        id: TaskId::from_str("1").unwrap(),
+      Cmd::Forecast {
+        days: 7,
+        verbose: false,
       },
-      log_out,
+      &*log_in,
     );
-    assert_eq!(output, format!("1 {} task\n", today_plus(30 + 6)));
-    let r = Regex::new(&(r.to_string() + "todo_later1: \\[2\\]\n")).unwrap();
-    assert!(r.is_match(&log_out));
+    assert_eq!(log_out, log_in);
+    assert_eq!(output, "2 todos due in 7 days\n");
+    let (_, output) = exec_command(
+      Cmd::Forecast {
+        days: 0,
+        verbose: true,
+      },
+      &*log_in,
+    );
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines[0], "1 todos due in 0 days");
+    assert!(lines[1].starts_with("3 ") && lines[1].ends_with(" Now"));
+    assert_eq!(lines.len(), 2);
+  }
+
+  #[test]
+  fn version() {
+    let (_, output) = exec_command(Cmd::Version { json: true }, "");
+    let info: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    let log_commands = info["log_commands"].as_array().unwrap();
+    assert!(log_commands.contains(&"create_task1".into()));
+    assert!(log_commands.contains(&"todo_later1".into()));
+  }
 
+  #[test]
+  fn defer_all() {
+    let long_ago = log_date_ago(40);
+    let log_in = format!(
+      r#"create_task1: ["Overdue", {{"secs":2592000,"nanos":0}}, 1, 1]
+complete_todo1: [1, "{long_ago}"]
+create_task1: ["Today", {{"secs":2592000,"nanos":0}}, 2, 3]
+"#
+    );
     let (log_out, output) = exec_command(
-      Cmd::Pause {
-        id: TaskId::from_str("1").unwrap(),
+      Cmd::DeferAll {
+        by: "2days".parse().unwrap(),
+        only_overdue: true,
       },
+      &*log_in,
+    );
+    assert_eq!(output, "Deferred 1 todos by 2days\n");
+    assert_eq!(
       log_out,
+      log_in + "postpone_todo1: [2, {\"secs\":172800,\"nanos\":0}]\n"
     );
-    assert_eq!(output, "1 task\n");
-    let r = Regex::new(&(r.to_string() + "pause_task1: \\[1\\]\n")).unwrap();
-    assert!(r.is_match(&log_out));
-
-    let (new_log_out, output) = exec_command(Cmd::List { all: false }, log_out.as_ref());
-    assert_eq!(output, "Paused tasks:\n1 task\n");
-    assert_eq!(new_log_out, log_out);
 
     let (log_out, output) = exec_command(
-      Cmd::Unpause {
-        id: TaskId::from_str("1").unwrap(),
+      Cmd::DeferAll {
+        by: "2days".parse().unwrap(),
+        only_overdue: false,
       },
       log_out,
     );
-    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
-    let r = Regex::new(&(r.to_string() + "unpause_task1: \\[1\\]\n")).unwrap();
-    assert!(r.is_match(&log_out));
+    assert_eq!(output, "Deferred 1 todos by 2days\n");
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      log_out,
+    );
+    assert_eq!(
+      output,
+      format!("1 {0} Overdue\n2 {0} Today\n", today_plus(2))
+    );
+  }
 
-    let (new_log_out, output) = exec_command(Cmd::List { all: false }, log_out.as_ref());
-    assert_eq!(output, format!("1 {} task\n", today_plus(0)));
-    assert_eq!(new_log_out, log_out);
+  #[test]
+  fn digest() {
+    let (now, long_ago) = (log_date_ago(0), log_date_ago(40));
+    let log_in = format!(
+      r#"create_task1: ["Overdue", {{"secs":2592000,"nanos":0}}, 1, 1]
+complete_todo1: [1, "{long_ago}"]
+create_task1: ["Today", {{"secs":2592000,"nanos":0}}, 2, 3]
+create_task1: ["Paused", {{"secs":2592000,"nanos":0}}, 3, 4]
+pause_task1: [3]
+create_task1: ["Soon", {{"secs":259200,"nanos":0}}, 4, 5]
+complete_todo1: [5, "{now}"]
+"#
+    );
+    let (log_out, output) = exec_command(Cmd::Digest, &*log_in);
+    assert_eq!(log_out, log_in);
+    assert_eq!(
+      output,
+      format!(
+        "Allesatt digest for {}\n\n1 overdue, 1 due today, 1 due later this week, 1 paused.\n\nOverdue:\n  - Overdue (#1), due 10 days ago\n\nDue today:\n  - Today (#2), due today\n\nDue later this week:\n  - Soon (#4), due in 3 days\n",
+        today_plus(0)
+      )
+    );
   }
 
   #[test]
+  #[allow(clippy::too_many_lines)]
   fn list_todos() {
     let log_out = [
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 1".into(),
-      },
+        ..AddArgs::default()
+      }),
       &Cmd::Do {
-        id: TaskId::from_str("1").unwrap(),
+        ids: vec![TaskRef::from_str("1").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
       },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 2".into(),
-      },
+        ..AddArgs::default()
+      }),
       &Cmd::Do {
-        id: TaskId::from_str("2").unwrap(),
+        ids: vec![TaskRef::from_str("2").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
       },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 3".into(),
-      },
+        ..AddArgs::default()
+      }),
       &Cmd::Do {
-        id: TaskId::from_str("3").unwrap(),
+        ids: vec![TaskRef::from_str("3").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
       },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 4 due".into(),
-      },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+        ..AddArgs::default()
+      }),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 5 due".into(),
-      },
+        ..AddArgs::default()
+      }),
     ]
     .iter()
     .fold(String::new(), |log_out, &cmd| exec_command(cmd, log_out).0);
 
-    let (_, output) = exec_command(Cmd::List { all: false }, &*log_out);
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), &*log_out);
     let r = Regex::new(&format!(
       "^4 {0} Task 4 due\n5 {0} Task 5 due\n1 {1} Task 1\n$",
       today_plus(0),
@@ -443,7 +4722,13 @@ complete_todo1: [1, ""#,
     .unwrap();
     assert!(r.is_match(&output));
 
-    let (_, output) = exec_command(Cmd::List { all: true }, &*log_out);
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
     let r = Regex::new(&format!(
       "^4 {0} Task 4 due\n5 {0} Task 5 due\n1 {1} Task 1\n2 {1} Task 2\n3 {1} Task 3\n$",
       today_plus(0),
@@ -453,25 +4738,27 @@ complete_todo1: [1, ""#,
     assert!(r.is_match(&output));
 
     let log_out = [
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 6 paused".into(),
-      },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+        ..AddArgs::default()
+      }),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 7 paused".into(),
-      },
+        ..AddArgs::default()
+      }),
       &Cmd::Pause {
-        id: TaskId::from_str("7").unwrap(),
+        ids: vec![TaskRef::from_str("7").unwrap()],
       },
       &Cmd::Pause {
-        id: TaskId::from_str("6").unwrap(),
+        ids: vec![TaskRef::from_str("6").unwrap()],
       },
     ]
     .iter()
     .fold(log_out, |log_out, &cmd| exec_command(cmd, log_out).0);
 
-    let (_, output) = exec_command(Cmd::List { all: false }, &*log_out);
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), &*log_out);
     let r = Regex::new(&format!(
       "^4 {0} Task 4 due\n5 {0} Task 5 due\n1 {1} Task 1\n\nPaused tasks:\n6 Task 6 paused\n7 Task 7 paused\n$",
       today_plus(0),
@@ -480,7 +4767,13 @@ complete_todo1: [1, ""#,
     .unwrap();
     assert!(r.is_match(&output));
 
-    let (_, output) = exec_command(Cmd::List { all: true }, &*log_out);
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
     let r = Regex::new(&format!(
       "^4 {0} Task 4 due\n5 {0} Task 5 due\n1 {1} Task 1\n2 {1} Task 2\n3 {1} Task 3\n\nPaused tasks:\n6 Task 6 paused\n7 Task 7 paused\n$",
       today_plus(0),
@@ -490,26 +4783,35 @@ complete_todo1: [1, ""#,
     assert!(r.is_match(&output));
 
     let log_out = [
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 8 due".into(),
-      },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+        ..AddArgs::default()
+      }),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 9 due".into(),
-      },
-      &Cmd::Add {
-        every: "30days".parse().unwrap(),
+        ..AddArgs::default()
+      }),
+      &Cmd::Add(AddArgs {
+        every: Some("30days".parse().unwrap()),
         description: "Task 10".into(),
-      },
+        ..AddArgs::default()
+      }),
       &Cmd::Do {
-        id: TaskId::from_str("10").unwrap(),
+        ids: vec![TaskRef::from_str("10").unwrap()],
+        snap_to: None,
+        yes: false,
+        note: None,
+        repeat: 1,
+        unpause: false,
+        once_per: None,
       },
     ]
     .iter()
     .fold(log_out, |log_out, &cmd| exec_command(cmd, log_out).0);
 
-    let (_, output) = exec_command(Cmd::List { all: false }, &*log_out);
+    let (_, output) = exec_command(Cmd::List(ListArgs::default()), &*log_out);
     let r = Regex::new(&format!(
       "^4 {0} Task 4 due\n5 {0} Task 5 due\n8 {0} Task 8 due\n9 {0} Task 9 due\n\nPaused tasks:\n6 Task 6 paused\n7 Task 7 paused\n$",
       today_plus(0),
@@ -517,7 +4819,13 @@ complete_todo1: [1, ""#,
     .unwrap();
     assert!(r.is_match(&output));
 
-    let (_, output) = exec_command(Cmd::List { all: true }, &*log_out);
+    let (_, output) = exec_command(
+      Cmd::List(ListArgs {
+        all: true,
+        ..ListArgs::default()
+      }),
+      &*log_out,
+    );
     let r = Regex::new(&format!(
       "^ 4 {0} Task 4 due\n 5 {0} Task 5 due\n 8 {0} Task 8 due\n 9 {0} Task 9 due\n 1 {1} Task 1\n 2 {1} Task 2\n 3 {1} Task 3\n10 {1} Task 10\n\nPaused tasks:\n 6 Task 6 paused\n 7 Task 7 paused\n$",
       today_plus(0),
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::engine::{Store, TaskId};
+
+/// A task given on the command line, either by id or by `@alias`
+#[derive(Clone, Debug)]
+pub enum TaskRef {
+  Id(TaskId),
+  Alias(String),
+}
+
+impl FromStr for TaskRef {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.starts_with('@') {
+      return parse_alias(s).map(Self::Alias);
+    }
+    s.parse()
+      .map(Self::Id)
+      .map_err(|e| format!("{e} (use @name to refer to a task by alias)"))
+  }
+}
+
+impl Display for TaskRef {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::Id(id) => write!(f, "{id}"),
+      Self::Alias(alias) => write!(f, "@{alias}"),
+    }
+  }
+}
+
+impl TaskRef {
+  pub fn resolve<S: Store>(&self, store: &S) -> Result<TaskId, Box<dyn Error>> {
+    match self {
+      Self::Id(id) => Ok(id.clone()),
+      Self::Alias(alias) => Ok(
+        store
+          .find_task_by_alias(alias)
+          .ok_or_else(|| format!("No task with alias @{alias}"))?
+          .id
+          .clone(),
+      ),
+    }
+  }
+}
+
+/// Parses an alias, with or without the leading `@`
+pub fn parse_alias(s: &str) -> Result<String, String> {
+  let alias = s.strip_prefix('@').unwrap_or(s);
+  if alias.is_empty() || alias.contains(char::is_whitespace) {
+    return Err(format!("Invalid alias {s:?}"));
+  }
+  Ok(alias.into())
+}
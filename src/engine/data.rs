@@ -4,9 +4,10 @@ use serde_derive::{Deserialize, Serialize};
 use std::fmt::{Display, Error as FmtError, Formatter};
 use std::num::ParseIntError;
 use std::str::FromStr;
+use std::time::Duration;
 use time::format_description::FormatItem;
 use time::macros::format_description;
-use time::{OffsetDateTime, PrimitiveDateTime};
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
 
 const FULL_FORMAT: &[FormatItem<'static>] =
   format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]");
@@ -17,12 +18,46 @@ const OLD_FORMAT2: &[FormatItem<'static>] =
 
 // FIXME TaskId should probably be store-dependent and just move there
 #[derive(Debug, Eq, Hash, PartialEq, Serialize, Deserialize, Clone, Ord, PartialOrd)]
-pub struct TaskId(pub(super) u64);
+pub struct TaskId(u64);
 
-#[derive(Debug, Serialize, Deserialize)]
+impl TaskId {
+  #[must_use]
+  pub const fn new(id: u64) -> Self {
+    Self(id)
+  }
+
+  /// Returns the number the task is referred to by in the log
+  #[must_use]
+  pub const fn get(&self) -> u64 {
+    self.0
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Task {
   pub id: TaskId,
   pub title: String,
+  #[serde(default)]
+  pub effort: Duration,
+  /// How long before it is due the task is already listed as due, e.g. for preparing it
+  #[serde(default)]
+  pub lead_time: Duration,
+  #[serde(default)]
+  pub max_completions: Option<u32>,
+  #[serde(default)]
+  pub url: Option<String>,
+  #[serde(default)]
+  pub aliases: Vec<String>,
+  /// Why the task can't be done currently, if it is blocked
+  #[serde(default)]
+  pub blocked: Option<String>,
+  /// When the task was created, or the earliest date known for it in old logs
+  #[serde(default = "unknown_date")]
+  pub created: TodoDate,
+}
+
+const fn unknown_date() -> TodoDate {
+  OffsetDateTime::UNIX_EPOCH
 }
 
 impl Display for TaskId {
@@ -39,31 +74,66 @@ impl FromStr for TaskId {
 }
 
 #[derive(Debug, Eq, Hash, PartialEq, Serialize, Deserialize, Clone)]
-pub struct TodoId(pub u64);
+pub struct TodoId(u64);
+
+impl TodoId {
+  #[must_use]
+  pub const fn new(id: u64) -> Self {
+    Self(id)
+  }
+
+  /// Returns the number the todo is referred to by in the log
+  #[must_use]
+  pub const fn get(&self) -> u64 {
+    self.0
+  }
+}
+
+impl Display for TodoId {
+  fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), FmtError> {
+    self.0.fmt(formatter)
+  }
+}
+
 pub type TodoDate = OffsetDateTime;
 
 #[derive(Clone, Debug)]
 pub struct TodoCompleted {
   pub date: TodoDate,
+  /// Something worth remembering about this particular completion
+  pub note: Option<String>,
 }
 
 impl TodoCompleted {
   #[must_use]
   pub const fn new(date: TodoDate) -> Self {
-    Self { date }
+    Self { date, note: None }
+  }
+
+  #[must_use]
+  pub fn with_note(mut self, note: Option<String>) -> Self {
+    self.note = note;
+    self
   }
 }
 
-impl serde::Serialize for TodoCompleted {
+/// A date as written to the log
+///
+/// Dates are written in UTC without an offset, so dates with another offset are converted first.
+#[derive(Clone, Copy, Debug)]
+pub struct LogDate(pub TodoDate);
+
+impl serde::Serialize for LogDate {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
     S: Serializer,
   {
-    serializer.serialize_str(&self.date.format(&FULL_FORMAT).expect("Date formatting"))
+    let utc = self.0.to_offset(UtcOffset::UTC);
+    serializer.serialize_str(&utc.format(&FULL_FORMAT).expect("Date formatting"))
   }
 }
 
-impl<'de> serde::Deserialize<'de> for TodoCompleted {
+impl<'de> serde::Deserialize<'de> for LogDate {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
     D: Deserializer<'de>,
@@ -73,24 +143,98 @@ impl<'de> serde::Deserialize<'de> for TodoCompleted {
       .or_else(|_| PrimitiveDateTime::parse(&s, &OLD_FORMAT1))
       .or_else(|_| PrimitiveDateTime::parse(&s, &OLD_FORMAT2))
     {
-      Ok(v) => Ok(Self::new(v.assume_utc())),
+      Ok(v) => Ok(Self(v.assume_utc())),
       Err(e) => Err(D::Error::custom(e.to_string())),
     }
   }
 }
 
+/// A duration as written to the log, in `humantime` format
+#[derive(Clone, Copy, Debug)]
+pub struct LogDuration(pub Duration);
+
+impl serde::Serialize for LogDuration {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&humantime::format_duration(self.0).to_string())
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for LogDuration {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s)
+      .map(Self)
+      .map_err(|e| D::Error::custom(e.to_string()))
+  }
+}
+
+impl serde::Serialize for TodoCompleted {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    LogDate(self.date).serialize(serializer)
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for TodoCompleted {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    LogDate::deserialize(deserializer).map(|LogDate(date)| Self::new(date))
+  }
+}
+
+/// Serializes a completion together with its note, which `TodoCompleted` leaves out to match the
+/// log format
+mod completed_with_note {
+  use super::{LogDate, TodoCompleted};
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+  #[allow(clippy::ref_option)] // Required by `serde(with)`
+  pub fn serialize<S>(completed: &Option<TodoCompleted>, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    completed
+      .as_ref()
+      .map(|completed| (LogDate(completed.date), &completed.note))
+      .serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<TodoCompleted>, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let completed: Option<(LogDate, Option<String>)> = Option::deserialize(deserializer)?;
+    Ok(completed.map(|(LogDate(date), note)| TodoCompleted::new(date).with_note(note)))
+  }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Todo {
   pub id: TodoId,
   pub task: TaskId,
+  #[serde(with = "completed_with_note")]
   pub completed: Option<TodoCompleted>,
   pub due: TodoDate,
+  /// How many of how many steps of the todo are done, if it is done in stages
+  #[serde(default)]
+  pub progress: Option<(u32, u32)>,
 }
 
 #[cfg(test)]
 mod test {
-  use super::TodoCompleted;
-  use serde_json::from_str as from_json;
+  use super::{LogDate, TodoCompleted};
+  use serde_json::{from_str as from_json, to_string as to_json};
+  use time::macros::{datetime, offset};
 
   #[test]
   fn deserialize_completed() {
@@ -98,4 +242,16 @@ mod test {
     let _: TodoCompleted = from_json("\"2019-05-04T09:41:17.942422315\"").unwrap();
     let _: TodoCompleted = from_json("\"2021-03-30T8:04:24.237224778\"").unwrap();
   }
+
+  #[test]
+  fn serializes_dates_in_utc() {
+    let utc = datetime!(2024-01-01 23:30 UTC);
+    for offset in [offset!(UTC), offset!(+2), offset!(-5:30)] {
+      let date = utc.to_offset(offset);
+      let json = to_json(&TodoCompleted::new(date)).unwrap();
+      assert_eq!(json, "\"2024-01-01T23:30:00.0\"");
+      let LogDate(read) = from_json(&json).unwrap();
+      assert_eq!(read, date);
+    }
+  }
 }
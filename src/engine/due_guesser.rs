@@ -1,17 +1,41 @@
 use super::{Store, TaskId, TodoCompleted, TodoDate, TodoId};
-use std::collections::HashMap;
-use std::convert::TryInto;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
 use std::time::Duration as StdDuration;
-use time::Duration;
-use time::OffsetDateTime;
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime};
+use time_tz::{OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, Tz};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 enum DueIn {
   Calculated(Duration, u32),
   Fixed(Duration),
+  Learned(Duration),
 }
 
 const DEFAULT_PERIOD: Duration = Duration::days(30);
+/// Most intervals between completions the interval of a task is averaged over
+pub(super) const MAX_SAMPLES: u32 = 10;
+/// Longest interval a task can be due in, so that due dates stay representable
+const MAX_PERIOD: Duration = Duration::days(100 * 365);
+
+/// How often a task is due, known to be positive and not absurdly large
+#[derive(Clone, Copy, Debug)]
+pub struct Interval(Duration);
+
+impl TryFrom<StdDuration> for Interval {
+  type Error = Box<dyn Error>;
+
+  fn try_from(interval: StdDuration) -> Result<Self, Self::Error> {
+    if interval.is_zero() {
+      return Err("Interval must be positive".into());
+    }
+    match Duration::try_from(interval) {
+      Ok(interval) if interval <= MAX_PERIOD => Ok(Self(interval)),
+      _ => Err("Interval must be at most 100 years".into()),
+    }
+  }
+}
 
 impl DueIn {
   const fn new(duration: Duration) -> Self {
@@ -20,47 +44,208 @@ impl DueIn {
   fn get(v: Option<Self>) -> Duration {
     match v {
       Some(Self::Calculated(sum, count)) => sum / count,
-      Some(Self::Fixed(v)) => v,
+      Some(Self::Fixed(v) | Self::Learned(v)) => v,
       _ => DEFAULT_PERIOD,
     }
   }
-  fn add(v: Option<Self>, duration: Duration) -> Option<Self> {
-    Some(if let Some(Self::Calculated(sum, count)) = v {
-      let new_count = 10.min(count + 1);
-      Self::Calculated(duration + (sum / count) * (new_count - 1), new_count)
-    } else {
-      Self::Calculated(duration, 1)
-    })
+  fn add(v: Option<Self>, duration: Duration) -> Self {
+    match v {
+      Some(Self::Calculated(sum, count)) => {
+        let new_count = MAX_SAMPLES.min(count + 1);
+        Self::Calculated(duration + (sum / count) * (new_count - 1), new_count)
+      }
+      Some(learned @ Self::Learned(_)) => learned,
+      _ => Self::Calculated(duration, 1),
+    }
+  }
+}
+
+/// Running mean and variance of the intervals between completions
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+struct IntervalStats {
+  count: u32,
+  mean: f64,
+  m2: f64,
+}
+
+impl IntervalStats {
+  fn add(&mut self, interval: Duration) {
+    let seconds = interval.as_seconds_f64();
+    self.count += 1;
+    let delta = seconds - self.mean;
+    self.mean += delta / f64::from(self.count);
+    self.m2 += delta * (seconds - self.mean);
   }
 }
 
-#[derive(Clone, Debug)]
+/// How regularly a task has been done
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskStats {
+  /// Number of intervals between completions
+  pub intervals: u32,
+  pub mean_interval: Duration,
+  /// Standard deviation of the intervals divided by their mean, if there are at least two
+  pub variation: Option<f64>,
+  /// The interval the task is currently due in
+  pub interval: Duration,
+  pub interval_kind: IntervalKind,
+  /// Task whose completions the interval also counts from, see `DueGuesser::set_after`
+  pub after: Option<TaskId>,
+  /// Whether the interval counts from due dates, see `DueGuesser::set_from_due`
+  pub from_due: bool,
+}
+
+/// Where the interval a task is due in comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntervalKind {
+  /// Given when creating the task and not adapted yet
+  Fixed,
+  /// No longer adapted since `learn_task`
+  Learned,
+  /// Averaged over this many of the latest completions
+  Calculated(u32),
+  /// Due every year on the same day
+  Yearly,
+  /// Neither given nor adapted yet
+  Default,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct DueInfo {
   due_in: Option<DueIn>,
   last_completed: Option<TodoDate>,
+  yearly: Option<TodoDate>,
+  #[serde(default)]
+  after: Option<TaskId>,
+  /// Count the interval from when the last completed todo was due rather than when it was done
+  #[serde(default)]
+  from_due: bool,
+  /// When the last completed todo was due, or the date it was touched at
+  #[serde(default)]
+  last_due: Option<TodoDate>,
+  intervals: IntervalStats,
+}
+
+/// Returns the date in the given year with the same month, day and time as `anchor`
+///
+/// February 29 becomes February 28 in non-leap years.
+fn anniversary(anchor: TodoDate, year: i32) -> TodoDate {
+  let day = anchor.day().min(anchor.month().length(year));
+  anchor.replace_date(Date::from_calendar_date(year, anchor.month(), day).expect("Valid date"))
+}
+
+/// Returns the anniversary of `anchor` closest to a year after `base`
+fn next_anniversary(anchor: TodoDate, base: TodoDate) -> TodoDate {
+  let target = base + Duration::days(365);
+  [target.year() - 1, target.year(), target.year() + 1]
+    .into_iter()
+    .map(|year| anniversary(anchor, year))
+    .min_by_key(|date| (*date - target).abs())
+    .expect("Non-empty array")
+}
+
+/// Adds `interval` to `date`, keeping the local time of day in `timezone` for whole days
+///
+/// Local times skipped by a DST change are moved forward by the length of the gap.
+fn add_interval(date: TodoDate, interval: Duration, timezone: Option<&Tz>) -> TodoDate {
+  let Some(timezone) = timezone
+    .filter(|_| interval.whole_seconds() % 86400 == 0 && interval.subsec_nanoseconds() == 0)
+  else {
+    return date + interval;
+  };
+  let local = date.to_timezone(timezone);
+  let target = PrimitiveDateTime::new(local.date() + interval, local.time());
+  match target.assume_timezone(timezone) {
+    OffsetResult::Some(date) | OffsetResult::Ambiguous(date, _) => date,
+    OffsetResult::None => target.assume_offset(local.offset()).to_timezone(timezone),
+  }
+}
+
+/// Local settings used when computing due dates
+#[derive(Clone, Debug, Default)]
+pub struct Calendar {
+  /// Timezone to keep the local time of day in when adding whole days
+  pub timezone: Option<&'static Tz>,
+  /// Days on which nothing is due; due dates on them move to the next day that isn't one
+  pub holidays: BTreeSet<Date>,
+  /// Least time `todo_later` pushes a todo back by, a day if not given
+  pub min_later: Option<Duration>,
+  /// Most time a single `todo_later` pushes a todo back by, the task's interval if not given
+  ///
+  /// Takes precedence over `min_later`.
+  pub max_later: Option<Duration>,
+  /// Most time repeated `todo_later`s push a todo past its due date before the first of them
+  pub max_deferral: Option<Duration>,
+}
+
+impl Calendar {
+  /// The day `date` is on in the configured timezone, or in UTC without one
+  #[must_use]
+  pub fn local_date(&self, date: TodoDate) -> Date {
+    self
+      .timezone
+      .map_or_else(|| date.date(), |timezone| date.to_timezone(timezone).date())
+  }
+
+  fn is_holiday(&self, date: TodoDate) -> bool {
+    self.holidays.contains(&self.local_date(date))
+  }
+
+  /// Moves `date` forward by whole days until it isn't on a holiday
+  fn skip_holidays(&self, mut date: TodoDate) -> TodoDate {
+    while self.is_holiday(date) {
+      date = add_interval(date, Duration::DAY, self.timezone);
+    }
+    date
+  }
 }
 
-#[derive(Debug, Default)]
+/// What a `DueGuesser` knew about a task, see `DueGuesser::save_task`
+#[derive(Debug)]
+pub struct SavedTask(TaskId, Option<DueInfo>);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct DueGuesser {
   info: HashMap<TaskId, DueInfo>,
+  /// Comes from the configuration rather than the log, so it isn't serialized
+  #[serde(skip)]
+  calendar: Calendar,
 }
 
 impl DueGuesser {
+  #[cfg(test)]
   pub fn new() -> Self {
     Self::default()
   }
 
-  pub fn init_task<S: Store>(
-    &mut self,
-    _store: &S,
-    task_id: &TaskId,
-    due_after: Option<StdDuration>,
-  ) {
+  /// Creates a guesser that uses the given timezone and holidays
+  pub fn with_calendar(calendar: Calendar) -> Self {
+    Self {
+      calendar,
+      ..Self::default()
+    }
+  }
+
+  /// Uses the given timezone and holidays from now on, e.g. after deserializing the guesser
+  pub fn set_calendar(&mut self, calendar: Calendar) {
+    self.calendar = calendar;
+  }
+
+  pub const fn calendar(&self) -> &Calendar {
+    &self.calendar
+  }
+
+  pub fn init_task<S: Store>(&mut self, _store: &S, task_id: &TaskId, due_after: Option<Interval>) {
     self.info.insert(
       task_id.clone(),
       DueInfo {
-        due_in: due_after.map(|d| DueIn::new(d.try_into().unwrap())),
+        due_in: due_after.map(|Interval(d)| DueIn::new(d)),
         last_completed: None,
+        yearly: None,
+        after: None,
+        from_due: false,
+        last_due: None,
+        intervals: IntervalStats::default(),
       },
     );
   }
@@ -72,50 +257,262 @@ impl DueGuesser {
     );
   }
 
+  /// Saves what is known about the given task, so that `restore_task` can undo changes to it
+  pub fn save_task(&self, task_id: &TaskId) -> SavedTask {
+    SavedTask(task_id.clone(), self.info.get(task_id).cloned())
+  }
+
+  pub fn restore_task(&mut self, SavedTask(task_id, info): SavedTask) {
+    if let Some(info) = info {
+      self.info.insert(task_id, info);
+    } else {
+      self.info.remove(&task_id);
+    }
+  }
+
   pub fn handle_completion<S: Store>(
     &mut self,
     store: &S,
     todo_id: &TodoId,
     completed: &TodoCompleted,
   ) {
-    let task_id = &store.get_todo(todo_id).expect("Todo not found").task;
-    if let Some(info) = self.info.get_mut(task_id) {
+    let todo = store.get_todo(todo_id).expect("Todo not found");
+    if let Some(info) = self.info.get_mut(&todo.task) {
       if let Some(last_completed) = info.last_completed {
         let diff = completed.date - last_completed;
-        info.due_in = DueIn::add(info.due_in, diff);
+        info.due_in = Some(DueIn::add(info.due_in, diff));
+        info.intervals.add(diff);
       }
       info.last_completed = Some(completed.date);
+      info.last_due = Some(todo.due);
+    }
+  }
+
+  /// Restarts the interval of the given task at `date` without counting it as a completion
+  pub fn handle_touch(&mut self, task_id: &TaskId, date: TodoDate) {
+    if let Some(info) = self.info.get_mut(task_id) {
+      info.last_completed = Some(date);
+      info.last_due = Some(date);
     }
   }
 
+  /// Recomputes the interval of the given task from its completions after one was changed from
+  /// `old`, returning whether its last completion changed
+  ///
+  /// Intervals are counted between all completions, including ones separated by a pause.
+  pub fn handle_amend<S: Store>(&mut self, store: &S, task_id: &TaskId, old: TodoDate) -> bool {
+    let Some(info) = self.info.get_mut(task_id) else {
+      return false;
+    };
+    let mut completions: Vec<_> = store
+      .iter_todos(Some(task_id), Some(true))
+      .filter_map(|todo| {
+        todo
+          .completed
+          .as_ref()
+          .map(|completed| (completed.date, todo.due))
+      })
+      .collect();
+    completions.sort_unstable();
+    let dates: Vec<_> = completions.iter().map(|&(date, _)| date).collect();
+    if let Some(DueIn::Calculated(..)) = info.due_in {
+      info.due_in = None;
+    }
+    info.intervals = IntervalStats::default();
+    for pair in dates.windows(2) {
+      let diff = pair[1] - pair[0];
+      info.due_in = Some(DueIn::add(info.due_in, diff));
+      info.intervals.add(diff);
+    }
+    let last = dates.last().copied();
+    // Paused tasks don't have a last completion to change
+    let changed = info
+      .last_completed
+      .is_some_and(|last_completed| last_completed == old || Some(last_completed) < last);
+    if changed {
+      info.last_completed = last;
+      info.last_due = completions.last().map(|&(_, due)| due);
+    }
+    changed
+  }
+
   pub fn handle_pause(&mut self, task_id: &TaskId) {
     if let Some(info) = self.info.get_mut(task_id) {
       info.last_completed = None;
+      info.last_due = None;
     }
   }
 
+  pub fn task_stats(&self, task_id: &TaskId) -> Option<TaskStats> {
+    let info = self.info.get(task_id)?;
+    let IntervalStats { count, mean, m2 } = info.intervals;
+    let interval_kind = match (info.yearly, info.due_in) {
+      (Some(_), _) => IntervalKind::Yearly,
+      (None, Some(DueIn::Fixed(_))) => IntervalKind::Fixed,
+      (None, Some(DueIn::Learned(_))) => IntervalKind::Learned,
+      (None, Some(DueIn::Calculated(_, samples))) => IntervalKind::Calculated(samples),
+      (None, None) => IntervalKind::Default,
+    };
+    Some(TaskStats {
+      intervals: count,
+      mean_interval: Duration::seconds_f64(mean),
+      variation: (count >= 2 && mean > 0.0).then(|| (m2 / f64::from(count)).sqrt() / mean),
+      interval: DueIn::get(info.due_in),
+      interval_kind,
+      after: info.after.clone(),
+      from_due: info.from_due,
+    })
+  }
+
+  /// Makes the given task due every year on the anniversary of `anchor`
+  pub fn set_yearly(&mut self, task_id: &TaskId, anchor: Option<TodoDate>) -> Option<()> {
+    self.info.get_mut(task_id)?.yearly = anchor;
+    Some(())
+  }
+
+  /// Makes the given task due its interval after its last completed todo was due rather than
+  /// after it was done, so that late completions don't move later due dates
+  pub fn set_from_due(&mut self, task_id: &TaskId, from_due: bool) -> Option<()> {
+    self.info.get_mut(task_id)?.from_due = from_due;
+    Some(())
+  }
+
+  /// Makes the given task due its interval after `anchor` or itself was last done, whichever
+  /// was later
+  ///
+  /// Fails if `anchor` is due after the given task, directly or through other tasks.
+  pub fn set_after(&mut self, task_id: &TaskId, anchor: Option<TaskId>) -> Result<(), String> {
+    let mut next = anchor.as_ref();
+    while let Some(other) = next {
+      if other == task_id {
+        return Err(format!("Task {task_id} can't be due after itself"));
+      }
+      next = self.info.get(other).and_then(|info| info.after.as_ref());
+    }
+    self.info.get_mut(task_id).ok_or("Task not found")?.after = anchor;
+    Ok(())
+  }
+
+  /// Returns the tasks that are due after the given one, see `set_after`
+  pub fn tasks_after(&self, anchor: &TaskId) -> Vec<TaskId> {
+    self
+      .info
+      .iter()
+      .filter(|(_, info)| info.after.as_ref() == Some(anchor))
+      .map(|(task_id, _)| task_id.clone())
+      .collect()
+  }
+
+  /// Exchanges what is known about two tasks after their ids were swapped
+  pub fn swap_tasks(&mut self, a: &TaskId, b: &TaskId) {
+    let info_a = self.info.remove(a);
+    let info_b = self.info.remove(b);
+    self.info.extend(info_a.map(|info| (b.clone(), info)));
+    self.info.extend(info_b.map(|info| (a.clone(), info)));
+    for info in self.info.values_mut() {
+      if info.after.as_ref() == Some(a) {
+        info.after = Some(b.clone());
+      } else if info.after.as_ref() == Some(b) {
+        info.after = Some(a.clone());
+      }
+    }
+  }
+
+  /// Stops adapting the interval of the given task, keeping the current one
+  pub fn learn(&mut self, task_id: &TaskId) -> Option<Duration> {
+    let info = self.info.get_mut(task_id)?;
+    let due_in = DueIn::get(info.due_in);
+    info.due_in = Some(DueIn::Learned(due_in));
+    Some(due_in)
+  }
+
+  /// Starts adapting the interval of the given task again, starting from the current one
+  pub fn unlearn(&mut self, task_id: &TaskId) -> Option<Duration> {
+    let info = self.info.get_mut(task_id)?;
+    let due_in = DueIn::get(info.due_in);
+    info.due_in = Some(DueIn::Calculated(due_in, 1));
+    Some(due_in)
+  }
+
+  #[cfg_attr(feature = "trace", tracing::instrument(skip(self, _store), ret))]
   pub fn guess_due<S: Store>(&self, _store: &S, task_id: &TaskId) -> TodoDate {
-    let info = self.info.get(task_id);
-    let base = info
-      .and_then(|info| info.last_completed)
+    let base = self
+      .last_completed(task_id)
       .unwrap_or_else(OffsetDateTime::now_utc);
-    let plus = DueIn::get(info.and_then(|info| info.due_in));
-    base + plus
+    self.guess_next_due(task_id, base)
   }
 
-  pub fn guess_later<S: Store>(&self, store: &S, todo_id: &TodoId) -> TodoDate {
+  /// Returns when the given task, or the task it is due after, was last done or touched
+  ///
+  /// For tasks counting their interval from due dates, this is when the last completed todo was
+  /// due instead.
+  fn last_completed(&self, task_id: &TaskId) -> Option<TodoDate> {
+    let info = self.info.get(task_id)?;
+    let anchor = info
+      .after
+      .as_ref()
+      .and_then(|anchor| self.info.get(anchor)?.last_completed);
+    let own = if info.from_due {
+      // Logs and snapshots from before due dates were recorded don't have them
+      info.last_due.or(info.last_completed)
+    } else {
+      info.last_completed
+    };
+    own.max(anchor)
+  }
+
+  /// Guesses when the given task will be due if it is done at `completed`
+  pub fn guess_next_due(&self, task_id: &TaskId, completed: TodoDate) -> TodoDate {
+    let info = self.info.get(task_id);
+    let due = info.and_then(|info| info.yearly).map_or_else(
+      || {
+        add_interval(
+          completed,
+          DueIn::get(info.and_then(|info| info.due_in)),
+          self.calendar.timezone,
+        )
+      },
+      |anchor| next_anniversary(anchor, completed),
+    );
+    self.calendar.skip_holidays(due)
+  }
+
+  /// Guesses when the open todo of a task is due, if the task was ever done or touched
+  pub fn recompute_due(&self, task_id: &TaskId) -> Option<TodoDate> {
+    let last_completed = self.last_completed(task_id)?;
+    Some(self.guess_next_due(task_id, last_completed))
+  }
+
+  /// Guesses how far to push back a todo that was due at `deferred_from` before it was deferred
+  ///
+  /// Never returns a date before the todo's current due date.
+  pub fn guess_later<S: Store>(
+    &self,
+    store: &S,
+    todo_id: &TodoId,
+    deferred_from: TodoDate,
+  ) -> TodoDate {
     let todo = store.get_todo(todo_id).expect("Todo not found");
-    OffsetDateTime::now_utc().max(todo.due)
-      + Duration::DAY.max(DueIn::get(self.info.get(&todo.task).and_then(|info| info.due_in)) / 5)
+    let interval = DueIn::get(self.info.get(&todo.task).and_then(|info| info.due_in));
+    let min_later = self.calendar.min_later.unwrap_or(Duration::DAY);
+    let later = OffsetDateTime::now_utc().max(todo.due)
+      + min_later
+        .max(interval / 5)
+        .min(self.calendar.max_later.unwrap_or(interval));
+    self
+      .calendar
+      .max_deferral
+      .map_or(later, |max| later.min(deferred_from + max))
+      .max(todo.due)
   }
 }
 
 #[cfg(test)]
 mod test {
-  use super::DueGuesser;
+  use super::{Calendar, DueGuesser, Interval};
   use crate::engine::mem_store::MemStore;
-  use crate::engine::{Store, TodoCompleted};
-  use std::convert::TryInto;
+  use crate::engine::{Store, TaskId, TodoCompleted};
+  use time::macros::{date, datetime};
   use time::Duration;
   use time::OffsetDateTime;
 
@@ -123,14 +520,10 @@ mod test {
   fn ignores_fixed_after_two_completions() {
     let mut due_guesser = DueGuesser::new();
     let mut store = MemStore::new();
-    let task_id = store.create_task("Task".into());
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
     let now = OffsetDateTime::now_utc();
     let todo1_id = store.create_todo(&task_id, now);
-    due_guesser.init_task(
-      &store,
-      &task_id,
-      Some(Duration::days(5).try_into().unwrap()),
-    );
+    due_guesser.init_task(&store, &task_id, Some(Interval(Duration::days(5))));
     let completed = TodoCompleted::new(now);
     due_guesser.handle_completion(&store, &todo1_id, &completed);
     assert_eq!(
@@ -156,7 +549,7 @@ mod test {
   fn makes_a_good_guess() {
     let mut due_guesser = DueGuesser::new();
     let mut store = MemStore::new();
-    let task_id = store.create_task("Task".into());
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
     due_guesser.init_task(&store, &task_id, None);
 
     let mut now = OffsetDateTime::now_utc();
@@ -188,15 +581,147 @@ mod test {
     );
   }
 
+  #[test]
+  fn keeps_local_time_across_dst() {
+    let berlin = time_tz::timezones::db::europe::BERLIN;
+    let mut due_guesser = DueGuesser::with_calendar(Calendar {
+      timezone: Some(berlin),
+      ..Calendar::default()
+    });
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+    due_guesser.init_task(&store, &task_id, Some(Interval(Duration::DAY)));
+
+    let todo_id = store.create_todo(&task_id, datetime!(2024-03-30 09:00 +1));
+    let completed = TodoCompleted::new(datetime!(2024-03-30 09:00 +1));
+    due_guesser.handle_completion(&store, &todo_id, &completed);
+    assert_eq!(
+      due_guesser.guess_due(&store, &task_id),
+      datetime!(2024-03-31 09:00 +2)
+    );
+    assert_eq!(
+      due_guesser.guess_next_due(&task_id, datetime!(2024-03-30 02:30 +1)),
+      datetime!(2024-03-31 03:30 +2)
+    );
+
+    let mut due_guesser = DueGuesser::new();
+    due_guesser.init_task(&store, &task_id, Some(Interval(Duration::DAY)));
+    assert_eq!(
+      due_guesser.guess_next_due(&task_id, datetime!(2024-03-30 09:00 +1)),
+      datetime!(2024-03-31 09:00 +1)
+    );
+  }
+
+  #[test]
+  fn measures_variation() {
+    let variation = |days: &[i64]| {
+      let mut due_guesser = DueGuesser::new();
+      let mut store = MemStore::new();
+      let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+      due_guesser.init_task(&store, &task_id, None);
+      let mut now = datetime!(2024-01-01 10:00 UTC);
+      for days in std::iter::once(&0).chain(days) {
+        now += Duration::days(*days);
+        let todo_id = store.create_todo(&task_id, now);
+        let completed = TodoCompleted::new(now);
+        due_guesser.handle_completion(&store, &todo_id, &completed);
+        store.set_todo_completed(&todo_id, Some(completed)).unwrap();
+      }
+      due_guesser.task_stats(&task_id).unwrap()
+    };
+    let regular = variation(&[7, 7, 7, 7]);
+    assert_eq!(regular.intervals, 4);
+    assert_eq!(regular.mean_interval, Duration::days(7));
+    assert_eq!(regular.variation, Some(0.0));
+    let erratic = variation(&[1, 13, 2, 12]);
+    assert_eq!(erratic.mean_interval, Duration::days(7));
+    assert!(erratic.variation.unwrap() > 0.7);
+    assert_eq!(variation(&[3]).variation, None);
+  }
+
+  #[test]
+  fn keeps_learned_interval() {
+    let mut due_guesser = DueGuesser::new();
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+    due_guesser.init_task(&store, &task_id, None);
+
+    let mut now = OffsetDateTime::now_utc();
+    for days in [0, 2, 4] {
+      now += Duration::days(days);
+      let todo_id = store.create_todo(&task_id, now);
+      let completed = TodoCompleted::new(now);
+      due_guesser.handle_completion(&store, &todo_id, &completed);
+      store.set_todo_completed(&todo_id, Some(completed)).unwrap();
+    }
+    assert_eq!(due_guesser.learn(&task_id), Some(Duration::days(3)));
+
+    now += Duration::days(10);
+    let todo_id = store.create_todo(&task_id, now);
+    let completed = TodoCompleted::new(now);
+    due_guesser.handle_completion(&store, &todo_id, &completed);
+    store.set_todo_completed(&todo_id, Some(completed)).unwrap();
+    assert_eq!(
+      due_guesser.guess_due(&store, &task_id),
+      now + Duration::days(3)
+    );
+
+    assert_eq!(due_guesser.unlearn(&task_id), Some(Duration::days(3)));
+    now += Duration::days(5);
+    let todo_id = store.create_todo(&task_id, now);
+    due_guesser.handle_completion(&store, &todo_id, &TodoCompleted::new(now));
+    assert_eq!(
+      due_guesser.guess_due(&store, &task_id),
+      now + Duration::days(4)
+    );
+  }
+
+  #[test]
+  fn yearly_on_leap_day() {
+    let mut due_guesser = DueGuesser::new();
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+    due_guesser.init_task(&store, &task_id, None);
+    let anchor = datetime!(2024-02-29 9:00 UTC);
+    due_guesser.set_yearly(&task_id, Some(anchor)).unwrap();
+
+    let mut expected = [
+      (
+        datetime!(2024-03-01 12:00 UTC),
+        datetime!(2025-02-28 9:00 UTC),
+      ),
+      (
+        datetime!(2025-02-20 12:00 UTC),
+        datetime!(2026-02-28 9:00 UTC),
+      ),
+      (
+        datetime!(2026-03-30 12:00 UTC),
+        datetime!(2027-02-28 9:00 UTC),
+      ),
+      (
+        datetime!(2027-02-28 12:00 UTC),
+        datetime!(2028-02-29 9:00 UTC),
+      ),
+    ]
+    .into_iter();
+    for (completed, due) in &mut expected {
+      let todo_id = store.create_todo(&task_id, completed);
+      let completed = TodoCompleted::new(completed);
+      due_guesser.handle_completion(&store, &todo_id, &completed);
+      store.set_todo_completed(&todo_id, Some(completed)).unwrap();
+      assert_eq!(due_guesser.guess_due(&store, &task_id), due);
+    }
+  }
+
   #[test]
   fn makes_a_good_later() {
     let mut due_guesser = DueGuesser::new();
     let mut store = MemStore::new();
-    let task_id = store.create_task("Task".into());
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
     due_guesser.init_task(&store, &task_id, None);
     let mut now = OffsetDateTime::now_utc();
     let todo_id = store.create_todo(&task_id, now);
-    let later = due_guesser.guess_later(&store, &todo_id);
+    let later = due_guesser.guess_later(&store, &todo_id, now);
     assert!(later >= now + Duration::days(6));
     now = OffsetDateTime::now_utc();
     assert!(later <= now + Duration::days(6));
@@ -218,9 +743,76 @@ mod test {
     due_guesser.handle_completion(&store, &todo_id, &TodoCompleted::new(now));
     store.set_todo_completed(&todo_id, Some(completed)).unwrap();
 
-    let later = due_guesser.guess_later(&store, &todo_id);
+    let later = due_guesser.guess_later(&store, &todo_id, now);
     assert!(later >= now + Duration::days(8));
     now = OffsetDateTime::now_utc() + Duration::days(90);
     assert!(later <= now + Duration::days(8));
   }
+
+  #[test]
+  fn later_for_short_and_long_intervals() {
+    let later = |min_later, interval| {
+      let mut due_guesser = DueGuesser::with_calendar(Calendar {
+        min_later,
+        ..Calendar::default()
+      });
+      let mut store = MemStore::new();
+      let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+      due_guesser.init_task(&store, &task_id, Some(Interval(interval)));
+      let due = OffsetDateTime::now_utc() + Duration::days(10);
+      let todo_id = store.create_todo(&task_id, due);
+      due_guesser.guess_later(&store, &todo_id, due) - due
+    };
+    assert_eq!(later(None, Duration::DAY), Duration::DAY);
+    assert_eq!(
+      later(Some(Duration::HOUR), Duration::DAY),
+      Duration::minutes(288)
+    );
+    assert_eq!(later(None, Duration::days(30)), Duration::days(6));
+    assert_eq!(
+      later(Some(Duration::HOUR), Duration::days(30)),
+      Duration::days(6)
+    );
+    assert_eq!(later(None, Duration::HOUR), Duration::HOUR);
+  }
+
+  #[test]
+  fn caps_later() {
+    let mut due_guesser = DueGuesser::with_calendar(Calendar {
+      max_later: Some(Duration::days(2)),
+      max_deferral: Some(Duration::days(5)),
+      ..Calendar::default()
+    });
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+    due_guesser.init_task(&store, &task_id, Some(Interval(Duration::days(30))));
+    let original = OffsetDateTime::now_utc() + Duration::days(10);
+    let todo_id = store.create_todo(&task_id, original);
+    let mut dues = vec![];
+    for _ in 0..4 {
+      let due = due_guesser.guess_later(&store, &todo_id, original);
+      store.set_todo_due(&todo_id, due).unwrap();
+      dues.push((due - original).whole_days());
+    }
+    assert_eq!(dues, [2, 4, 5, 5]);
+  }
+
+  #[test]
+  fn skips_holidays() {
+    let mut due_guesser = DueGuesser::with_calendar(Calendar {
+      holidays: [date!(2024 - 12 - 25), date!(2024 - 12 - 26)].into(),
+      ..Calendar::default()
+    });
+    let store = MemStore::new();
+    let task_id = TaskId::new(1);
+    due_guesser.init_task(&store, &task_id, Some(Interval(Duration::DAY)));
+    assert_eq!(
+      due_guesser.guess_next_due(&task_id, datetime!(2024-12-24 09:00 UTC)),
+      datetime!(2024-12-27 09:00 UTC)
+    );
+    assert_eq!(
+      due_guesser.guess_next_due(&task_id, datetime!(2024-12-22 09:00 UTC)),
+      datetime!(2024-12-23 09:00 UTC)
+    );
+  }
 }
@@ -1,131 +1,741 @@
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
+use std::io::{Read, Write};
 use std::time::Duration;
 use time::OffsetDateTime;
+use time_tz::Tz;
 
-use super::due_guesser::DueGuesser;
-use super::{Logger, Store, TaskId, TodoCompleted, TodoId};
+use super::due_guesser::{Calendar, DueGuesser, Interval, TaskStats};
+use super::{
+  remaining_completions, LogPosition, Logger, OpenTodoError, Store, TaskId, TodoCompleted,
+  TodoDate, TodoId,
+};
 
 pub trait Allesatt {
   type Store: Store;
-  fn create_task(&mut self, title: String, due_every: Option<Duration>) -> (TaskId, TodoId);
+  /// Creates a task, `created` being `None` for tasks from logs that didn't record it
+  fn create_task(
+    &mut self,
+    title: String,
+    due_every: Option<Duration>,
+    created: Option<TodoDate>,
+  ) -> Result<(TaskId, TodoId), Box<dyn Error>>;
+  /// Clones a task, `created` being `None` for clones from logs that didn't record it, which get
+  /// the original task's creation date
   fn clone_task(
     &mut self,
     task_id: &TaskId,
     title: String,
+    created: Option<TodoDate>,
   ) -> Result<(TaskId, TodoId), Box<dyn Error>>;
+  /// Clones a task while replaying a log, making the clone's todo `logged.1` its open one
+  ///
+  /// The clone's completed todos get the next todo ids in the order of the original task's
+  /// completions, its open todo the id after them. Logs only need to agree on which of those
+  /// ids is the open todo, though.
+  fn replay_clone_task(
+    &mut self,
+    task_id: &TaskId,
+    title: String,
+    created: Option<TodoDate>,
+    logged: &(TaskId, TodoId),
+  ) -> Result<(), Box<dyn Error>> {
+    let result = self.clone_task(task_id, title, created)?;
+    if *logged != result {
+      return Err(
+        format!("Mismatch in task or todo ids: expected {logged:?}, found {result:?}").into(),
+      );
+    }
+    Ok(())
+  }
   fn complete_todo(
     &mut self,
     todo_id: &TodoId,
     completed: TodoCompleted,
   ) -> Result<(), Box<dyn Error>>;
+  /// Changes when a completed todo was done
+  fn amend_completion(&mut self, todo_id: &TodoId, date: TodoDate) -> Result<(), Box<dyn Error>>;
+  /// Moves a completed todo to another task, e.g. if it was recorded for the wrong one
+  ///
+  /// Both tasks' intervals are recomputed from their completions. Open todos can't be moved.
+  fn reattach_todo(&mut self, todo_id: &TodoId, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
   fn todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>>;
+  /// Reverts the most recent `todo_later` of a todo, returning the due date it had before
+  ///
+  /// Fails if the todo wasn't deferred or was rescheduled otherwise since.
+  fn undo_todo_later(&mut self, todo_id: &TodoId) -> Result<TodoDate, Box<dyn Error>>;
+  fn postpone_todo(&mut self, todo_id: &TodoId, by: Duration) -> Result<(), Box<dyn Error>>;
+  fn set_todo_due(&mut self, todo_id: &TodoId, due: TodoDate) -> Result<(), Box<dyn Error>>;
+  /// Records that `done` of `total` steps of an open todo are done
+  fn set_todo_progress(
+    &mut self,
+    todo_id: &TodoId,
+    done: u32,
+    total: u32,
+  ) -> Result<(), Box<dyn Error>>;
+  /// Removes one of several open todos of a task, repairing logs that created them
+  fn discard_todo(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>>;
+  /// Makes a task due as if it had been done at `at`, without recording a completion
+  fn touch_task(&mut self, task_id: &TaskId, at: TodoDate) -> Result<TodoId, Box<dyn Error>>;
   fn pause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
-  fn unpause_task(&mut self, task_id: &TaskId) -> Result<TodoId, Box<dyn Error>>;
+  /// Makes a paused task due again, at `at` or now
+  ///
+  /// Fails if the task doesn't exist or isn't paused.
+  fn unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>>;
+  /// Unpauses a task while replaying a log
+  ///
+  /// Older versions logged unpausing tasks that weren't paused, giving them a second open todo,
+  /// so this doesn't fail then. `check --fix` repairs such logs.
+  fn replay_unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>>;
+  fn rename_task(&mut self, task_id: &TaskId, title: String) -> Result<(), Box<dyn Error>>;
+  fn set_task_effort(&mut self, task_id: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>>;
+  /// Lists a task as due this long before it is actually due, without changing its due date
+  fn set_task_lead_time(
+    &mut self,
+    task_id: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>>;
+  fn set_task_url(&mut self, task_id: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>>;
+  /// Flags a task as blocked for the given reason, or unblocks it with `None`
+  ///
+  /// Blocked tasks keep their open todo, but aren't listed as due.
+  fn set_task_blocked(
+    &mut self,
+    task_id: &TaskId,
+    reason: Option<String>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn set_task_max_completions(
+    &mut self,
+    task_id: &TaskId,
+    max_completions: Option<u32>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn set_task_yearly(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TodoDate>,
+  ) -> Result<(), Box<dyn Error>>;
+  /// Makes a task due its interval after `anchor` was last done, unless it was done itself
+  /// since, or only after itself again with `None`
+  ///
+  /// Completing `anchor` reschedules the task's open todo. Fails if this would make the tasks
+  /// due after each other in a cycle.
+  fn set_task_after(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TaskId>,
+  ) -> Result<(), Box<dyn Error>>;
+  /// Makes a task due its interval after its last completed todo was due rather than after it
+  /// was done, so that doing it late doesn't move later due dates
+  fn set_task_from_due(&mut self, task_id: &TaskId, from_due: bool) -> Result<(), Box<dyn Error>>;
+  /// Adds a name a task can be referred to by, which must not be used by any task yet
+  fn add_task_alias(&mut self, task_id: &TaskId, alias: String) -> Result<(), Box<dyn Error>>;
+  fn learn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>>;
+  fn unlearn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>>;
+  /// Exchanges the ids of two tasks, e.g. for keeping a tidy numbering
+  fn swap_tasks(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>>;
+  /// Guesses when a task will be due next if it is done at `completed`
+  fn guess_next_due(&self, task_id: &TaskId, completed: TodoDate) -> TodoDate;
+  /// Guesses when a task's open todo is due with the current settings, if it was ever done
+  fn recompute_due(&self, task_id: &TaskId) -> Option<TodoDate>;
+  fn task_stats(&self, task_id: &TaskId) -> Option<TaskStats>;
+  /// The timezone and holidays due dates are computed with
+  fn calendar(&self) -> &Calendar;
   fn get_store(&self) -> &Self::Store;
+  /// Returns the store for maintenance, e.g. migrating how a custom store keeps its data
+  ///
+  /// Changes made through it bypass the log, so they are lost on the next replay and make the
+  /// store inconsistent with the engine if they change tasks or todos. Use the logged methods
+  /// above for changing those.
+  fn get_store_mut(&mut self) -> &mut Self::Store;
+  /// How far the log has been replayed and written, if the engine logs its actions
+  fn log_position(&self) -> LogPosition {
+    LogPosition::default()
+  }
+  /// Makes sure everything logged so far is written, see `Logger::flush`
+  fn flush_log(&mut self) -> Result<(), Box<dyn Error>> {
+    Ok(())
+  }
+  /// Writes the state of the engine and the log position, see `try_from_snapshot`
+  fn write_snapshot(&self, target: &mut dyn Write) -> Result<(), Box<dyn Error>>
+  where
+    Self::Store: serde::Serialize;
 }
 
+/// The state of the engine after replaying the log up to `position`
+#[derive(Serialize)]
+struct SnapshotRef<'a, S> {
+  position: &'a LogPosition,
+  store: &'a S,
+  due_guesser: &'a DueGuesser,
+  deferrals: &'a Deferrals,
+}
+
+/// An owned `SnapshotRef`, for reading it back
+#[derive(Deserialize)]
+struct Snapshot<S> {
+  position: LogPosition,
+  store: S,
+  due_guesser: DueGuesser,
+  #[serde(default)]
+  deferrals: Deferrals,
+}
+
+/// Due dates of todos before and after each `todo_later`, for undoing them
+type Deferrals = HashMap<TodoId, Vec<(TodoDate, TodoDate)>>;
+
 #[derive(Debug)]
 struct AllesattInner<S> {
   store: S,
   due_guesser: DueGuesser,
+  deferrals: Deferrals,
+  /// Tasks whose creation date is to be derived from their todos
+  unknown_created: Vec<TaskId>,
 }
 
 impl<S> AllesattInner<S> {
+  #[cfg(test)]
   pub fn new(store: S) -> Self {
+    Self::with_calendar(store, Calendar::default())
+  }
+
+  pub fn with_calendar(store: S, calendar: Calendar) -> Self {
     Self {
       store,
-      due_guesser: DueGuesser::new(),
+      due_guesser: DueGuesser::with_calendar(calendar),
+      deferrals: Deferrals::new(),
+      unknown_created: vec![],
     }
   }
+
+  fn write_snapshot_at(
+    &self,
+    position: &LogPosition,
+    target: &mut dyn Write,
+  ) -> Result<(), Box<dyn Error>>
+  where
+    S: serde::Serialize,
+  {
+    serde_json::to_writer(
+      target,
+      &SnapshotRef {
+        position,
+        store: &self.store,
+        due_guesser: &self.due_guesser,
+        deferrals: &self.deferrals,
+      },
+    )?;
+    Ok(())
+  }
+}
+
+impl<S: Store> AllesattInner<S> {
+  fn atomically<T>(
+    &mut self,
+    f: impl FnOnce(&mut Self) -> Result<T, Box<dyn Error>>,
+  ) -> Result<T, Box<dyn Error>> {
+    self.store.begin();
+    let result = f(self);
+    if result.is_ok() {
+      self.store.commit();
+    } else {
+      self.store.rollback();
+    }
+    result
+  }
+
+  /// Sets the creation date of tasks that didn't record it to the earliest date of their todos
+  fn derive_created(&mut self) -> Result<(), Box<dyn Error>> {
+    for task_id in std::mem::take(&mut self.unknown_created) {
+      let earliest = self
+        .store
+        .iter_todos(Some(&task_id), None)
+        .flat_map(|todo| [Some(todo.due), todo.completed.as_ref().map(|c| c.date)])
+        .flatten()
+        .min();
+      if let Some(created) = earliest {
+        self.store.set_task_created(&task_id, created)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Moves the open todo of the given task to when it is due with what is known now
+  fn reschedule(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
+    let Some(todo) = self.store.find_open_todo(task_id) else {
+      return Ok(());
+    };
+    let todo_id = todo.id.clone();
+    if let Some(due) = self.due_guesser.recompute_due(task_id) {
+      self.store.set_todo_due(&todo_id, due)?;
+    }
+    Ok(())
+  }
 }
 
 impl<S: Store> Allesatt for AllesattInner<S> {
   type Store = S;
 
-  fn create_task(&mut self, title: String, due_every: Option<Duration>) -> (TaskId, TodoId) {
-    let task_id = self.store.create_task(title);
+  fn create_task(
+    &mut self,
+    title: String,
+    due_every: Option<Duration>,
+    created: Option<TodoDate>,
+  ) -> Result<(TaskId, TodoId), Box<dyn Error>> {
+    let due_every = due_every.map(Interval::try_from).transpose()?;
+    let now = OffsetDateTime::now_utc();
+    let task_id = self.store.create_task(title, created.unwrap_or(now));
+    if created.is_none() {
+      self.unknown_created.push(task_id.clone());
+    }
     self.due_guesser.init_task(&self.store, &task_id, due_every);
-    let todo_id = self.store.create_todo(&task_id, OffsetDateTime::now_utc());
-    (task_id, todo_id)
+    let todo_id = self.store.create_todo(&task_id, now);
+    Ok((task_id, todo_id))
   }
 
   fn clone_task(
     &mut self,
     task_id: &TaskId,
     title: String,
+    created: Option<TodoDate>,
   ) -> Result<(TaskId, TodoId), Box<dyn Error>> {
-    self.store.get_task(task_id).ok_or("task not found")?;
-    let new_task_id = self.store.create_task(title);
+    let original = self.store.get_task(task_id).ok_or("task not found")?;
+    let created = created.unwrap_or(original.created);
+    let (new_task_id, todo_id) = self.atomically(|this| {
+      let new_task_id = this.store.create_task(title, created);
+      // Copy the completions first, since the store can't be changed while iterating it, and
+      // in the order they happened, so that the copies get ids in that order
+      let mut todos: Vec<_> = this
+        .store
+        .iter_todos(Some(task_id), Some(true))
+        .map(|t| (t.id.get(), t.due, t.completed.clone()))
+        .collect();
+      todos.sort_by_key(|(id, _, completed)| (completed.as_ref().map(|c| c.date), *id));
+      for (_, due, completed) in todos {
+        let todo_id = this.store.create_todo(&new_task_id, due);
+        this.store.set_todo_completed(&todo_id, completed)?;
+      }
+      let due = this
+        .store
+        .find_open_todo(task_id)
+        .ok_or("Cloning paused tasks is not implemented")?
+        .due;
+      let todo_id = this.store.create_todo(&new_task_id, due);
+      Ok((new_task_id, todo_id))
+    })?;
     self
       .due_guesser
       .copy_task(&self.store, &new_task_id, task_id);
-    let todos: Box<[_]> = self
-      .store
-      .get_todos(Some(task_id), Some(true))
-      .into_iter()
-      .map(|t| (t.due, t.completed.clone()))
-      .collect();
-    // Waiting for #116607 for dropping into_vec()
-    for (due, completed) in todos.into_vec() {
-      let todo_id = self.store.create_todo(&new_task_id, due);
-      self.store.set_todo_completed(&todo_id, completed)?;
+    Ok((new_task_id, todo_id))
+  }
+
+  fn replay_clone_task(
+    &mut self,
+    task_id: &TaskId,
+    title: String,
+    created: Option<TodoDate>,
+    logged: &(TaskId, TodoId),
+  ) -> Result<(), Box<dyn Error>> {
+    let (new_task_id, todo_id) = self.clone_task(task_id, title, created)?;
+    if new_task_id != logged.0 {
+      return Err(
+        format!(
+          "Mismatch in task ids: expected {:?}, found {new_task_id:?}",
+          logged.0
+        )
+        .into(),
+      );
     }
-    let due = self
+    if todo_id == logged.1 {
+      return Ok(());
+    }
+    // Only the open todo is referred to by later lines, so swap it with the logged one
+    let logged_todo = self
       .store
-      .find_open_todo(task_id)
-      .ok_or("Cloning paused tasks is not implemented")?
-      .due;
-    let todo_id = self.store.create_todo(&new_task_id, due);
-    Ok((new_task_id, todo_id))
+      .get_todo(&logged.1)
+      .filter(|todo| todo.task == new_task_id)
+      .ok_or_else(|| format!("Todo {} is not one of the clone's todos", logged.1))?;
+    let (due, completed) = (logged_todo.due, logged_todo.completed.clone());
+    let open_due = self.store.get_todo(&todo_id).ok_or("Todo not found")?.due;
+    self.atomically(|this| {
+      this.store.set_todo_completed(&todo_id, completed)?;
+      this.store.set_todo_due(&todo_id, due)?;
+      this.store.set_todo_completed(&logged.1, None)?;
+      this.store.set_todo_due(&logged.1, open_due)
+    })
   }
 
+  #[cfg_attr(feature = "trace", tracing::instrument(skip(self)))]
   fn complete_todo(
     &mut self,
     todo_id: &TodoId,
     completed: TodoCompleted,
   ) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    if let Some(previous) = &todo.completed {
+      return Err(
+        format!(
+          "Todo {} was already completed at {}",
+          todo_id, previous.date
+        )
+        .into(),
+      );
+    }
+    let task_id = todo.task.clone();
+    // The store is rolled back if completing fails, so the guesser has to be as well
+    let saved = self.due_guesser.save_task(&task_id);
+    let result = self.atomically(|this| {
+      this
+        .due_guesser
+        .handle_completion(&this.store, todo_id, &completed);
+      this.store.set_todo_completed(todo_id, Some(completed))?;
+      for other in this.due_guesser.tasks_after(&task_id) {
+        this.reschedule(&other)?;
+      }
+      let task = this.store.get_task(&task_id).ok_or("Task not found")?;
+      if remaining_completions(&this.store, task) == Some(0) {
+        this.due_guesser.handle_pause(&task_id);
+        return Ok(());
+      }
+      let due = this.due_guesser.guess_due(&this.store, &task_id);
+      this.store.create_todo(&task_id, due);
+      Ok(())
+    });
+    if result.is_ok() {
+      // Completed todos can't be deferred anymore
+      self.deferrals.remove(todo_id);
+    } else {
+      self.due_guesser.restore_task(saved);
+    }
+    result
+  }
+
+  fn amend_completion(&mut self, todo_id: &TodoId, date: TodoDate) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    let completed = todo
+      .completed
+      .clone()
+      .ok_or_else(|| format!("Todo {todo_id} is not completed"))?;
+    let task_id = todo.task.clone();
+    let old = completed.date;
+    self.store.set_todo_completed(
+      todo_id,
+      Some(TodoCompleted::new(date).with_note(completed.note)),
+    )?;
+    if self.due_guesser.handle_amend(&self.store, &task_id, old) {
+      if let Some(open) = self.store.find_open_todo(&task_id) {
+        let open = open.id.clone();
+        let due = self.due_guesser.guess_due(&self.store, &task_id);
+        self.store.set_todo_due(&open, due)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn reattach_todo(&mut self, todo_id: &TodoId, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    let date = todo
+      .completed
+      .as_ref()
+      .ok_or_else(|| format!("Todo {todo_id} is open, only completed todos can be reattached"))?
+      .date;
+    let from = todo.task.clone();
+    if from == *task_id {
+      return Err(format!("Todo {todo_id} already belongs to task {task_id}").into());
+    }
     self
-      .due_guesser
-      .handle_completion(&self.store, todo_id, &completed);
-    self.store.set_todo_completed(todo_id, Some(completed))?;
-    let task_id = self
       .store
-      .get_todo(todo_id)
-      .ok_or("Todo not found")?
-      .task
-      .clone();
-    let due = self.due_guesser.guess_due(&self.store, &task_id);
-    self.store.create_todo(&task_id, due);
-    Ok(())
+      .get_task(task_id)
+      .ok_or_else(|| format!("Task {task_id} not found"))?;
+    self.atomically(|this| {
+      this.store.set_todo_task(todo_id, task_id)?;
+      for task in [&from, task_id] {
+        if this.due_guesser.handle_amend(&this.store, task, date) {
+          this.reschedule(task)?;
+        }
+      }
+      Ok(())
+    })
   }
 
   fn todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
-    let due = self.due_guesser.guess_later(&self.store, todo_id);
+    let before = self.store.get_todo(todo_id).ok_or("Todo not found")?.due;
+    let deferrals = self.deferrals.entry(todo_id.clone()).or_default();
+    // Only deferrals since the todo was last rescheduled otherwise count
+    if deferrals.last().is_some_and(|&(_, after)| after != before) {
+      deferrals.clear();
+    }
+    let deferred_from = deferrals.first().map_or(before, |&(from, _)| from);
+    let due = self
+      .due_guesser
+      .guess_later(&self.store, todo_id, deferred_from);
+    self.store.set_todo_due(todo_id, due)?;
+    deferrals.push((before, due));
+    Ok(())
+  }
+
+  fn undo_todo_later(&mut self, todo_id: &TodoId) -> Result<TodoDate, Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    let deferrals = self.deferrals.get_mut(todo_id);
+    let Some(&(before, after)) = deferrals.as_ref().and_then(|deferrals| deferrals.last()) else {
+      return Err(format!("Todo {todo_id} wasn't deferred").into());
+    };
+    if todo.completed.is_some() || todo.due != after {
+      return Err(
+        format!("Todo {todo_id} was completed or rescheduled since it was deferred").into(),
+      );
+    }
+    deferrals.unwrap().pop();
+    self.store.set_todo_due(todo_id, before)?;
+    Ok(before)
+  }
+
+  fn postpone_todo(&mut self, todo_id: &TodoId, by: Duration) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    let due = OffsetDateTime::now_utc().max(todo.due) + by;
     self.store.set_todo_due(todo_id, due)?;
     Ok(())
   }
 
+  fn set_todo_due(&mut self, todo_id: &TodoId, due: TodoDate) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    if todo.completed.is_some() {
+      return Err(format!("Todo {todo_id} is already completed").into());
+    }
+    self.store.set_todo_due(todo_id, due)?;
+    Ok(())
+  }
+
+  fn set_todo_progress(
+    &mut self,
+    todo_id: &TodoId,
+    done: u32,
+    total: u32,
+  ) -> Result<(), Box<dyn Error>> {
+    if total == 0 || done > total {
+      return Err(format!("Invalid progress {done}/{total}").into());
+    }
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    if todo.completed.is_some() {
+      return Err(format!("Todo {todo_id} is already completed").into());
+    }
+    self.store.set_todo_progress(todo_id, Some((done, total)))
+  }
+
+  fn discard_todo(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
+    let todo = self.store.get_todo(todo_id).ok_or("Todo not found")?;
+    if todo.completed.is_some() {
+      return Err(format!("Todo {todo_id} is already completed").into());
+    }
+    let task_id = &todo.task;
+    if self.store.iter_todos(Some(task_id), Some(false)).count() < 2 {
+      return Err(format!("Todo {todo_id} is the only open todo of task {task_id}").into());
+    }
+    self.store.delete_todo(todo_id)
+  }
+
+  fn touch_task(&mut self, task_id: &TaskId, at: TodoDate) -> Result<TodoId, Box<dyn Error>> {
+    let todo_id = self.store.get_open_todo(task_id)?.id.clone();
+    self.due_guesser.handle_touch(task_id, at);
+    let due = self.due_guesser.guess_due(&self.store, task_id);
+    self.store.set_todo_due(&todo_id, due)?;
+    Ok(todo_id)
+  }
+
   fn pause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
-    let todo_id = self
-      .store
-      .find_open_todo(task_id)
-      .ok_or("Task not found or already paused")?
-      .id
-      .clone();
+    let todo_id = self.store.get_open_todo(task_id)?.id.clone();
     self.store.delete_todo(&todo_id)?;
     self.due_guesser.handle_pause(task_id);
     Ok(())
   }
 
-  fn unpause_task(&mut self, task_id: &TaskId) -> Result<TodoId, Box<dyn Error>> {
-    let todo_id = self.store.create_todo(task_id, OffsetDateTime::now_utc());
+  fn unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>> {
+    match self.store.get_open_todo(task_id) {
+      Err(OpenTodoError::Paused(_)) => self.replay_unpause_task(task_id, at),
+      Err(e) => Err(e.into()),
+      Ok(_) => Err(format!("Task {task_id} isn't paused").into()),
+    }
+  }
+
+  fn replay_unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>> {
+    let todo_id = self
+      .store
+      .create_todo(task_id, at.unwrap_or_else(OffsetDateTime::now_utc));
     Ok(todo_id)
   }
 
+  fn rename_task(&mut self, task_id: &TaskId, title: String) -> Result<(), Box<dyn Error>> {
+    self.store.set_task_title(task_id, title)
+  }
+
+  fn set_task_effort(&mut self, task_id: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>> {
+    self.store.set_task_effort(task_id, effort)
+  }
+
+  fn set_task_lead_time(
+    &mut self,
+    task_id: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>> {
+    self.store.set_task_lead_time(task_id, lead_time)
+  }
+
+  fn set_task_url(&mut self, task_id: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>> {
+    self.store.set_task_url(task_id, url)
+  }
+
+  fn set_task_blocked(
+    &mut self,
+    task_id: &TaskId,
+    reason: Option<String>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.store.set_task_blocked(task_id, reason)
+  }
+
+  fn set_task_max_completions(
+    &mut self,
+    task_id: &TaskId,
+    max_completions: Option<u32>,
+  ) -> Result<(), Box<dyn Error>> {
+    self
+      .store
+      .set_task_max_completions(task_id, max_completions)
+  }
+
+  fn add_task_alias(&mut self, task_id: &TaskId, alias: String) -> Result<(), Box<dyn Error>> {
+    if let Some(other) = self.store.find_task_by_alias(&alias) {
+      return Err(format!("Alias @{alias} is already used by task {}", other.id).into());
+    }
+    self.store.add_task_alias(task_id, alias)
+  }
+
+  fn set_task_yearly(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TodoDate>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.store.get_task(task_id).ok_or("Task not found")?;
+    self
+      .due_guesser
+      .set_yearly(task_id, anchor)
+      .ok_or("Task not found")?;
+    Ok(())
+  }
+
+  fn set_task_after(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TaskId>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.store.get_task(task_id).ok_or("Task not found")?;
+    if let Some(anchor) = &anchor {
+      self
+        .store
+        .get_task(anchor)
+        .ok_or_else(|| format!("Task {anchor} not found"))?;
+    }
+    self.due_guesser.set_after(task_id, anchor)?;
+    self.reschedule(task_id)
+  }
+
+  fn set_task_from_due(&mut self, task_id: &TaskId, from_due: bool) -> Result<(), Box<dyn Error>> {
+    self.store.get_task(task_id).ok_or("Task not found")?;
+    self
+      .due_guesser
+      .set_from_due(task_id, from_due)
+      .ok_or("Task not found")?;
+    self.reschedule(task_id)
+  }
+
+  fn learn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>> {
+    self.store.get_task(task_id).ok_or("Task not found")?;
+    Ok(
+      self
+        .due_guesser
+        .learn(task_id)
+        .ok_or("Task not found")?
+        .try_into()?,
+    )
+  }
+
+  fn unlearn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>> {
+    self.store.get_task(task_id).ok_or("Task not found")?;
+    Ok(
+      self
+        .due_guesser
+        .unlearn(task_id)
+        .ok_or("Task not found")?
+        .try_into()?,
+    )
+  }
+
+  fn swap_tasks(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>> {
+    for task_id in [a, b] {
+      self
+        .store
+        .get_task(task_id)
+        .ok_or_else(|| format!("Task {task_id} not found"))?;
+    }
+    self.store.swap_task_ids(a, b)?;
+    self.due_guesser.swap_tasks(a, b);
+    for task_id in &mut self.unknown_created {
+      if task_id == a {
+        *task_id = b.clone();
+      } else if task_id == b {
+        *task_id = a.clone();
+      }
+    }
+    Ok(())
+  }
+
+  fn guess_next_due(&self, task_id: &TaskId, completed: TodoDate) -> TodoDate {
+    self.due_guesser.guess_next_due(task_id, completed)
+  }
+
+  fn recompute_due(&self, task_id: &TaskId) -> Option<TodoDate> {
+    self.due_guesser.recompute_due(task_id)
+  }
+
+  fn task_stats(&self, task_id: &TaskId) -> Option<TaskStats> {
+    self.due_guesser.task_stats(task_id)
+  }
+
+  fn calendar(&self) -> &Calendar {
+    self.due_guesser.calendar()
+  }
+
   // This is non-mutable
   fn get_store(&self) -> &Self::Store {
     &self.store
   }
+
+  fn get_store_mut(&mut self) -> &mut Self::Store {
+    &mut self.store
+  }
+
+  fn write_snapshot(&self, target: &mut dyn Write) -> Result<(), Box<dyn Error>>
+  where
+    S: serde::Serialize,
+  {
+    self.write_snapshot_at(&self.log_position(), target)
+  }
 }
 
 #[derive(Debug)]
@@ -135,33 +745,71 @@ struct AllesattImpl<S: Store, L: Logger> {
 }
 
 impl<S: Store, L: Logger> AllesattImpl<S, L> {
-  fn try_new(store: S, mut logger: L) -> Result<Self, Box<dyn Error>> {
-    let mut inner = AllesattInner::new(store);
+  fn try_new(store: S, logger: L, calendar: Calendar) -> Result<Self, Box<dyn Error>> {
+    Self::replay(AllesattInner::with_calendar(store, calendar), logger)
+  }
+
+  /// Returns `None` if the log doesn't start with the records the snapshot covers
+  fn try_from_snapshot(
+    snapshot: impl Read,
+    mut logger: L,
+    calendar: Calendar,
+  ) -> Result<Option<Self>, Box<dyn Error>>
+  where
+    S: DeserializeOwned,
+  {
+    let Snapshot {
+      position,
+      store,
+      mut due_guesser,
+      deferrals,
+    } = serde_json::from_reader(snapshot)?;
+    due_guesser.set_calendar(calendar);
+    if !logger.skip(&position)? {
+      return Ok(None);
+    }
+    let inner = AllesattInner {
+      store,
+      due_guesser,
+      deferrals,
+      unknown_created: vec![],
+    };
+    Self::replay(inner, logger).map(Some)
+  }
+
+  /// Replays the rest of the log on top of `inner`
+  fn replay(mut inner: AllesattInner<S>, mut logger: L) -> Result<Self, Box<dyn Error>> {
     logger.play_back(&mut inner)?;
+    inner.derive_created()?;
     Ok(Self { inner, logger })
   }
 }
 
 impl<S: Store, L: Logger> Allesatt for AllesattImpl<S, L> {
   type Store = S;
-  fn create_task(&mut self, title: String, due_every: Option<Duration>) -> (TaskId, TodoId) {
-    let (task_id, todo_id) = self.inner.create_task(title.clone(), due_every);
+  fn create_task(
+    &mut self,
+    title: String,
+    due_every: Option<Duration>,
+    created: Option<TodoDate>,
+  ) -> Result<(TaskId, TodoId), Box<dyn Error>> {
+    let (task_id, todo_id) = self.inner.create_task(title.clone(), due_every, created)?;
     self
       .logger
-      .log_create_task(title.as_ref(), &due_every, &task_id, &todo_id)
-      .expect("Error logging task creation");
-    (task_id, todo_id)
+      .log_create_task(title.as_ref(), &due_every, created, &task_id, &todo_id)?;
+    Ok((task_id, todo_id))
   }
 
   fn clone_task(
     &mut self,
     task_id: &TaskId,
     title: String,
+    created: Option<TodoDate>,
   ) -> Result<(TaskId, TodoId), Box<dyn Error>> {
-    let (new_task_id, todo_id) = self.inner.clone_task(task_id, title.clone())?;
+    let (new_task_id, todo_id) = self.inner.clone_task(task_id, title.clone(), created)?;
     self
       .logger
-      .log_clone_task(task_id, title.as_ref(), &new_task_id, &todo_id)
+      .log_clone_task(task_id, title.as_ref(), created, &new_task_id, &todo_id)
       .expect("Error logging task creation");
     Ok((new_task_id, todo_id))
   }
@@ -182,48 +830,683 @@ impl<S: Store, L: Logger> Allesatt for AllesattImpl<S, L> {
     Ok(())
   }
 
+  fn undo_todo_later(&mut self, todo_id: &TodoId) -> Result<TodoDate, Box<dyn Error>> {
+    let due = self.inner.undo_todo_later(todo_id)?;
+    self.logger.log_undo_todo_later(todo_id)?;
+    Ok(due)
+  }
+
+  fn amend_completion(&mut self, todo_id: &TodoId, date: TodoDate) -> Result<(), Box<dyn Error>> {
+    self.inner.amend_completion(todo_id, date)?;
+    self.logger.log_amend_completion(todo_id, &date)?;
+    Ok(())
+  }
+
+  fn reattach_todo(&mut self, todo_id: &TodoId, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
+    self.inner.reattach_todo(todo_id, task_id)?;
+    self.logger.log_reattach_todo(todo_id, task_id)?;
+    Ok(())
+  }
+
+  fn postpone_todo(&mut self, todo_id: &TodoId, by: Duration) -> Result<(), Box<dyn Error>> {
+    self.inner.postpone_todo(todo_id, by)?;
+    self.logger.log_postpone_todo(todo_id, &by)?;
+    Ok(())
+  }
+
+  fn set_todo_due(&mut self, todo_id: &TodoId, due: TodoDate) -> Result<(), Box<dyn Error>> {
+    self.inner.set_todo_due(todo_id, due)?;
+    self.logger.log_set_todo_due(todo_id, &due)?;
+    Ok(())
+  }
+
+  fn set_todo_progress(
+    &mut self,
+    todo_id: &TodoId,
+    done: u32,
+    total: u32,
+  ) -> Result<(), Box<dyn Error>> {
+    self.inner.set_todo_progress(todo_id, done, total)?;
+    self.logger.log_set_todo_progress(todo_id, done, total)?;
+    Ok(())
+  }
+
+  fn discard_todo(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
+    self.inner.discard_todo(todo_id)?;
+    self.logger.log_discard_todo(todo_id)?;
+    Ok(())
+  }
+
+  fn touch_task(&mut self, task_id: &TaskId, at: TodoDate) -> Result<TodoId, Box<dyn Error>> {
+    let todo_id = self.inner.touch_task(task_id, at)?;
+    self.logger.log_touch_task(task_id, &at)?;
+    Ok(todo_id)
+  }
+
   fn pause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
     self.inner.pause_task(task_id)?;
     self.logger.log_pause_task(task_id)?;
     Ok(())
   }
 
-  fn unpause_task(&mut self, task_id: &TaskId) -> Result<TodoId, Box<dyn Error>> {
-    let result = self.inner.unpause_task(task_id)?;
-    self.logger.log_unpause_task(task_id)?;
+  fn unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>> {
+    let result = self.inner.unpause_task(task_id, at)?;
+    self.logger.log_unpause_task(task_id, at.as_ref())?;
+    Ok(result)
+  }
+
+  fn replay_unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<TodoDate>,
+  ) -> Result<TodoId, Box<dyn Error>> {
+    let result = self.inner.replay_unpause_task(task_id, at)?;
+    self.logger.log_unpause_task(task_id, at.as_ref())?;
+    Ok(result)
+  }
+
+  fn rename_task(&mut self, task_id: &TaskId, title: String) -> Result<(), Box<dyn Error>> {
+    let old_title = self
+      .inner
+      .store
+      .get_task(task_id)
+      .ok_or("Task not found")?
+      .title
+      .clone();
+    self.inner.rename_task(task_id, title.clone())?;
+    self.logger.log_rename_task(task_id, &old_title, &title)?;
+    Ok(())
+  }
+
+  fn set_task_effort(&mut self, task_id: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_effort(task_id, effort)?;
+    self.logger.log_set_task_effort(task_id, &effort)?;
+    Ok(())
+  }
+
+  fn set_task_lead_time(
+    &mut self,
+    task_id: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_lead_time(task_id, lead_time)?;
+    self.logger.log_set_task_lead_time(task_id, lead_time)?;
+    Ok(())
+  }
+
+  fn set_task_url(&mut self, task_id: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_url(task_id, url.clone())?;
+    self.logger.log_set_task_url(task_id, &url)?;
+    Ok(())
+  }
+
+  fn set_task_blocked(
+    &mut self,
+    task_id: &TaskId,
+    reason: Option<String>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_blocked(task_id, reason.clone())?;
+    self.logger.log_set_task_blocked(task_id, &reason)?;
+    Ok(())
+  }
+
+  fn set_task_max_completions(
+    &mut self,
+    task_id: &TaskId,
+    max_completions: Option<u32>,
+  ) -> Result<(), Box<dyn Error>> {
+    self
+      .inner
+      .set_task_max_completions(task_id, max_completions)?;
+    self
+      .logger
+      .log_set_task_max_completions(task_id, &max_completions)?;
+    Ok(())
+  }
+
+  fn add_task_alias(&mut self, task_id: &TaskId, alias: String) -> Result<(), Box<dyn Error>> {
+    self.inner.add_task_alias(task_id, alias.clone())?;
+    self.logger.log_add_task_alias(task_id, &alias)?;
+    Ok(())
+  }
+
+  fn set_task_yearly(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TodoDate>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_yearly(task_id, anchor)?;
+    self.logger.log_set_task_yearly(task_id, &anchor)?;
+    Ok(())
+  }
+
+  fn set_task_after(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<TaskId>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_after(task_id, anchor.clone())?;
+    self.logger.log_set_task_after(task_id, anchor.as_ref())?;
+    Ok(())
+  }
+
+  fn set_task_from_due(&mut self, task_id: &TaskId, from_due: bool) -> Result<(), Box<dyn Error>> {
+    self.inner.set_task_from_due(task_id, from_due)?;
+    self.logger.log_set_task_from_due(task_id, from_due)?;
+    Ok(())
+  }
+
+  fn learn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>> {
+    let result = self.inner.learn_task(task_id)?;
+    self.logger.log_learn_task(task_id)?;
     Ok(result)
   }
 
+  fn unlearn_task(&mut self, task_id: &TaskId) -> Result<Duration, Box<dyn Error>> {
+    let result = self.inner.unlearn_task(task_id)?;
+    self.logger.log_unlearn_task(task_id)?;
+    Ok(result)
+  }
+
+  fn swap_tasks(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>> {
+    self.inner.swap_tasks(a, b)?;
+    self.logger.log_swap_tasks(a, b)?;
+    Ok(())
+  }
+
+  fn guess_next_due(&self, task_id: &TaskId, completed: TodoDate) -> TodoDate {
+    self.inner.guess_next_due(task_id, completed)
+  }
+
+  fn recompute_due(&self, task_id: &TaskId) -> Option<TodoDate> {
+    self.inner.recompute_due(task_id)
+  }
+
+  fn task_stats(&self, task_id: &TaskId) -> Option<TaskStats> {
+    self.inner.task_stats(task_id)
+  }
+
+  fn calendar(&self) -> &Calendar {
+    self.inner.calendar()
+  }
+
   // This is non-mutable
   fn get_store(&self) -> &Self::Store {
     &self.inner.store
   }
+
+  fn get_store_mut(&mut self) -> &mut Self::Store {
+    &mut self.inner.store
+  }
+
+  fn log_position(&self) -> LogPosition {
+    self.logger.position()
+  }
+
+  fn flush_log(&mut self) -> Result<(), Box<dyn Error>> {
+    self.logger.flush()
+  }
+
+  fn write_snapshot(&self, target: &mut dyn Write) -> Result<(), Box<dyn Error>>
+  where
+    S: serde::Serialize,
+  {
+    self.inner.write_snapshot_at(&self.log_position(), target)
+  }
 }
 
 pub fn try_new(store: impl Store, logger: impl Logger) -> Result<impl Allesatt, Box<dyn Error>> {
-  AllesattImpl::try_new(store, logger)
+  AllesattImpl::try_new(store, logger, Calendar::default())
+}
+
+/// Like `try_new`, but keeps the local time of day in `timezone` for intervals of whole days
+pub fn try_new_in_timezone(
+  store: impl Store,
+  logger: impl Logger,
+  timezone: Option<&'static Tz>,
+) -> Result<impl Allesatt, Box<dyn Error>> {
+  AllesattImpl::try_new(
+    store,
+    logger,
+    Calendar {
+      timezone,
+      ..Calendar::default()
+    },
+  )
+}
+
+/// Like `try_new`, but computes due dates using the given timezone and holidays
+pub fn try_new_with_calendar<S: Store>(
+  store: S,
+  logger: impl Logger,
+  calendar: Calendar,
+) -> Result<impl Allesatt<Store = S>, Box<dyn Error>> {
+  AllesattImpl::try_new(store, logger, calendar)
+}
+
+/// Like `try_new_with_calendar`, but starts from a snapshot written by `Allesatt::write_snapshot`
+///
+/// Only the log records after the ones the snapshot covers are replayed. Returns `None` if the log
+/// doesn't start with these records anymore, e.g. because it was renumbered since, so the snapshot
+/// can't be used.
+pub fn try_from_snapshot<S: Store + DeserializeOwned>(
+  snapshot: impl Read,
+  logger: impl Logger,
+  calendar: Calendar,
+) -> Result<Option<impl Allesatt<Store = S>>, Box<dyn Error>> {
+  AllesattImpl::try_from_snapshot(snapshot, logger, calendar)
 }
 
 #[cfg(test)]
 mod tests {
   use super::{
-    super::{MemStore, Store, TodoCompleted},
-    Allesatt, AllesattInner,
+    super::{
+      Calendar, MemStore, OpenTodoError, ReadWriteLogger, Store, Task, TaskId, Todo, TodoCompleted,
+      TodoDate, TodoId,
+    },
+    try_from_snapshot, try_new_with_calendar, Allesatt, AllesattInner,
   };
+  use std::error::Error;
   use std::time::Duration;
   use time::OffsetDateTime;
 
+  #[derive(Debug, Default)]
+  struct SnapshotStore {
+    store: MemStore,
+    snapshot: Option<MemStore>,
+    /// Makes changing due dates fail, to test rolling back
+    fail_set_todo_due: bool,
+  }
+
+  impl Store for SnapshotStore {
+    fn create_task(&mut self, title: String, created: TodoDate) -> TaskId {
+      self.store.create_task(title, created)
+    }
+    fn create_todo(&mut self, task: &TaskId, due: TodoDate) -> TodoId {
+      self.store.create_todo(task, due)
+    }
+    fn set_task_title(&mut self, task: &TaskId, title: String) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_title(task, title)
+    }
+    fn set_task_effort(&mut self, task: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_effort(task, effort)
+    }
+    fn set_task_lead_time(
+      &mut self,
+      task: &TaskId,
+      lead_time: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_lead_time(task, lead_time)
+    }
+    fn set_task_url(&mut self, task: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_url(task, url)
+    }
+    fn set_task_blocked(
+      &mut self,
+      task: &TaskId,
+      blocked: Option<String>,
+    ) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_blocked(task, blocked)
+    }
+    fn set_task_created(&mut self, task: &TaskId, created: TodoDate) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_created(task, created)
+    }
+    fn set_task_max_completions(
+      &mut self,
+      task: &TaskId,
+      max_completions: Option<u32>,
+    ) -> Result<(), Box<dyn Error>> {
+      self.store.set_task_max_completions(task, max_completions)
+    }
+    fn add_task_alias(&mut self, task: &TaskId, alias: String) -> Result<(), Box<dyn Error>> {
+      self.store.add_task_alias(task, alias)
+    }
+    fn swap_task_ids(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>> {
+      self.store.swap_task_ids(a, b)
+    }
+    fn set_todo_completed(
+      &mut self,
+      todo: &TodoId,
+      completed: Option<TodoCompleted>,
+    ) -> Result<(), Box<dyn Error>> {
+      self.store.set_todo_completed(todo, completed)
+    }
+    fn set_todo_due(&mut self, todo: &TodoId, due: TodoDate) -> Result<(), Box<dyn Error>> {
+      if self.fail_set_todo_due {
+        return Err("Failing as requested".into());
+      }
+      self.store.set_todo_due(todo, due)
+    }
+    fn set_todo_task(&mut self, todo: &TodoId, task: &TaskId) -> Result<(), Box<dyn Error>> {
+      self.store.set_todo_task(todo, task)
+    }
+    fn set_todo_progress(
+      &mut self,
+      todo: &TodoId,
+      progress: Option<(u32, u32)>,
+    ) -> Result<(), Box<dyn Error>> {
+      self.store.set_todo_progress(todo, progress)
+    }
+    fn delete_todo(&mut self, todo: &TodoId) -> Result<(), Box<dyn Error>> {
+      self.store.delete_todo(todo)
+    }
+    fn get_task(&self, task: &TaskId) -> Option<&Task> {
+      self.store.get_task(task)
+    }
+    fn get_tasks(&self) -> Vec<&Task> {
+      self.store.get_tasks()
+    }
+    fn get_todo(&self, todo: &TodoId) -> Option<&Todo> {
+      self.store.get_todo(todo)
+    }
+    fn iter_todos<'a>(
+      &'a self,
+      task_id_filter: Option<&TaskId>,
+      completed_filter: Option<bool>,
+    ) -> impl Iterator<Item = &'a Todo> + 'a {
+      self.store.iter_todos(task_id_filter, completed_filter)
+    }
+    fn find_open_todo(&self, task: &TaskId) -> Option<&Todo> {
+      self.store.find_open_todo(task)
+    }
+    fn begin(&mut self) {
+      self.snapshot = Some(self.store.clone());
+    }
+    fn commit(&mut self) {
+      self.snapshot = None;
+    }
+    fn rollback(&mut self) {
+      if let Some(snapshot) = self.snapshot.take() {
+        self.store = snapshot;
+      }
+    }
+  }
+
+  #[test]
+  fn failed_completion_is_rolled_back() {
+    let start = OffsetDateTime::now_utc() - Duration::from_hours(24 * 10);
+    let mut engine = AllesattInner::new(SnapshotStore::default());
+    let (task_id, todo_id) = engine.create_task("x".into(), None, None).unwrap();
+    let (other_id, _) = engine.create_task("y".into(), None, None).unwrap();
+    engine
+      .set_task_after(&other_id, Some(task_id.clone()))
+      .unwrap();
+    engine
+      .complete_todo(&todo_id, TodoCompleted::new(start))
+      .unwrap();
+    let stats = |engine: &AllesattInner<SnapshotStore>| {
+      let stats = engine.task_stats(&task_id).unwrap();
+      (stats.intervals, stats.interval)
+    };
+    let before = stats(&engine);
+    let todo_id = engine
+      .get_store()
+      .find_open_todo(&task_id)
+      .unwrap()
+      .id
+      .clone();
+    engine.store.fail_set_todo_due = true;
+    let date = start + Duration::from_hours(24 * 3);
+    assert!(engine
+      .complete_todo(&todo_id, TodoCompleted::new(date))
+      .is_err());
+    assert_eq!(stats(&engine), before);
+    engine.store.fail_set_todo_due = false;
+    engine
+      .complete_todo(&todo_id, TodoCompleted::new(date))
+      .unwrap();
+    assert_eq!(engine.task_stats(&task_id).unwrap().intervals, 1);
+  }
+
+  #[test]
+  fn failed_clone_is_rolled_back() {
+    let mut engine = AllesattInner::new(SnapshotStore::default());
+    let (task_id, todo_id) = engine.create_task("x".into(), None, None).unwrap();
+    engine
+      .complete_todo(&todo_id, TodoCompleted::new(OffsetDateTime::now_utc()))
+      .unwrap();
+    engine.pause_task(&task_id).unwrap();
+    assert!(engine.clone_task(&task_id, "y".into(), None).is_err());
+    let store = engine.get_store();
+    assert_eq!(store.get_tasks().len(), 1);
+    assert_eq!(store.get_todos(None, None).len(), 1);
+    let (new_task_id, _) = engine.create_task("z".into(), None, None).unwrap();
+    assert_eq!(new_task_id, TaskId::new(2));
+  }
+
+  fn task_with_two_completions() -> AllesattInner<MemStore> {
+    let now = OffsetDateTime::now_utc();
+    let mut engine = AllesattInner::new(MemStore::new());
+    let (_, todo_id) = engine.create_task("x".into(), None, None).unwrap();
+    engine
+      .complete_todo(&todo_id, TodoCompleted::new(now - Duration::from_hours(24)))
+      .unwrap();
+    engine
+      .complete_todo(&TodoId::new(2), TodoCompleted::new(now))
+      .unwrap();
+    engine
+  }
+
+  #[test]
+  fn clone_assigns_ids_in_order() {
+    let mut engine = task_with_two_completions();
+    let (new_task_id, todo_id) = engine
+      .clone_task(&TaskId::new(1), "y".into(), None)
+      .unwrap();
+    assert_eq!((new_task_id, todo_id), (TaskId::new(2), TodoId::new(6)));
+    let store = engine.get_store();
+    let completed = |id| {
+      let todo = store.get_todo(&TodoId::new(id)).unwrap();
+      todo.completed.as_ref().unwrap().date
+    };
+    assert_eq!((completed(4), completed(5)), (completed(1), completed(2)));
+    assert!(completed(4) < completed(5));
+  }
+
+  #[test]
+  fn clone_copies_many_completions() {
+    let start = OffsetDateTime::now_utc() - Duration::from_hours(24 * 100);
+    let mut engine = AllesattInner::new(MemStore::new());
+    let (task_id, _) = engine.create_task("x".into(), None, None).unwrap();
+    for day in 0..100 {
+      let todo_id = engine
+        .get_store()
+        .find_open_todo(&task_id)
+        .unwrap()
+        .id
+        .clone();
+      let date = start + Duration::from_hours(24 * day);
+      engine
+        .complete_todo(&todo_id, TodoCompleted::new(date))
+        .unwrap();
+    }
+    let (_, todo_id) = engine.clone_task(&task_id, "y".into(), None).unwrap();
+    assert_eq!(todo_id, TodoId::new(202));
+    let store = engine.get_store();
+    // The originals are todos 1 to 100 and their copies 102 to 201, in the same order
+    let completion = |id| {
+      let todo = store.get_todo(&TodoId::new(id)).unwrap();
+      (todo.due, todo.completed.as_ref().unwrap().date)
+    };
+    for day in 0..100 {
+      assert_eq!(completion(102 + day), completion(1 + day));
+      assert_eq!(
+        completion(1 + day).1,
+        start + Duration::from_hours(24 * day)
+      );
+    }
+  }
+
+  #[test]
+  fn caps_deferrals_until_rescheduled() {
+    let mut engine = AllesattInner::with_calendar(
+      MemStore::new(),
+      Calendar {
+        max_deferral: Some(time::Duration::days(3)),
+        ..Calendar::default()
+      },
+    );
+    let (_, todo_id) = engine
+      .create_task("x".into(), Some(Duration::from_hours(24 * 30)), None)
+      .unwrap();
+    let original = engine.get_store().get_todo(&todo_id).unwrap().due;
+    let later = |engine: &mut AllesattInner<MemStore>| {
+      engine.todo_later(&todo_id).unwrap();
+      (engine.get_store().get_todo(&todo_id).unwrap().due - original).whole_days()
+    };
+    assert_eq!(later(&mut engine), 3);
+    assert_eq!(later(&mut engine), 3);
+    engine.set_todo_due(&todo_id, original).unwrap();
+    assert_eq!(later(&mut engine), 3);
+    engine
+      .complete_todo(&todo_id, TodoCompleted::new(OffsetDateTime::now_utc()))
+      .unwrap();
+    assert!(engine.deferrals.is_empty());
+  }
+
+  #[test]
+  fn completing_anchor_reschedules_task_after_it() {
+    let mut engine = AllesattInner::new(MemStore::new());
+    let (oil, oil_todo) = engine
+      .create_task(
+        "Change oil".into(),
+        Some(Duration::from_hours(24 * 365)),
+        None,
+      )
+      .unwrap();
+    let (tires, _) = engine
+      .create_task(
+        "Rotate tires".into(),
+        Some(Duration::from_hours(24 * 180)),
+        None,
+      )
+      .unwrap();
+    engine.set_task_after(&tires, Some(oil.clone())).unwrap();
+    assert!(engine.set_task_after(&oil, Some(tires.clone())).is_err());
+    assert!(engine.set_task_after(&oil, Some(oil.clone())).is_err());
+
+    let done = OffsetDateTime::now_utc() + time::Duration::days(10);
+    engine
+      .complete_todo(&oil_todo, TodoCompleted::new(done))
+      .unwrap();
+    let due = engine.get_store().find_open_todo(&tires).unwrap().due;
+    assert_eq!(due, done + time::Duration::days(180));
+    assert_eq!(engine.task_stats(&tires).unwrap().after, Some(oil));
+  }
+
+  #[test]
+  fn due_anchored_tasks_ignore_late_completions() {
+    let mut engine = AllesattInner::new(MemStore::new());
+    let week = Some(Duration::from_hours(24 * 7));
+    let (done, _) = engine.create_task("Water".into(), week, None).unwrap();
+    let (due, _) = engine.create_task("Bins".into(), week, None).unwrap();
+    engine.set_task_from_due(&due, true).unwrap();
+    assert!(engine.task_stats(&due).unwrap().from_due);
+
+    for task_id in [&done, &due] {
+      engine.learn_task(task_id).unwrap();
+      let todo = engine.get_store().find_open_todo(task_id).unwrap().clone();
+      let late = todo.due + time::Duration::days(3);
+      engine
+        .complete_todo(&todo.id, TodoCompleted::new(late))
+        .unwrap();
+      let next = engine.get_store().find_open_todo(task_id).unwrap().due;
+      let anchor = if task_id == &due { todo.due } else { late };
+      assert_eq!(next, anchor + time::Duration::days(7));
+    }
+  }
+
+  #[test]
+  fn reattaching_completion_updates_both_tasks() {
+    let start = OffsetDateTime::now_utc() - time::Duration::days(30);
+    let mut engine = AllesattInner::new(MemStore::new());
+    let complete = |engine: &mut AllesattInner<MemStore>, task_id, days| {
+      let todo_id = engine
+        .get_store()
+        .find_open_todo(task_id)
+        .unwrap()
+        .id
+        .clone();
+      engine
+        .complete_todo(
+          &todo_id,
+          TodoCompleted::new(start + time::Duration::days(days)),
+        )
+        .unwrap();
+      todo_id
+    };
+    let week = Some(Duration::from_hours(24 * 7));
+    let (a, _) = engine.create_task("a".into(), week, None).unwrap();
+    let (b, _) = engine.create_task("b".into(), week, None).unwrap();
+    complete(&mut engine, &a, 0);
+    complete(&mut engine, &a, 7);
+    let wrong = complete(&mut engine, &a, 14);
+    complete(&mut engine, &b, 0);
+    complete(&mut engine, &b, 10);
+    assert_eq!(engine.task_stats(&a).unwrap().intervals, 2);
+    assert_eq!(engine.task_stats(&b).unwrap().intervals, 1);
+
+    let open = engine.get_store().find_open_todo(&a).unwrap().id.clone();
+    assert!(engine.reattach_todo(&open, &b).is_err());
+    assert!(engine.reattach_todo(&wrong, &a).is_err());
+    engine.reattach_todo(&wrong, &b).unwrap();
+    assert_eq!(engine.get_store().get_todo(&wrong).unwrap().task, b);
+    let stats = engine.task_stats(&a).unwrap();
+    assert_eq!(
+      (stats.intervals, stats.mean_interval),
+      (1, time::Duration::days(7))
+    );
+    let stats = engine.task_stats(&b).unwrap();
+    assert_eq!(
+      (stats.intervals, stats.mean_interval),
+      (2, time::Duration::days(7))
+    );
+    let due = |task_id| engine.get_store().find_open_todo(task_id).unwrap().due;
+    assert_eq!(due(&a), start + time::Duration::days(7 + 7));
+    assert_eq!(due(&b), start + time::Duration::days(14 + 7));
+  }
+
+  #[test]
+  fn replayed_clone_uses_logged_open_todo() {
+    let mut engine = task_with_two_completions();
+    let due = engine.get_store().get_todo(&TodoId::new(3)).unwrap().due;
+    engine
+      .replay_clone_task(
+        &TaskId::new(1),
+        "y".into(),
+        None,
+        &(TaskId::new(2), TodoId::new(4)),
+      )
+      .unwrap();
+    let store = engine.get_store();
+    let open = store.find_open_todo(&TaskId::new(2)).unwrap();
+    assert_eq!((&open.id, open.due), (&TodoId::new(4), due));
+    assert_eq!(store.get_todos(Some(&TaskId::new(2)), Some(true)).len(), 2);
+    assert!(engine
+      .replay_clone_task(
+        &TaskId::new(1),
+        "z".into(),
+        None,
+        &(TaskId::new(3), TodoId::new(1))
+      )
+      .is_err());
+  }
+
   #[test]
   fn default_duration_after_pausing() {
     let now = OffsetDateTime::now_utc();
-    let day = Duration::from_secs(60 * 60 * 24);
+    let day = Duration::from_hours(24);
     let mut engine = AllesattInner::new(MemStore::new());
-    let (task_id, todo_id) = engine.create_task("x".into(), Some(day * 7));
+    let (task_id, todo_id) = engine.create_task("x".into(), Some(day * 7), None).unwrap();
     engine
       .complete_todo(&todo_id, TodoCompleted::new(now - day * 28))
       .unwrap();
     engine.pause_task(&task_id).unwrap();
-    let todo_id = engine.unpause_task(&task_id).unwrap();
+    let todo_id = engine.unpause_task(&task_id, None).unwrap();
     let due = engine.get_store().get_todo(&todo_id).unwrap().due;
     assert!(due > now - day);
     assert!(due < now + day);
@@ -234,4 +1517,129 @@ mod tests {
     assert!(due > now - day);
     assert!(due < now + day);
   }
+
+  #[test]
+  fn unpause_at_date() {
+    let now = OffsetDateTime::now_utc();
+    let mut engine = AllesattInner::new(MemStore::new());
+    let (task_id, _) = engine.create_task("x".into(), None, None).unwrap();
+    engine.pause_task(&task_id).unwrap();
+    let at = now + time::Duration::days(7);
+    let todo_id = engine.unpause_task(&task_id, Some(at)).unwrap();
+    assert_eq!(engine.get_store().get_todo(&todo_id).unwrap().due, at);
+  }
+
+  #[test]
+  fn pause_tells_missing_from_paused() {
+    let mut engine = AllesattInner::new(MemStore::new());
+    let (task_id, _) = engine.create_task("x".into(), None, None).unwrap();
+    engine.pause_task(&task_id).unwrap();
+    let error = |engine: &mut AllesattInner<MemStore>, task_id| {
+      *engine
+        .pause_task(&task_id)
+        .unwrap_err()
+        .downcast::<OpenTodoError>()
+        .unwrap()
+    };
+    assert_eq!(
+      error(&mut engine, task_id.clone()),
+      OpenTodoError::Paused(task_id)
+    );
+    assert_eq!(
+      error(&mut engine, TaskId::new(2)),
+      OpenTodoError::TaskNotFound(TaskId::new(2))
+    );
+  }
+
+  #[test]
+  fn unpauses_only_paused_tasks() {
+    let log = "create_task2: [\"Sweep\", \"7days\", 1, 1]\n";
+    let mut log_out = Vec::new();
+    let mut engine = try_new_with_calendar(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+      Calendar::default(),
+    )
+    .unwrap();
+    assert!(engine.unpause_task(&TaskId::new(1), None).is_err());
+    let error = engine.unpause_task(&TaskId::new(99), None).unwrap_err();
+    assert_eq!(
+      *error.downcast::<OpenTodoError>().unwrap(),
+      OpenTodoError::TaskNotFound(TaskId::new(99))
+    );
+    assert_eq!(engine.get_store().validate(), Ok(()));
+    drop(engine);
+    assert!(log_out.is_empty());
+  }
+
+  #[test]
+  fn resumes_from_snapshot() {
+    fn logger(log: &str) -> ReadWriteLogger<&[u8], Vec<u8>, Vec<u8>> {
+      ReadWriteLogger::new(log.as_bytes(), Vec::new())
+    }
+    let log = r#"create_task2: ["Sweep", "7days", 1, 1]
+complete_todo1: [1, "2024-01-01T10:00:00.0", "Used rain water"]
+complete_todo1: [2, "2024-01-09T10:00:00.0"]
+"#;
+    let head = &log[..log.find("complete_todo1: [2").unwrap()];
+    let engine = try_new_with_calendar(MemStore::new(), logger(head), Calendar::default()).unwrap();
+    let mut snapshot = Vec::new();
+    engine.write_snapshot(&mut snapshot).unwrap();
+
+    let resumed =
+      try_from_snapshot::<MemStore>(snapshot.as_slice(), logger(log), Calendar::default())
+        .unwrap()
+        .unwrap();
+    let replayed =
+      try_new_with_calendar(MemStore::new(), logger(log), Calendar::default()).unwrap();
+    assert_eq!(resumed.log_position(), replayed.log_position());
+    let state = |store: &MemStore| {
+      let mut todos: Vec<_> = store
+        .iter_todos(None, None)
+        .map(|todo| {
+          let completed = todo.completed.as_ref();
+          (
+            todo.id.get(),
+            completed.is_none().then_some(todo.due),
+            completed.map(|completed| (completed.date, completed.note.clone())),
+          )
+        })
+        .collect();
+      todos.sort_by_key(|(id, _, _)| *id);
+      (store.get_tasks().len(), todos)
+    };
+    assert_eq!(state(resumed.get_store()), state(replayed.get_store()));
+    let task_id = TaskId::new(1);
+    assert_eq!(resumed.task_stats(&task_id), replayed.task_stats(&task_id));
+
+    let first_line = &log[..=log.find('\n').unwrap()];
+    let changed = log.replace("rain water", "tap water");
+    let renumbered = log.replace("[1,", "[3,").replace("[2,", "[4,");
+    for log in [first_line, &changed, &renumbered] {
+      assert!(
+        try_from_snapshot::<MemStore>(snapshot.as_slice(), logger(log), Calendar::default())
+          .unwrap()
+          .is_none()
+      );
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_intervals() {
+    let mut engine = AllesattInner::new(MemStore::new());
+    assert!(engine
+      .create_task("x".into(), Some(Duration::ZERO), None)
+      .is_err());
+    assert!(engine
+      .create_task("x".into(), Some(Duration::MAX), None)
+      .is_err());
+    assert!(engine
+      .create_task(
+        "x".into(),
+        Some(Duration::from_hours(24 * 365 * 1000)),
+        None
+      )
+      .is_err());
+    assert!(engine.get_store().get_tasks().is_empty());
+  }
 }
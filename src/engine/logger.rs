@@ -1,18 +1,74 @@
-use serde_json::{from_str as from_json, to_string as to_json};
-use std::borrow::BorrowMut;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{from_str as from_json, to_string as to_json, Deserializer, Value};
+use std::borrow::{BorrowMut, Cow};
 use std::error::Error;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::io::{BufRead, BufReader, Lines, Read, Write};
 use std::marker::PhantomData;
+use std::str::FromStr;
 use std::time::Duration;
 
-use super::{Allesatt, TaskId, TodoCompleted, TodoId};
+use super::{Allesatt, LogDate, LogDuration, Store, TaskId, TodoCompleted, TodoDate, TodoId};
+
+/// How far into the log replaying and writing has got
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogPosition {
+  /// Number of records replayed and written so far
+  pub records: usize,
+  /// Length of these records in the log, in bytes
+  pub bytes: u64,
+  /// Hash of these records as they appear in the log, see `hash_record`
+  #[serde(default)]
+  pub hash: u64,
+}
+
+impl LogPosition {
+  /// Counts a record, given as it appears in the log without the line break
+  fn add(&mut self, record: &str) {
+    self.records += 1;
+    self.bytes += record.len() as u64 + 1;
+    self.hash = hash_record(self.hash, record);
+  }
+}
+
+/// Continues the FNV-1a hash `hash` of the log up to a record with the record and its line break
+///
+/// Unlike the hashers of the standard library, this stays the same across versions, so that
+/// snapshots stay valid.
+fn hash_record(hash: u64, record: &str) -> u64 {
+  const PRIME: u64 = 0x0100_0000_01b3;
+  let hash = if hash == 0 {
+    0xcbf2_9ce4_8422_2325
+  } else {
+    hash
+  };
+  record.bytes().chain([b'\n']).fold(hash, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+  })
+}
 
 pub trait Logger {
   fn play_back<A: Allesatt>(&mut self, app: &mut A) -> Result<(), Box<dyn Error>>;
+  /// Skips the records up to `position` without replaying them, e.g. because a snapshot already
+  /// covers them
+  ///
+  /// Returns `false` if the log doesn't start with exactly these records, i.e. if their length
+  /// or hash differ.
+  fn skip(&mut self, _position: &LogPosition) -> Result<bool, Box<dyn Error>> {
+    Err("This logger can't skip records".into())
+  }
+  fn position(&self) -> LogPosition {
+    LogPosition::default()
+  }
+  /// Makes sure the records logged so far are written, e.g. before exiting
+  fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+    Ok(())
+  }
   fn log_create_task(
     &mut self,
     title: &str,
     due_every: &Option<Duration>,
+    created: Option<TodoDate>,
     task_id: &TaskId,
     todo_id: &TodoId,
   ) -> Result<(), Box<dyn Error>>;
@@ -20,6 +76,7 @@ pub trait Logger {
     &mut self,
     task_id: &TaskId,
     title: &str,
+    created: Option<TodoDate>,
     new_task_id: &TaskId,
     todo_id: &TodoId,
   ) -> Result<(), Box<dyn Error>>;
@@ -28,15 +85,198 @@ pub trait Logger {
     todo_id: &TodoId,
     completed: &TodoCompleted,
   ) -> Result<(), Box<dyn Error>>;
+  fn log_amend_completion(
+    &mut self,
+    todo_id: &TodoId,
+    date: &TodoDate,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_reattach_todo(&mut self, todo_id: &TodoId, task_id: &TaskId)
+    -> Result<(), Box<dyn Error>>;
   fn log_todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>>;
+  fn log_undo_todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>>;
+  fn log_postpone_todo(&mut self, todo_id: &TodoId, by: &Duration) -> Result<(), Box<dyn Error>>;
+  fn log_set_todo_due(&mut self, todo_id: &TodoId, due: &TodoDate) -> Result<(), Box<dyn Error>>;
+  fn log_discard_todo(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>>;
+  fn log_set_todo_progress(
+    &mut self,
+    todo_id: &TodoId,
+    done: u32,
+    total: u32,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_touch_task(&mut self, task_id: &TaskId, at: &TodoDate) -> Result<(), Box<dyn Error>>;
   fn log_pause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
-  fn log_unpause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
+  fn log_unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<&TodoDate>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_rename_task(
+    &mut self,
+    task_id: &TaskId,
+    old_title: &str,
+    title: &str,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_effort(
+    &mut self,
+    task_id: &TaskId,
+    effort: &Duration,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_lead_time(
+    &mut self,
+    task_id: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_url(
+    &mut self,
+    task_id: &TaskId,
+    url: &Option<String>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_blocked(
+    &mut self,
+    task_id: &TaskId,
+    reason: &Option<String>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_max_completions(
+    &mut self,
+    task_id: &TaskId,
+    max_completions: &Option<u32>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_yearly(
+    &mut self,
+    task_id: &TaskId,
+    anchor: &Option<TodoDate>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_after(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<&TaskId>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_set_task_from_due(
+    &mut self,
+    task_id: &TaskId,
+    from_due: bool,
+  ) -> Result<(), Box<dyn Error>>;
+  fn log_add_task_alias(&mut self, task_id: &TaskId, alias: &str) -> Result<(), Box<dyn Error>>;
+  fn log_learn_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
+  fn log_unlearn_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>>;
+  fn log_swap_tasks(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>>;
+}
+
+/// How log records are written; both formats can be read back
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+  /// One JSON array of arguments per record, like `complete_todo1: [2, "2024-01-08T10:00:00.0"]`
+  #[default]
+  V1,
+  /// Space-separated arguments, quoted only if needed, like
+  /// `complete_todo1 2 2024-01-08T10:00:00.0`
+  Human,
+}
+
+impl FromStr for LogFormat {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "v1" => Ok(Self::V1),
+      "human" => Ok(Self::Human),
+      _ => Err(format!("Unknown log format {s}, expected v1 or human")),
+    }
+  }
+}
+
+/// Writes an argument of a human-readable record, only quoting strings that need it
+fn human_arg(value: &Value) -> Result<String, serde_json::Error> {
+  match value {
+    Value::String(s)
+      if !s.is_empty()
+        && s
+          .chars()
+          .all(|c| c.is_alphanumeric() || "-+.:/_@".contains(c))
+        && from_json::<Value>(s).is_err() =>
+    {
+      Ok(s.clone())
+    }
+    value => to_json(value),
+  }
+}
+
+/// Converts a record from `LogFormat::V1` to `LogFormat::Human`
+fn to_human(line: &str) -> Result<String, Box<dyn Error>> {
+  let (kind, v) = line.split_once(':').ok_or("Invalid line")?;
+  let args: Vec<Value> = from_json(v)?;
+  let mut human = kind.to_owned();
+  for arg in &args {
+    human.push(' ');
+    human.push_str(&human_arg(arg)?);
+  }
+  Ok(human)
+}
+
+/// Converts a record in either format to `LogFormat::V1`
+pub(super) fn to_v1(line: &str) -> Result<Cow<'_, str>, Box<dyn Error>> {
+  let (kind, mut rest) = line.split_once(' ').unwrap_or((line, ""));
+  if kind.contains(':') {
+    return Ok(Cow::Borrowed(line));
+  }
+  let mut args = vec![];
+  loop {
+    rest = rest.trim_start();
+    if rest.is_empty() {
+      break;
+    }
+    if rest.starts_with(['"', '[', '{']) {
+      let mut values = Deserializer::from_str(rest).into_iter::<Value>();
+      args.push(values.next().ok_or("Invalid line")??);
+      rest = &rest[values.byte_offset()..];
+    } else {
+      let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+      let (arg, tail) = rest.split_at(end);
+      args.push(from_json(arg).unwrap_or_else(|_| Value::String(arg.into())));
+      rest = tail;
+    }
+  }
+  let args = args.iter().map(to_json).collect::<Result<Vec<_>, _>>()?;
+  Ok(Cow::Owned(format!("{kind}: [{}]", args.join(", "))))
+}
+
+/// Writes the log in `source` to `target` in the given format, without replaying it
+pub fn convert_log(
+  source: impl Read,
+  mut target: impl Write,
+  format: LogFormat,
+) -> Result<(), Box<dyn Error>> {
+  for line_result in BufReader::new(source).lines() {
+    let line = line_result?;
+    let v1 = to_v1(&line).map_err(|e| e.to_string() + "\nLine content: " + &line)?;
+    match format {
+      LogFormat::V1 => writeln!(target, "{v1}")?,
+      LogFormat::Human => writeln!(target, "{}", to_human(&v1)?)?,
+    }
+  }
+  Ok(())
+}
+
+/// Called during `play_back` every `every` lines with the number of lines and bytes replayed
+struct Progress {
+  every: usize,
+  report: Box<dyn FnMut(usize, u64)>,
+}
+
+impl Debug for Progress {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    f.debug_struct("Progress")
+      .field("every", &self.every)
+      .finish_non_exhaustive()
+  }
 }
 
 #[derive(Debug)]
 pub struct ReadWriteLogger<R: Read, IW: Write, W: BorrowMut<IW>> {
   source: Lines<BufReader<R>>,
   target: W,
+  progress: Option<Progress>,
+  position: LogPosition,
+  format: LogFormat,
   phantom: PhantomData<IW>,
 }
 
@@ -45,45 +285,246 @@ impl<R: Read, IW: Write, W: BorrowMut<IW>> ReadWriteLogger<R, IW, W> {
     Self {
       source: BufReader::new(source).lines(),
       target,
+      progress: None,
+      position: LogPosition::default(),
+      format: LogFormat::default(),
       phantom: PhantomData::<IW>,
     }
   }
+
+  /// Counts a record and writes it in the configured format
+  fn write_record(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+    let record = match self.format {
+      LogFormat::V1 => line.to_owned(),
+      LogFormat::Human => to_human(line)?,
+    };
+    writeln!(self.target.borrow_mut(), "{record}")?;
+    self.position.add(&record);
+    Ok(())
+  }
+
+  /// Writes new records in the given format
+  #[must_use]
+  pub const fn with_format(mut self, format: LogFormat) -> Self {
+    self.format = format;
+    self
+  }
+
+  /// Reports progress every `every` lines while replaying the log
+  #[must_use]
+  pub fn with_progress(mut self, every: usize, report: impl FnMut(usize, u64) + 'static) -> Self {
+    self.progress = Some(Progress {
+      every: every.max(1),
+      report: Box::new(report),
+    });
+    self
+  }
+}
+
+/// Log commands understood by `parse_line`, including their format version
+pub const LOG_COMMANDS: &[&str] = &[
+  "create_task1",
+  "create_task2",
+  "create_task3",
+  "clone_task1",
+  "clone_task2",
+  "complete_todo1",
+  "amend_completion1",
+  "reattach_todo1",
+  "todo_later1",
+  "undo_todo_later1",
+  "postpone_todo1",
+  "set_todo_due1",
+  "discard_todo1",
+  "set_todo_progress1",
+  "touch_task1",
+  "pause_task1",
+  "unpause_task1",
+  "unpause_task2",
+  "rename_task1",
+  "rename_task2",
+  "set_task_effort1",
+  "set_task_lead_time1",
+  "set_task_url1",
+  "set_task_blocked1",
+  "set_task_max_completions1",
+  "set_task_yearly1",
+  "set_task_after1",
+  "set_task_from_due1",
+  "add_task_alias1",
+  "learn_task1",
+  "unlearn_task1",
+  "swap_tasks1",
+];
+
+/// Makes zero intervals logged by older versions, which accepted them, usable
+///
+/// They made their tasks due again right after completing them, as the shortest interval does.
+pub(super) fn replayed_interval(due_every: Duration) -> Duration {
+  due_every.max(Duration::from_secs(1))
 }
 
-fn parse_line(line: &str, app: &mut impl Allesatt) -> Result<(), Box<dyn Error>> {
-  match line.split_at(line.find(':').ok_or_else(|| String::from("Invalid line"))? + 1) {
+#[allow(clippy::too_many_lines)]
+pub(super) fn parse_line(line: &str, app: &mut impl Allesatt) -> Result<(), Box<dyn Error>> {
+  let line = &*to_v1(line)?;
+  let (kind, v) = line.split_at(line.find(':').ok_or_else(|| String::from("Invalid line"))? + 1);
+  if !LOG_COMMANDS.contains(&&kind[..kind.len() - 1]) {
+    return Err(format!("Unexpected {kind}{v}").into());
+  }
+  match (kind, v) {
     ("create_task1:", v) => {
-      let (title, due_every, task_id, todo_id) = from_json(v)?;
-      if (task_id, todo_id) != app.create_task(title, due_every) {
+      let (title, due_every, task_id, todo_id): (_, Option<Duration>, _, _) = from_json(v)?;
+      let due_every = due_every.map(replayed_interval);
+      if (task_id, todo_id) != app.create_task(title, due_every, None)? {
+        return Err("Mismatch in task or todo ids".into());
+      }
+    }
+    ("create_task2:", v) => {
+      let (title, due_every, task_id, todo_id): (_, Option<LogDuration>, _, _) = from_json(v)?;
+      let due_every = due_every.map(|LogDuration(due_every)| replayed_interval(due_every));
+      if (task_id, todo_id) != app.create_task(title, due_every, None)? {
+        return Err("Mismatch in task or todo ids".into());
+      }
+    }
+    ("create_task3:", v) => {
+      let (title, due_every, LogDate(created), task_id, todo_id): (
+        _,
+        Option<LogDuration>,
+        _,
+        _,
+        _,
+      ) = from_json(v)?;
+      let due_every = due_every.map(|LogDuration(due_every)| replayed_interval(due_every));
+      if (task_id, todo_id) != app.create_task(title, due_every, Some(created))? {
         return Err("Mismatch in task or todo ids".into());
       }
     }
     ("clone_task1:", v) => {
       let (task_id, title, new_task_id, todo_id) = from_json(v)?;
-      let expected_result = (new_task_id, todo_id);
-      let result = app.clone_task(&task_id, title)?;
-      if expected_result != result {
-        return Err(
-          format!("Mismatch in task or todo ids: expected {expected_result:?}, found {result:?}")
-            .into(),
-        );
-      }
+      app.replay_clone_task(&task_id, title, None, &(new_task_id, todo_id))?;
+    }
+    ("clone_task2:", v) => {
+      let (task_id, title, LogDate(created), new_task_id, todo_id) = from_json(v)?;
+      app.replay_clone_task(&task_id, title, Some(created), &(new_task_id, todo_id))?;
     }
     ("complete_todo1:", v) => {
-      let (todo_id, completed) = from_json(v)?;
-      app.complete_todo(&todo_id, completed)?;
+      let (todo_id, completed, note): (TodoId, TodoCompleted, _) = from_json(v)
+        .or_else(|_| from_json(v).map(|(todo_id, completed)| (todo_id, completed, None)))?;
+      app.complete_todo(&todo_id, completed.with_note(note))?;
+    }
+    ("amend_completion1:", v) => {
+      let (todo_id, LogDate(date)): (TodoId, LogDate) = from_json(v)?;
+      app.amend_completion(&todo_id, date)?;
+    }
+    ("reattach_todo1:", v) => {
+      let (todo_id, task_id): (TodoId, TaskId) = from_json(v)?;
+      app.reattach_todo(&todo_id, &task_id)?;
     }
     ("todo_later1:", v) => {
       let (todo_id,): (TodoId,) = from_json(v)?;
       app.todo_later(&todo_id)?;
     }
+    ("undo_todo_later1:", v) => {
+      let (todo_id,): (TodoId,) = from_json(v)?;
+      app.undo_todo_later(&todo_id)?;
+    }
+    ("postpone_todo1:", v) => {
+      let (todo_id, by): (TodoId, Duration) = from_json(v)?;
+      app.postpone_todo(&todo_id, by)?;
+    }
+    ("set_todo_due1:", v) => {
+      let (todo_id, LogDate(due)): (TodoId, LogDate) = from_json(v)?;
+      app.set_todo_due(&todo_id, due)?;
+    }
+    ("discard_todo1:", v) => {
+      let (todo_id,): (TodoId,) = from_json(v)?;
+      app.discard_todo(&todo_id)?;
+    }
+    ("set_todo_progress1:", v) => {
+      let (todo_id, done, total): (TodoId, u32, u32) = from_json(v)?;
+      app.set_todo_progress(&todo_id, done, total)?;
+    }
+    ("touch_task1:", v) => {
+      let (task_id, LogDate(at)): (TaskId, LogDate) = from_json(v)?;
+      app.touch_task(&task_id, at)?;
+    }
     ("pause_task1:", v) => {
       let (task_id,): (TaskId,) = from_json(v)?;
       app.pause_task(&task_id)?;
     }
     ("unpause_task1:", v) => {
       let (task_id,): (TaskId,) = from_json(v)?;
-      app.unpause_task(&task_id)?;
+      app.replay_unpause_task(&task_id, None)?;
+    }
+    ("unpause_task2:", v) => {
+      let (task_id, LogDate(at)): (TaskId, LogDate) = from_json(v)?;
+      app.replay_unpause_task(&task_id, Some(at))?;
+    }
+    ("rename_task1:", v) => {
+      let (task_id, title): (TaskId, String) = from_json(v)?;
+      app.rename_task(&task_id, title)?;
+    }
+    ("rename_task2:", v) => {
+      let (task_id, old_title, title): (TaskId, String, String) = from_json(v)?;
+      let current_title = &app
+        .get_store()
+        .get_task(&task_id)
+        .ok_or("Task not found")?
+        .title;
+      if *current_title != old_title {
+        return Err(
+          format!("Mismatch in task title: expected {old_title:?}, found {current_title:?}").into(),
+        );
+      }
+      app.rename_task(&task_id, title)?;
+    }
+    ("set_task_effort1:", v) => {
+      let (task_id, effort): (TaskId, Duration) = from_json(v)?;
+      app.set_task_effort(&task_id, effort)?;
+    }
+    ("set_task_lead_time1:", v) => {
+      let (task_id, LogDuration(lead_time)): (TaskId, LogDuration) = from_json(v)?;
+      app.set_task_lead_time(&task_id, lead_time)?;
+    }
+    ("set_task_url1:", v) => {
+      let (task_id, url): (TaskId, Option<String>) = from_json(v)?;
+      app.set_task_url(&task_id, url)?;
+    }
+    ("set_task_blocked1:", v) => {
+      let (task_id, reason): (TaskId, Option<String>) = from_json(v)?;
+      app.set_task_blocked(&task_id, reason)?;
+    }
+    ("set_task_max_completions1:", v) => {
+      let (task_id, max_completions): (TaskId, Option<u32>) = from_json(v)?;
+      app.set_task_max_completions(&task_id, max_completions)?;
+    }
+    ("set_task_yearly1:", v) => {
+      let (task_id, anchor): (TaskId, Option<LogDate>) = from_json(v)?;
+      app.set_task_yearly(&task_id, anchor.map(|LogDate(date)| date))?;
+    }
+    ("set_task_after1:", v) => {
+      let (task_id, anchor): (TaskId, Option<TaskId>) = from_json(v)?;
+      app.set_task_after(&task_id, anchor)?;
+    }
+    ("set_task_from_due1:", v) => {
+      let (task_id, from_due): (TaskId, bool) = from_json(v)?;
+      app.set_task_from_due(&task_id, from_due)?;
+    }
+    ("add_task_alias1:", v) => {
+      let (task_id, alias): (TaskId, String) = from_json(v)?;
+      app.add_task_alias(&task_id, alias)?;
+    }
+    ("learn_task1:", v) => {
+      let (task_id,): (TaskId,) = from_json(v)?;
+      app.learn_task(&task_id)?;
+    }
+    ("unlearn_task1:", v) => {
+      let (task_id,): (TaskId,) = from_json(v)?;
+      app.unlearn_task(&task_id)?;
+    }
+    ("swap_tasks1:", v) => {
+      let (a, b): (TaskId, TaskId) = from_json(v)?;
+      app.swap_tasks(&a, &b)?;
     }
     (something, something_else) => {
       return Err(format!("Unexpected {something}:{something_else}").into());
@@ -92,30 +533,76 @@ fn parse_line(line: &str, app: &mut impl Allesatt) -> Result<(), Box<dyn Error>>
   Ok(())
 }
 
+/// Flushes the target, so that records aren't lost in a buffer if `flush` wasn't called
+impl<R: Read, IW: Write, W: BorrowMut<IW>> Drop for ReadWriteLogger<R, IW, W> {
+  fn drop(&mut self) {
+    // There is no way to report the error here, which is why callers should flush explicitly
+    let _ = self.target.borrow_mut().flush();
+  }
+}
+
 impl<R: Read, IW: Write, W: BorrowMut<IW>> Logger for ReadWriteLogger<R, IW, W> {
   fn play_back<A: Allesatt>(&mut self, app: &mut A) -> Result<(), Box<dyn Error>> {
-    for line_result in &mut self.source {
+    for (index, line_result) in (&mut self.source).enumerate() {
       let line = line_result?;
+      if let Some(Progress { every, report }) = &mut self.progress {
+        if (index + 1) % *every == 0 {
+          report(index + 1, self.position.bytes + line.len() as u64 + 1);
+        }
+      }
+      #[cfg(feature = "trace")]
+      let _span = tracing::debug_span!("play_back", %line).entered();
       parse_line(&line, app).map_err(|e| e.to_string() + "\nLine content: " + &line)?;
+      self.position.add(&line);
     }
     Ok(())
   }
 
+  fn skip(&mut self, position: &LogPosition) -> Result<bool, Box<dyn Error>> {
+    // The records are read and hashed, which is still much faster than replaying them
+    while self.position.bytes < position.bytes {
+      let Some(line) = self.source.next() else {
+        return Ok(false);
+      };
+      self.position.add(&line?);
+    }
+    Ok(self.position == *position)
+  }
+
+  fn position(&self) -> LogPosition {
+    self.position.clone()
+  }
+
+  fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+    Ok(self.target.borrow_mut().flush()?)
+  }
+
   fn log_clone_task(
     &mut self,
     task_id: &TaskId,
     title: &str,
+    created: Option<TodoDate>,
     new_task_id: &TaskId,
     todo_id: &TodoId,
   ) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "clone_task1: [{}, {}, {}, {}]",
-      to_json(task_id)?,
-      to_json(title)?,
-      to_json(new_task_id)?,
-      to_json(todo_id)?
-    )?;
+    if let Some(created) = created {
+      self.write_record(&format!(
+        "clone_task2: [{}, {}, {}, {}, {}]",
+        to_json(task_id)?,
+        to_json(title)?,
+        to_json(&LogDate(created))?,
+        to_json(new_task_id)?,
+        to_json(todo_id)?
+      ))?;
+    } else {
+      self.write_record(&format!(
+        "clone_task1: [{}, {}, {}, {}]",
+        to_json(task_id)?,
+        to_json(title)?,
+        to_json(new_task_id)?,
+        to_json(todo_id)?
+      ))?;
+    }
     Ok(())
   }
 
@@ -123,17 +610,28 @@ impl<R: Read, IW: Write, W: BorrowMut<IW>> Logger for ReadWriteLogger<R, IW, W>
     &mut self,
     title: &str,
     due_every: &Option<Duration>,
+    created: Option<TodoDate>,
     task_id: &TaskId,
     todo_id: &TodoId,
   ) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "create_task1: [{}, {}, {}, {}]",
-      to_json(title)?,
-      to_json(due_every)?,
-      to_json(task_id)?,
-      to_json(todo_id)?
-    )?;
+    if let Some(created) = created {
+      self.write_record(&format!(
+        "create_task3: [{}, {}, {}, {}, {}]",
+        to_json(title)?,
+        to_json(&due_every.map(LogDuration))?,
+        to_json(&LogDate(created))?,
+        to_json(task_id)?,
+        to_json(todo_id)?
+      ))?;
+    } else {
+      self.write_record(&format!(
+        "create_task2: [{}, {}, {}, {}]",
+        to_json(title)?,
+        to_json(&due_every.map(LogDuration))?,
+        to_json(task_id)?,
+        to_json(todo_id)?
+      ))?;
+    }
     Ok(())
   }
 
@@ -142,39 +640,691 @@ impl<R: Read, IW: Write, W: BorrowMut<IW>> Logger for ReadWriteLogger<R, IW, W>
     todo_id: &TodoId,
     completed: &TodoCompleted,
   ) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "complete_todo1: [{}, {}]",
-      to_json(todo_id)?,
-      to_json(completed)?
-    )?;
+    if let Some(note) = &completed.note {
+      self.write_record(&format!(
+        "complete_todo1: [{}, {}, {}]",
+        to_json(todo_id)?,
+        to_json(completed)?,
+        to_json(note)?
+      ))?;
+    } else {
+      self.write_record(&format!(
+        "complete_todo1: [{}, {}]",
+        to_json(todo_id)?,
+        to_json(completed)?
+      ))?;
+    }
     Ok(())
   }
 
   fn log_todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "todo_later1: [{}]",
+    self.write_record(&format!("todo_later1: [{}]", to_json(todo_id)?))?;
+    Ok(())
+  }
+
+  fn log_undo_todo_later(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!("undo_todo_later1: [{}]", to_json(todo_id)?))?;
+    Ok(())
+  }
+
+  fn log_postpone_todo(&mut self, todo_id: &TodoId, by: &Duration) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "postpone_todo1: [{}, {}]",
+      to_json(todo_id)?,
+      to_json(by)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_todo_due(&mut self, todo_id: &TodoId, due: &TodoDate) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_todo_due1: [{}, {}]",
+      to_json(todo_id)?,
+      to_json(&LogDate(*due))?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_todo_progress(
+    &mut self,
+    todo_id: &TodoId,
+    done: u32,
+    total: u32,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_todo_progress1: [{}, {done}, {total}]",
       to_json(todo_id)?
-    )?;
+    ))?;
+    Ok(())
+  }
+
+  fn log_discard_todo(&mut self, todo_id: &TodoId) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!("discard_todo1: [{}]", to_json(todo_id)?))?;
+    Ok(())
+  }
+
+  fn log_touch_task(&mut self, task_id: &TaskId, at: &TodoDate) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "touch_task1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(&LogDate(*at))?
+    ))?;
     Ok(())
   }
 
   fn log_pause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "pause_task1: [{}]",
+    self.write_record(&format!("pause_task1: [{}]", to_json(task_id)?))?;
+    Ok(())
+  }
+
+  fn log_unpause_task(
+    &mut self,
+    task_id: &TaskId,
+    at: Option<&TodoDate>,
+  ) -> Result<(), Box<dyn Error>> {
+    if let Some(at) = at {
+      self.write_record(&format!(
+        "unpause_task2: [{}, {}]",
+        to_json(task_id)?,
+        to_json(&LogDate(*at))?
+      ))?;
+    } else {
+      self.write_record(&format!("unpause_task1: [{}]", to_json(task_id)?))?;
+    }
+    Ok(())
+  }
+
+  fn log_rename_task(
+    &mut self,
+    task_id: &TaskId,
+    old_title: &str,
+    title: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "rename_task2: [{}, {}, {}]",
+      to_json(task_id)?,
+      to_json(old_title)?,
+      to_json(title)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_effort(
+    &mut self,
+    task_id: &TaskId,
+    effort: &Duration,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_effort1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(effort)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_lead_time(
+    &mut self,
+    task_id: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_lead_time1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(&LogDuration(lead_time))?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_url(
+    &mut self,
+    task_id: &TaskId,
+    url: &Option<String>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_url1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(url)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_blocked(
+    &mut self,
+    task_id: &TaskId,
+    reason: &Option<String>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_blocked1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(reason)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_max_completions(
+    &mut self,
+    task_id: &TaskId,
+    max_completions: &Option<u32>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_max_completions1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(max_completions)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_add_task_alias(&mut self, task_id: &TaskId, alias: &str) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "add_task_alias1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(alias)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_yearly(
+    &mut self,
+    task_id: &TaskId,
+    anchor: &Option<TodoDate>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_yearly1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(&anchor.map(LogDate))?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_after(
+    &mut self,
+    task_id: &TaskId,
+    anchor: Option<&TaskId>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_after1: [{}, {}]",
+      to_json(task_id)?,
+      to_json(&anchor)?
+    ))?;
+    Ok(())
+  }
+
+  fn log_set_task_from_due(
+    &mut self,
+    task_id: &TaskId,
+    from_due: bool,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "set_task_from_due1: [{}, {from_due}]",
       to_json(task_id)?
-    )?;
+    ))?;
     Ok(())
   }
 
-  fn log_unpause_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
-    writeln!(
-      self.target.borrow_mut(),
-      "unpause_task1: [{}]",
+  fn log_learn_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!("learn_task1: [{}]", to_json(task_id)?))?;
+    Ok(())
+  }
+
+  fn log_unlearn_task(&mut self, task_id: &TaskId) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!("unlearn_task1: [{}]", to_json(task_id)?))?;
+    Ok(())
+  }
+
+  fn log_swap_tasks(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!("swap_tasks1: [{}, {}]", to_json(a)?, to_json(b)?))?;
+    Ok(())
+  }
+
+  fn log_amend_completion(
+    &mut self,
+    todo_id: &TodoId,
+    date: &TodoDate,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "amend_completion1: [{}, {}]",
+      to_json(todo_id)?,
+      to_json(&LogDate(*date))?
+    ))?;
+    Ok(())
+  }
+
+  fn log_reattach_todo(
+    &mut self,
+    todo_id: &TodoId,
+    task_id: &TaskId,
+  ) -> Result<(), Box<dyn Error>> {
+    self.write_record(&format!(
+      "reattach_todo1: [{}, {}]",
+      to_json(todo_id)?,
       to_json(task_id)?
-    )?;
+    ))?;
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::{convert_log, parse_line, LogFormat, LOG_COMMANDS};
+  use crate::engine::{
+    try_new, Allesatt, Logger, MemStore, ReadWriteLogger, Store, TaskId, TodoCompleted, TodoDate,
+    TodoId,
+  };
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use std::time::Duration;
+  use time::macros::datetime;
+
+  const CREATE: &str = "create_task1: [\"Old\", null, 1, 1]\n";
+
+  const V1_LOG: &str = r#"create_task1: ["Old", {"nanos":0,"secs":86400}, 1, 1]
+create_task3: ["Water \"the\" plants", "3days", "2024-01-01T00:00:00.0", 2, 2]
+complete_todo1: [2, "2024-01-01T10:00:00.0", "Used rain water"]
+postpone_todo1: [3, {"nanos":0,"secs":86400}]
+rename_task2: [1, "Old", "42"]
+add_task_alias1: [2, "plants"]
+create_task2: ["", null, 3, 4]
+"#;
+
+  const HUMAN_LOG: &str = r#"create_task1 Old {"nanos":0,"secs":86400} 1 1
+create_task3 "Water \"the\" plants" 3days 2024-01-01T00:00:00.0 2 2
+complete_todo1 2 2024-01-01T10:00:00.0 "Used rain water"
+postpone_todo1 3 {"nanos":0,"secs":86400}
+rename_task2 1 Old "42"
+add_task_alias1 2 plants
+create_task2 "" null 3 4
+"#;
+
+  fn convert(log: &str, format: LogFormat) -> String {
+    let mut converted = Vec::new();
+    convert_log(log.as_bytes(), &mut converted, format).unwrap();
+    String::from_utf8(converted).unwrap()
+  }
+
+  #[test]
+  fn converts_log_formats() {
+    assert_eq!(convert(V1_LOG, LogFormat::Human), HUMAN_LOG);
+    assert_eq!(convert(HUMAN_LOG, LogFormat::V1), V1_LOG);
+    assert_eq!(convert(HUMAN_LOG, LogFormat::Human), HUMAN_LOG);
+    assert_eq!(convert(V1_LOG, LogFormat::V1), V1_LOG);
+  }
+
+  #[test]
+  fn replays_and_writes_human_format() {
+    let titles = |log: &str| {
+      let app = try_new(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+      )
+      .unwrap();
+      let titles: Vec<_> = app
+        .get_store()
+        .get_tasks()
+        .iter()
+        .map(|task| (task.title.clone(), task.aliases.clone()))
+        .collect();
+      titles
+    };
+    assert_eq!(titles(HUMAN_LOG), titles(V1_LOG));
+
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(HUMAN_LOG.as_bytes(), &mut log_out)
+        .with_format(LogFormat::Human),
+    )
+    .unwrap();
+    app.rename_task(&TaskId::new(3), "Dust off".into()).unwrap();
+    drop(app);
+    assert_eq!(
+      String::from_utf8(log_out).unwrap(),
+      "rename_task2 3 \"\" \"Dust off\"\n"
+    );
+  }
+
+  #[test]
+  fn parses_all_log_commands() {
+    for command in LOG_COMMANDS {
+      let mut app = try_new(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(&b""[..], Vec::new()),
+      )
+      .unwrap();
+      let error = parse_line(&format!("{command}: []"), &mut app).unwrap_err();
+      assert!(!error.to_string().starts_with("Unexpected"), "{command}");
+    }
+  }
+
+  /// Keeps written bytes to itself until they are flushed
+  struct Unflushed<'a> {
+    buffer: Vec<u8>,
+    flushed: &'a mut Vec<u8>,
+  }
+
+  impl std::io::Write for Unflushed<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.buffer.extend_from_slice(buf);
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      self.flushed.append(&mut self.buffer);
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn flushes_when_dropped() {
+    let mut flushed = Vec::new();
+    let target = Unflushed {
+      buffer: Vec::new(),
+      flushed: &mut flushed,
+    };
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Unflushed<'_>, _>::new(&b""[..], target),
+    )
+    .unwrap();
+    app.create_task("Sweep".into(), None, None).unwrap();
+    drop(app);
+    assert_eq!(
+      String::from_utf8(flushed).unwrap(),
+      "create_task2: [\"Sweep\", null, 1, 1]\n"
+    );
+  }
+
+  #[test]
+  fn store_changes_are_not_logged() {
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(CREATE.as_bytes(), &mut log_out),
+    )
+    .unwrap();
+    let task_id = TaskId::new(1);
+    app
+      .get_store_mut()
+      .set_task_url(&task_id, Some("https://example.org".into()))
+      .unwrap();
+    assert!(app.get_store().get_task(&task_id).unwrap().url.is_some());
+    drop(app);
+    assert!(log_out.is_empty());
+  }
+
+  #[test]
+  fn replays_rename() {
+    let log = String::from(CREATE) + "rename_task2: [1, \"Old\", \"New\"]\n";
+    let mut log_out = Vec::new();
+    let app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    )
+    .unwrap();
+    assert_eq!(
+      app.get_store().get_task(&TaskId::new(1)).unwrap().title,
+      "New"
+    );
+  }
+
+  #[test]
+  fn rejects_rename_with_mismatching_title() {
+    let log = String::from(CREATE) + "rename_task2: [1, \"Other\", \"New\"]\n";
+    let mut log_out = Vec::new();
+    let result = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    );
+    assert!(result.is_err());
+  }
+
+  fn guess_next_due(log: &str) -> TodoDate {
+    let app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    app.guess_next_due(&"1".parse().unwrap(), datetime!(2024-01-01 0:00 UTC))
+  }
+
+  #[test]
+  fn reads_intervals_as_struct() {
+    let log = "create_task1: [\"Task\", {\"secs\":2592000,\"nanos\":0}, 1, 1]\n";
+    assert_eq!(guess_next_due(log), datetime!(2024-01-31 0:00 UTC));
+  }
+
+  #[test]
+  fn reports_flush_errors() {
+    struct FailingFlush;
+    impl std::io::Write for FailingFlush {
+      fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(buf.len())
+      }
+      fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::Error::other("disk full"))
+      }
+    }
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, FailingFlush, _>::new(&b""[..], FailingFlush),
+    )
+    .unwrap();
+    app.create_task("Task".into(), None, None).unwrap();
+    assert_eq!(app.flush_log().unwrap_err().to_string(), "disk full");
+  }
+
+  #[test]
+  fn replays_zero_intervals() {
+    let log = "create_task1: [\"Old\", {\"secs\":0,\"nanos\":0}, 1, 1]
+create_task2: [\"Older\", \"0s\", 2, 2]
+complete_todo1: [1, \"2024-01-01T10:00:00.0\"]
+";
+    let app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let todo = app.get_store().find_open_todo(&TaskId::new(1)).unwrap();
+    assert_eq!(todo.due, datetime!(2024-01-01 10:00:01 UTC));
+  }
+
+  #[test]
+  fn counts_records() {
+    let log = String::from(CREATE) + "rename_task2: [1, \"Old\", \"New\"]\n";
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    )
+    .unwrap();
+    assert_eq!(app.log_position().records, 2);
+    app.pause_task(&TaskId::new(1)).unwrap();
+    app.unpause_task(&TaskId::new(1), None).unwrap();
+    let position = app.log_position();
+    drop(app);
+    assert_eq!(position.records, 4);
+    assert_eq!(position.bytes, (log.len() + log_out.len()) as u64);
+    let full = log + &String::from_utf8(log_out).unwrap();
+    let hash = full.lines().fold(0, super::hash_record);
+    assert_eq!(position.hash, hash);
+  }
+
+  #[test]
+  fn skips_only_unchanged_records() {
+    let log = String::from(CREATE) + "rename_task2: [1, \"Old\", \"New\"]\n";
+    let position = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+    )
+    .unwrap()
+    .log_position();
+    let skip = |log: &str| {
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new())
+        .skip(&position)
+        .unwrap()
+    };
+    assert!(skip(&log));
+    assert!(skip(&(log.clone() + "pause_task1: [1]\n")));
+    // Same length and same last record, but an earlier one changed
+    assert!(!skip(&log.replacen("Old", "Olf", 1)));
+    assert!(!skip(CREATE));
+  }
+
+  #[test]
+  fn writes_intervals_as_string() {
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(&b""[..], &mut log_out),
+    )
+    .unwrap();
+    app
+      .create_task(
+        "Task".into(),
+        Some(Duration::from_hours(720)),
+        Some(datetime!(2024-01-01 0:00 UTC)),
+      )
+      .unwrap();
+    drop(app);
+    let log = std::str::from_utf8(&log_out).unwrap();
+    assert_eq!(
+      log,
+      "create_task3: [\"Task\", \"30days\", \"2024-01-01T00:00:00.0\", 1, 1]\n"
+    );
+    assert_eq!(guess_next_due(log), datetime!(2024-01-31 0:00 UTC));
+    assert_eq!(
+      guess_next_due("create_task2: [\"Task\", \"1day 12h\", 1, 1]\n"),
+      datetime!(2024-01-02 12:00 UTC)
+    );
+  }
+
+  #[test]
+  fn reports_progress() {
+    let log = (1..=5).fold(String::new(), |log, i| {
+      log + &format!("create_task2: [\"Task {i}\", null, {i}, {i}]\n")
+    });
+    let reported = Rc::new(RefCell::new(vec![]));
+    let reported_clone = Rc::clone(&reported);
+    try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()).with_progress(
+        2,
+        move |lines, bytes| {
+          reported_clone.borrow_mut().push((lines, bytes));
+        },
+      ),
+    )
+    .unwrap();
+    assert_eq!(*reported.borrow(), [(2, 74), (4, 148)]);
+  }
+
+  #[test]
+  fn derives_creation_date() {
+    let log = String::from(CREATE)
+      + "complete_todo1: [1, \"2024-01-01T10:00:00.0\"]\n"
+      + "create_task3: [\"New\", null, \"2023-12-01T00:00:00.0\", 2, 3]\n";
+    let app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+    )
+    .unwrap();
+    let created = |id: &str| {
+      app
+        .get_store()
+        .get_task(&id.parse().unwrap())
+        .unwrap()
+        .created
+    };
+    assert_eq!(created("1"), datetime!(2024-01-01 10:00 UTC));
+    assert_eq!(created("2"), datetime!(2023-12-01 0:00 UTC));
+  }
+
+  #[test]
+  fn logs_clone_creation_date() {
+    let log = "create_task3: [\"Old\", null, \"2023-12-01T00:00:00.0\", 1, 1]\n";
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    )
+    .unwrap();
+    let created = Some(datetime!(2024-01-01 10:00 UTC));
+    app
+      .clone_task(&TaskId::new(1), "New".into(), created)
+      .unwrap();
+    drop(app);
+    let clone = String::from_utf8(log_out).unwrap();
+    assert_eq!(
+      clone,
+      "clone_task2: [1, \"New\", \"2024-01-01T10:00:00.0\", 2, 2]\n"
+    );
+
+    // Logs from before clones recorded their creation date fall back to the original's
+    let old_clone = "clone_task1: [1, \"New\", 2, 2]\n";
+    for (clone, created) in [
+      (&*clone, datetime!(2024-01-01 10:00 UTC)),
+      (old_clone, datetime!(2023-12-01 0:00 UTC)),
+    ] {
+      let log = String::from(log) + clone;
+      let app = try_new(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+      )
+      .unwrap();
+      let task = app.get_store().get_task(&TaskId::new(2)).unwrap();
+      assert_eq!(task.created, created);
+    }
+  }
+
+  #[test]
+  fn replays_completion_notes() {
+    let log = String::from(CREATE)
+      + "complete_todo1: [1, \"2024-01-01T10:00:00.0\"]\n"
+      + "complete_todo1: [2, \"2024-01-02T10:00:00.0\", \"Used the backup key\"]\n";
+    let mut log_out = Vec::new();
+    let mut app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    )
+    .unwrap();
+    let store = app.get_store();
+    let note = |id| {
+      store
+        .get_todo(&TodoId::new(id))
+        .unwrap()
+        .completed
+        .as_ref()
+        .unwrap()
+        .note
+        .clone()
+    };
+    assert_eq!(note(1), None);
+    assert_eq!(note(2).as_deref(), Some("Used the backup key"));
+
+    app
+      .complete_todo(
+        &TodoId::new(3),
+        TodoCompleted::new(datetime!(2024-01-03 10:00 UTC)).with_note(Some("Again".into())),
+      )
+      .unwrap();
+    drop(app);
+    assert_eq!(
+      std::str::from_utf8(&log_out).unwrap(),
+      "complete_todo1: [3, \"2024-01-03T10:00:00.0\", \"Again\"]\n"
+    );
+  }
+
+  #[test]
+  fn rejects_completing_todo_twice() {
+    let log = String::from(CREATE)
+      + "complete_todo1: [1, \"2024-01-01T10:00:00.0\"]\n"
+      + "complete_todo1: [1, \"2024-01-02T10:00:00.0\"]\n";
+    let mut log_out = Vec::new();
+    let result = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), &mut log_out),
+    );
+    let error = result.err().unwrap().to_string();
+    assert!(error.contains("already completed"), "{error}");
+    assert!(error.ends_with("2024-01-02T10:00:00.0\"]"), "{error}");
+  }
+}
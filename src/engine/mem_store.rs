@@ -1,23 +1,27 @@
-use std::collections::HashMap;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::time::Duration;
 
 use super::{Store, Task, TaskId, Todo, TodoCompleted, TodoDate, TodoId};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MemStore {
-  tasks: HashMap<TaskId, Task>,
+  tasks: BTreeMap<TaskId, Task>,
   last_task_id: TaskId,
   todos: HashMap<TodoId, Todo>,
+  open_todos: HashMap<TaskId, TodoId>,
   last_todo_id: TodoId,
 }
 
 impl Default for MemStore {
   fn default() -> Self {
     Self {
-      last_task_id: TaskId(0),
-      tasks: HashMap::default(),
-      last_todo_id: TodoId(0),
+      last_task_id: TaskId::new(0),
+      tasks: BTreeMap::default(),
+      last_todo_id: TodoId::new(0),
       todos: HashMap::default(),
+      open_todos: HashMap::default(),
     }
   }
 }
@@ -30,32 +34,139 @@ impl MemStore {
 }
 
 impl Store for MemStore {
-  fn create_task(&mut self, title: String) -> TaskId {
-    self.last_task_id = TaskId(self.last_task_id.0 + 1);
+  fn create_task(&mut self, title: String, created: TodoDate) -> TaskId {
+    self.last_task_id = TaskId::new(self.last_task_id.get() + 1);
     let task = Task {
       id: self.last_task_id.clone(),
       title,
+      effort: Duration::ZERO,
+      lead_time: Duration::ZERO,
+      max_completions: None,
+      url: None,
+      aliases: vec![],
+      blocked: None,
+      created,
     };
     self.tasks.insert(self.last_task_id.clone(), task);
     self.last_task_id.clone()
   }
 
   fn create_todo(&mut self, task: &TaskId, due: TodoDate) -> TodoId {
-    if let Some(other) = self.find_open_todo(task) {
-      panic!("Already has an open todo for {task:?} ({other:?})");
-    }
-    self.last_todo_id = TodoId(self.last_todo_id.0 + 1);
+    self.last_todo_id = TodoId::new(self.last_todo_id.get() + 1);
     let todo = Todo {
       id: self.last_todo_id.clone(),
       task: task.clone(),
       completed: None,
       due,
+      progress: None,
     };
     self.todos.insert(self.last_todo_id.clone(), todo);
+    self
+      .open_todos
+      .insert(task.clone(), self.last_todo_id.clone());
     self.last_todo_id.clone()
   }
+  fn set_task_title(&mut self, task: &TaskId, title: String) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.title = title;
+    Ok(())
+  }
+
+  fn set_task_effort(&mut self, task: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.effort = effort;
+    Ok(())
+  }
+
+  fn set_task_lead_time(
+    &mut self,
+    task: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.lead_time = lead_time;
+    Ok(())
+  }
+
+  fn set_task_url(&mut self, task: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.url = url;
+    Ok(())
+  }
+
+  fn set_task_created(&mut self, task: &TaskId, created: TodoDate) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.created = created;
+    Ok(())
+  }
+
+  fn set_task_blocked(
+    &mut self,
+    task: &TaskId,
+    blocked: Option<String>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.tasks.get_mut(task).ok_or("Task not found")?.blocked = blocked;
+    Ok(())
+  }
+
+  fn set_task_max_completions(
+    &mut self,
+    task: &TaskId,
+    max_completions: Option<u32>,
+  ) -> Result<(), Box<dyn Error>> {
+    self
+      .tasks
+      .get_mut(task)
+      .ok_or("Task not found")?
+      .max_completions = max_completions;
+    Ok(())
+  }
+
+  fn add_task_alias(&mut self, task: &TaskId, alias: String) -> Result<(), Box<dyn Error>> {
+    self
+      .tasks
+      .get_mut(task)
+      .ok_or("Task not found")?
+      .aliases
+      .push(alias);
+    Ok(())
+  }
+
+  fn swap_task_ids(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>> {
+    if !self.tasks.contains_key(a) || !self.tasks.contains_key(b) {
+      return Err("Task not found".into());
+    }
+    if a == b {
+      return Ok(());
+    }
+    let mut task_a = self.tasks.remove(a).unwrap();
+    let mut task_b = self.tasks.remove(b).unwrap();
+    task_a.id = b.clone();
+    task_b.id = a.clone();
+    self.tasks.insert(a.clone(), task_b);
+    self.tasks.insert(b.clone(), task_a);
+    for todo in self.todos.values_mut() {
+      if todo.task == *a {
+        todo.task = b.clone();
+      } else if todo.task == *b {
+        todo.task = a.clone();
+      }
+    }
+    let open_a = self.open_todos.remove(a);
+    let open_b = self.open_todos.remove(b);
+    self.open_todos.extend(open_a.map(|todo| (b.clone(), todo)));
+    self.open_todos.extend(open_b.map(|todo| (a.clone(), todo)));
+    Ok(())
+  }
+
   fn delete_todo(&mut self, todo: &TodoId) -> Result<(), Box<dyn Error>> {
-    self.todos.remove(todo).ok_or("Todo not found")?;
+    let todo = self.todos.remove(todo).ok_or("Todo not found")?;
+    if self.open_todos.get(&todo.task) == Some(&todo.id) {
+      self.open_todos.remove(&todo.task);
+      // Only tasks with several open todos, which are being repaired, have another one
+      let other = self
+        .iter_todos(Some(&todo.task), Some(false))
+        .min_by_key(|other| (other.due, other.id.get()))
+        .map(|other| other.id.clone());
+      self
+        .open_todos
+        .extend(other.map(|other| (todo.task, other)));
+    }
     Ok(())
   }
 
@@ -72,7 +183,13 @@ impl Store for MemStore {
     id: &TodoId,
     completed: Option<TodoCompleted>,
   ) -> Result<(), Box<dyn Error>> {
-    self.todos.get_mut(id).ok_or("Todo not found")?.completed = completed;
+    let todo = self.todos.get_mut(id).ok_or("Todo not found")?;
+    if completed.is_none() {
+      self.open_todos.insert(todo.task.clone(), id.clone());
+    } else if self.open_todos.get(&todo.task) == Some(id) {
+      self.open_todos.remove(&todo.task);
+    }
+    todo.completed = completed;
     Ok(())
   }
 
@@ -81,29 +198,194 @@ impl Store for MemStore {
     Ok(())
   }
 
-  fn get_todos(
-    &self,
+  fn set_todo_task(&mut self, id: &TodoId, task: &TaskId) -> Result<(), Box<dyn Error>> {
+    if !self.tasks.contains_key(task) {
+      return Err("Task not found".into());
+    }
+    let todo = self.todos.get_mut(id).ok_or("Todo not found")?;
+    if todo.completed.is_none() {
+      return Err("Only completed todos can be moved to another task".into());
+    }
+    todo.task = task.clone();
+    Ok(())
+  }
+
+  fn set_todo_progress(
+    &mut self,
+    id: &TodoId,
+    progress: Option<(u32, u32)>,
+  ) -> Result<(), Box<dyn Error>> {
+    self.todos.get_mut(id).ok_or("Todo not found")?.progress = progress;
+    Ok(())
+  }
+
+  fn iter_todos<'a>(
+    &'a self,
     task_id_filter: Option<&TaskId>,
     completed_filter: Option<bool>,
-  ) -> Vec<&Todo> {
-    self
-      .todos
-      .values()
-      .filter(|todo| {
-        task_id_filter.map_or(true, |task_id| *task_id == todo.task)
-          && completed_filter.map_or(true, |completed| completed == todo.completed.is_some())
-      })
-      .collect()
+  ) -> impl Iterator<Item = &'a Todo> + 'a {
+    let task_id_filter = task_id_filter.cloned();
+    self.todos.values().filter(move |todo| {
+      task_id_filter
+        .as_ref()
+        .is_none_or(|task_id| *task_id == todo.task)
+        && completed_filter.is_none_or(|completed| completed == todo.completed.is_some())
+    })
   }
 
   fn find_open_todo(&self, task: &TaskId) -> Option<&Todo> {
-    self
-      .todos
-      .values()
-      .find(|todo| todo.task == *task && todo.completed.is_none())
+    self.todos.get(self.open_todos.get(task)?)
   }
 
   fn get_tasks(&self) -> Vec<&Task> {
     self.tasks.values().collect()
   }
+
+  fn open_todos(&self) -> Vec<(&Todo, &Task)> {
+    self
+      .tasks
+      .values()
+      .filter_map(|task| Some((self.todos.get(self.open_todos.get(&task.id)?)?, task)))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::MemStore;
+  use crate::engine::{Inconsistency, Store, TaskId, TodoCompleted, TodoId};
+  use time::{Duration, OffsetDateTime};
+
+  #[test]
+  fn get_tasks_is_ordered_by_id() {
+    let mut store = MemStore::new();
+    for i in 0..20 {
+      store.create_task(format!("Task {i}"), OffsetDateTime::UNIX_EPOCH);
+    }
+    let ids: Vec<_> = store
+      .get_tasks()
+      .into_iter()
+      .map(|t| t.id.clone())
+      .collect();
+    assert_eq!(ids, (1..=20).map(TaskId::new).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn validates_invariants() {
+    let mut store = MemStore::new();
+    let now = OffsetDateTime::now_utc();
+    let task = store.create_task("Task".into(), OffsetDateTime::UNIX_EPOCH);
+    store.create_todo(&task, now);
+    assert_eq!(store.validate(), Ok(()));
+
+    store.create_todo(&task, now);
+    store.create_todo(&TaskId::new(7), now);
+    assert_eq!(
+      store.validate(),
+      Err(vec![
+        Inconsistency::OrphanTodo(TodoId::new(3), TaskId::new(7)),
+        Inconsistency::SeveralOpenTodos(task, vec![TodoId::new(1), TodoId::new(2)]),
+      ])
+    );
+  }
+
+  #[test]
+  fn keeps_open_todos_index() {
+    let mut store = MemStore::new();
+    let now = OffsetDateTime::now_utc();
+    let task1 = store.create_task("Task 1".into(), OffsetDateTime::UNIX_EPOCH);
+    let task2 = store.create_task("Task 2".into(), OffsetDateTime::UNIX_EPOCH);
+    store.create_todo(&task2, now);
+    let check = |store: &MemStore| {
+      for task in [&task1, &task2] {
+        let scanned = store
+          .iter_todos(Some(task), Some(false))
+          .map(|todo| &todo.id)
+          .next();
+        assert_eq!(store.find_open_todo(task).map(|todo| &todo.id), scanned);
+      }
+    };
+    for _ in 0..3 {
+      // Do
+      let todo = store.create_todo(&task1, now);
+      check(&store);
+      store
+        .set_todo_completed(&todo, Some(TodoCompleted::new(now)))
+        .unwrap();
+      check(&store);
+      // Pause and unpause
+      let todo = store.create_todo(&task1, now);
+      store.delete_todo(&todo).unwrap();
+      check(&store);
+    }
+    let todo = store
+      .iter_todos(Some(&task1), Some(true))
+      .next()
+      .unwrap()
+      .id
+      .clone();
+    store.set_todo_completed(&todo, None).unwrap();
+    check(&store);
+    assert!(store.find_open_todo(&task1).is_some());
+    let open_todos: Vec<_> = store
+      .open_todos()
+      .into_iter()
+      .map(|(todo, task)| (todo.id.clone(), task.id.clone()))
+      .collect();
+    assert_eq!(
+      open_todos,
+      [&task1, &task2].map(|task| (store.find_open_todo(task).unwrap().id.clone(), task.clone()))
+    );
+  }
+
+  #[test]
+  fn iter_todos_filters() {
+    let mut store = MemStore::new();
+    let now = OffsetDateTime::now_utc();
+    let task1 = store.create_task("Task 1".into(), OffsetDateTime::UNIX_EPOCH);
+    let task2 = store.create_task("Task 2".into(), OffsetDateTime::UNIX_EPOCH);
+    for _ in 0..3 {
+      let todo = store.create_todo(&task1, now);
+      store
+        .set_todo_completed(&todo, Some(TodoCompleted::new(now)))
+        .unwrap();
+    }
+    store.create_todo(&task1, now);
+    store.create_todo(&task2, now);
+    assert_eq!(store.iter_todos(None, None).count(), 5);
+    assert_eq!(store.iter_todos(Some(&task1), None).count(), 4);
+    assert_eq!(store.iter_todos(Some(&task1), Some(true)).count(), 3);
+    assert_eq!(store.iter_todos(None, Some(false)).count(), 2);
+    assert_eq!(store.get_todos(Some(&task2), Some(false)).len(), 1);
+  }
+
+  #[test]
+  fn iter_completed_todos_includes_boundaries() {
+    let mut store = MemStore::new();
+    let start = OffsetDateTime::UNIX_EPOCH;
+    let task = store.create_task("Task".into(), start);
+    for days in 1..=3 {
+      let todo = store.create_todo(&task, start);
+      let completed = TodoCompleted::new(start + Duration::days(days));
+      store.set_todo_completed(&todo, Some(completed)).unwrap();
+    }
+    store.create_todo(&task, start);
+    let completed = |from: Option<i64>, to: Option<i64>| {
+      let mut days: Vec<_> = store
+        .iter_completed_todos(
+          Some(&task),
+          from.map(|days| start + Duration::days(days)),
+          to.map(|days| start + Duration::days(days)),
+        )
+        .map(|todo| (todo.completed.as_ref().unwrap().date - start).whole_days())
+        .collect();
+      days.sort_unstable();
+      days
+    };
+    assert_eq!(completed(None, None), [1, 2, 3]);
+    assert_eq!(completed(Some(1), Some(2)), [1, 2]);
+    assert_eq!(completed(Some(2), None), [2, 3]);
+    assert_eq!(completed(None, Some(1)), [1]);
+    assert!(completed(Some(4), None).is_empty());
+  }
 }
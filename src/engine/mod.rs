@@ -1,79 +1,279 @@
 mod data;
 mod due_guesser;
+#[allow(clippy::module_inception)]
 mod engine;
 mod logger;
 mod mem_store;
+mod prune;
+mod renumber;
 mod store;
 
-pub use data::{Task, TaskId, Todo, TodoCompleted, TodoDate, TodoId};
-pub use engine::{try_new, Allesatt};
-pub use logger::{Logger, ReadWriteLogger};
+pub use data::{LogDate, LogDuration, Task, TaskId, Todo, TodoCompleted, TodoDate, TodoId};
+pub use due_guesser::{Calendar, IntervalKind, TaskStats};
+pub use engine::{
+  try_from_snapshot, try_new, try_new_in_timezone, try_new_with_calendar, Allesatt,
+};
+pub use logger::{convert_log, LogFormat, LogPosition, Logger, ReadWriteLogger, LOG_COMMANDS};
 pub use mem_store::MemStore;
-pub use store::Store;
+pub use prune::prune;
+pub use renumber::renumber;
+pub use store::{Inconsistency, OpenTodoError, Store};
 
 use rand::{seq::index::sample, thread_rng};
-use time::{Duration, OffsetDateTime};
+use std::collections::HashSet;
+use std::time::Duration as StdDuration;
+use time::OffsetDateTime;
 
 const MAX_DUE: usize = 5;
 const MAX_NOT_DUE: usize = 3;
-const RANDOM_SAMPLE: bool = true;
 
-pub fn get_todos(
-  store: &'_ impl Store,
+/// How many todos `get_todos` returns if not asked for all of them
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+  pub due: usize,
+  pub not_due: usize,
+  /// Pick a random selection of the due todos if there are too many, rather than the most overdue
+  pub sample: bool,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Self {
+      due: MAX_DUE,
+      not_due: MAX_NOT_DUE,
+      sample: false,
+    }
+  }
+}
+
+/// Returns how many more times a task with limited completions needs doing
+pub fn remaining_completions(store: &impl Store, task: &Task) -> Option<u32> {
+  let max_completions = task.max_completions?;
+  let completed = store.iter_todos(Some(&task.id), Some(true)).count();
+  Some(max_completions.saturating_sub(completed.try_into().unwrap_or(u32::MAX)))
+}
+
+/// How far ahead todos count as due rather than upcoming by default
+pub const DEFAULT_SOON: StdDuration = StdDuration::from_hours(24);
+
+/// Whether a todo counts as due rather than upcoming when listing todos
+///
+/// Todos count as due if they are due within `soon` from `now`, or within the lead time of
+/// their task.
+#[must_use]
+pub fn is_due(todo: &Todo, task: &Task, now: OffsetDateTime, soon: StdDuration) -> bool {
+  todo.due <= now + soon + task.lead_time
+}
+
+/// Returns the todos that were open at `as_of` with their tasks, ordered by task id
+///
+/// This only ignores completions after `as_of`, so other changes made since, like postponing a
+/// todo or pausing a task, still show.
+pub fn open_todos_as_of(store: &impl Store, as_of: TodoDate) -> Vec<(&Todo, &Task)> {
+  store
+    .get_tasks()
+    .into_iter()
+    .filter(|task| task.created <= as_of)
+    .filter_map(|task| {
+      let todo = store
+        .iter_todos(Some(&task.id), None)
+        .filter(|todo| {
+          todo
+            .completed
+            .as_ref()
+            .is_none_or(|completed| completed.date > as_of)
+        })
+        .min_by_key(|todo| {
+          let completed = todo.completed.as_ref().map(|completed| completed.date);
+          (completed.is_none(), completed)
+        })?;
+      Some((todo, task))
+    })
+    .collect()
+}
+
+/// Returns the todos to show, the paused tasks and whether some due todos were left out
+///
+/// Todos are ordered by due date, then by task id. Todos due within `soon`, or within the lead
+/// time of their task, count as due. Without
+/// `all`, at most `limits.due` due todos are returned, or, if `budget` is given, as many due todos
+/// as fit into that total effort. Those are the most overdue ones unless `limits.sample` is set.
+/// With `as_of`, the todos are listed as they were open at that time, see `open_todos_as_of`.
+/// Todos of blocked tasks are left out, but their tasks don't count as paused.
+pub fn get_todos<'a>(
+  store: &'a impl Store,
   all: bool,
-) -> (Vec<(&'_ Todo, &'_ Task)>, Vec<&'_ Task>, bool) {
-  let tasks = store.get_tasks();
-  let mut todos_due: Vec<(&Todo, _)> = Vec::with_capacity(if all { tasks.len() } else { MAX_DUE });
-  let mut todos_not_due: Vec<(&Todo, _)> =
-    Vec::with_capacity(if all { tasks.len() } else { MAX_NOT_DUE });
+  budget: Option<StdDuration>,
+  limits: &Limits,
+  soon: StdDuration,
+  as_of: Option<TodoDate>,
+) -> (Vec<(&'a Todo, &'a Task)>, Vec<&'a Task>, bool) {
+  let mut tasks = store.get_tasks();
+  if let Some(as_of) = as_of {
+    tasks.retain(|task| task.created <= as_of);
+  }
+  let max_due = if all || budget.is_some() {
+    tasks.len()
+  } else {
+    limits.due
+  };
+  let max_not_due = if all { tasks.len() } else { limits.not_due };
+  let mut todos_due: Vec<(&Todo, &Task)> = Vec::with_capacity(max_due);
+  let mut todos_not_due: Vec<(&Todo, &Task)> = Vec::with_capacity(max_not_due);
   let mut paused_tasks: Vec<&Task> = Vec::new();
   let mut and_more = false;
-  let tomorrow = OffsetDateTime::now_utc() + Duration::DAY;
-  for task in tasks {
-    #[allow(clippy::option_if_let_else)]
-    if let Some(todo) = store.find_open_todo(&task.id) {
-      let due = todo.due <= tomorrow;
-      let todos = if due {
-        &mut todos_due
-      } else {
-        &mut todos_not_due
-      };
-      let pos = todos
-        .binary_search_by_key(&todo.due, |&(t, _)| t.due)
-        .unwrap_or_else(|e| e);
-      if all || pos < (if due { MAX_DUE } else { MAX_NOT_DUE }) {
-        todos.insert(pos, (todo, task));
-      } else if due {
-        and_more = true;
-      }
+  let now = as_of.unwrap_or_else(OffsetDateTime::now_utc);
+  let open_todos = as_of.map_or_else(
+    || store.open_todos(),
+    |as_of| open_todos_as_of(store, as_of),
+  );
+  let open_tasks: HashSet<_> = open_todos.iter().map(|(_, task)| &task.id).collect();
+  paused_tasks.extend(tasks.iter().filter(|task| !open_tasks.contains(&task.id)));
+  for (todo, task) in open_todos {
+    if task.blocked.is_some() {
+      continue;
+    }
+    let due = is_due(todo, task, now, soon);
+    let todos = if due {
+      &mut todos_due
     } else {
-      let pos = paused_tasks
-        .binary_search_by_key(&&task.id, |&task| &task.id)
-        .unwrap_or_else(|e| e);
-      paused_tasks.insert(pos, task);
+      &mut todos_not_due
+    };
+    let pos = todos
+      .binary_search_by_key(&(todo.due, &task.id), |&(t, task)| (t.due, &task.id))
+      .unwrap_or_else(|e| e);
+    if pos < (if due { max_due } else { max_not_due }) {
+      todos.insert(pos, (todo, task));
+    } else if due {
+      and_more = true;
     }
   }
   let mut todos = todos_due;
+  let mut spent = StdDuration::ZERO;
+  let within_budget = budget.map(|budget| {
+    todos
+      .iter()
+      .take_while(|(_, task)| {
+        spent += task.effort;
+        spent <= budget
+      })
+      .count()
+  });
   if all {
     todos.append(&mut todos_not_due);
-  } else if todos.len() > MAX_DUE {
+  } else if let Some(within_budget) = within_budget.filter(|&n| n < todos.len()) {
+    and_more = true;
+    todos.truncate(within_budget);
+  } else if budget.is_none() && todos.len() > limits.due {
     and_more = true;
-    if RANDOM_SAMPLE {
+    if limits.sample {
       let mut rng = thread_rng();
-      let mut idxs = sample(&mut rng, todos.len(), MAX_DUE).into_vec();
+      let mut idxs = sample(&mut rng, todos.len(), limits.due).into_vec();
       idxs.sort_unstable_by(|a, b| b.cmp(a)); // sort reverse
-      let mut todos_new = Vec::with_capacity(MAX_DUE);
+      let mut todos_new = Vec::with_capacity(limits.due);
       for i in idxs {
         todos_new.push(todos.drain(i..).next().unwrap());
       }
       todos_new.reverse();
       todos = todos_new;
     } else {
-      todos.truncate(MAX_DUE);
+      todos.truncate(limits.due);
     }
   } else {
-    todos_not_due.truncate(MAX_NOT_DUE.saturating_sub(todos.len()));
+    todos_not_due.truncate(limits.not_due.saturating_sub(todos.len()));
     todos.append(&mut todos_not_due);
-  };
+  }
   (todos, paused_tasks, and_more)
 }
+
+#[cfg(test)]
+mod test {
+  use super::{get_todos, Limits, MemStore, Store, TodoCompleted, DEFAULT_SOON};
+  use std::time::Duration;
+  use time::macros::datetime;
+
+  #[test]
+  fn orders_same_due_todos_by_task_id() {
+    let mut store = MemStore::new();
+    let due = datetime!(2024-01-01 10:00 UTC);
+    for title in ["a", "b", "c", "d", "e", "f"] {
+      let task_id = store.create_task(title.into(), due);
+      store.create_todo(&task_id, due);
+    }
+    let (todos, _, _) = get_todos(&store, true, None, &Limits::default(), DEFAULT_SOON, None);
+    let titles: Vec<_> = todos.iter().map(|(_, task)| task.title.as_str()).collect();
+    assert_eq!(titles, ["a", "b", "c", "d", "e", "f"]);
+  }
+
+  #[test]
+  fn lists_todos_due_within_lead_time() {
+    let mut store = MemStore::new();
+    let due = datetime!(2024-01-10 10:00 UTC);
+    for (title, lead_time) in [("Replace filter", 3), ("Sweep", 0)] {
+      let task_id = store.create_task(title.into(), datetime!(2024-01-01 10:00 UTC));
+      store.create_todo(&task_id, due);
+      store
+        .set_task_lead_time(&task_id, Duration::from_hours(24 * lead_time))
+        .unwrap();
+    }
+    let limits = Limits {
+      not_due: 0,
+      ..Limits::default()
+    };
+    let as_of = datetime!(2024-01-07 12:00 UTC);
+    let (todos, _, _) = get_todos(&store, false, None, &limits, DEFAULT_SOON, Some(as_of));
+    let listed: Vec<_> = todos
+      .iter()
+      .map(|(todo, task)| (task.title.as_str(), todo.due))
+      .collect();
+    assert_eq!(listed, [("Replace filter", due)]);
+  }
+
+  #[test]
+  fn lists_todos_as_of() {
+    let mut store = MemStore::new();
+    let task_id = store.create_task("Sweep".into(), datetime!(2024-01-01 10:00 UTC));
+    for (due, completed) in [
+      (
+        datetime!(2024-01-01 10:00 UTC),
+        datetime!(2024-01-02 10:00 UTC),
+      ),
+      (
+        datetime!(2024-01-09 10:00 UTC),
+        datetime!(2024-01-12 10:00 UTC),
+      ),
+    ] {
+      let todo_id = store.create_todo(&task_id, due);
+      store
+        .set_todo_completed(&todo_id, Some(TodoCompleted::new(completed)))
+        .unwrap();
+    }
+    store.create_todo(&task_id, datetime!(2024-01-19 10:00 UTC));
+    let later = store.create_task("Mop".into(), datetime!(2024-01-15 10:00 UTC));
+    store.create_todo(&later, datetime!(2024-01-15 10:00 UTC));
+
+    let due_as_of = |as_of| {
+      let (todos, paused, _) = get_todos(
+        &store,
+        true,
+        None,
+        &Limits::default(),
+        DEFAULT_SOON,
+        Some(as_of),
+      );
+      assert!(paused.is_empty());
+      todos.iter().map(|(todo, _)| todo.due).collect::<Vec<_>>()
+    };
+    assert_eq!(
+      due_as_of(datetime!(2024-01-10 10:00 UTC)),
+      [datetime!(2024-01-09 10:00 UTC)]
+    );
+    assert_eq!(
+      due_as_of(datetime!(2024-01-16 10:00 UTC)),
+      [
+        datetime!(2024-01-15 10:00 UTC),
+        datetime!(2024-01-19 10:00 UTC)
+      ]
+    );
+  }
+}
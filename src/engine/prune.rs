@@ -0,0 +1,225 @@
+use serde_json::{from_str as from_json, from_value, to_string as to_json, to_value, Value};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{empty, sink, Read, Sink, Write};
+
+use super::due_guesser::MAX_SAMPLES;
+use super::logger::{parse_line, to_v1};
+use super::renumber::{create_task_unchecked, refers_to_todo};
+use super::{try_new, Allesatt, MemStore, ReadWriteLogger, Store, TodoDate, TodoId};
+
+/// Completions kept of each task however old they are, one more than the intervals the due
+/// guesser averages over
+const KEEP_COMPLETIONS: usize = MAX_SAMPLES as usize + 1;
+
+/// Returns the todos whose completions `prune` leaves out
+fn removed_todos(store: &impl Store, before: TodoDate) -> HashSet<TodoId> {
+  let mut removed = HashSet::new();
+  for task in store.get_tasks() {
+    if task.max_completions.is_some() {
+      continue;
+    }
+    let mut completions: Vec<_> = store
+      .iter_todos(Some(&task.id), Some(true))
+      .filter_map(|todo| Some((todo.completed.as_ref()?.date, todo.id.clone())))
+      .collect();
+    completions.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+    removed.extend(
+      completions
+        .into_iter()
+        .skip(KEEP_COMPLETIONS)
+        .filter(|(date, _)| *date < before)
+        .map(|(_, todo_id)| todo_id),
+    );
+  }
+  removed
+}
+
+/// Replays `line` of the original log in `original` and, unless it is about a removed
+/// completion, in `pruned`, referring to the corresponding todo there
+///
+/// `todos` maps the todos of `original` to the ones of `pruned`. Tasks keep their ids, since
+/// none are left out.
+fn prune_line(
+  line: &str,
+  original: &mut impl Allesatt,
+  pruned: &mut impl Allesatt,
+  todos: &mut HashMap<TodoId, TodoId>,
+  removed: &HashSet<TodoId>,
+) -> Result<(), Box<dyn Error>> {
+  let line = &*to_v1(line)?;
+  let (kind, v) = line.split_at(line.find(':').ok_or_else(|| String::from("Invalid line"))? + 1);
+  let mut keep = true;
+  let mut args: Vec<Value> = from_json(v)?;
+  if refers_to_todo(kind) {
+    let id = args.first_mut().ok_or("Invalid line")?;
+    let todo_id: TodoId = from_value(id.take())?;
+    keep = !removed.contains(&todo_id)
+      || !matches!(
+        kind,
+        "complete_todo1:" | "amend_completion1:" | "reattach_todo1:"
+      );
+    *id = to_value(todos.get(&todo_id).ok_or("Todo not found")?)?;
+  }
+  parse_line(line, original)?;
+  // Created tasks' todos get other ids once completions are left out
+  let created = create_task_unchecked(kind, v, pruned, |id| Ok(id.clone()))?;
+  if keep && created.is_none() {
+    parse_line(&format!("{kind} {}", to_json(&args)?), pruned)?;
+  }
+  for (todo, task) in original.get_store().open_todos() {
+    if !todos.contains_key(&todo.id) {
+      let open = pruned.get_store().find_open_todo(&task.id);
+      todos.insert(todo.id.clone(), open.ok_or("Todo not found")?.id.clone());
+    }
+  }
+  Ok(())
+}
+
+/// Replays the log in `source` and writes it to `target` without completions before `before`
+///
+/// The latest completions of each task are kept, so that the interval of regularly done tasks
+/// stays the same, as are all completions of tasks paused after some number of them. A todo
+/// whose completion is left out stays open until the next completion of its task, so the
+/// records about the todos in between refer to it instead.
+pub fn prune<W: Write>(
+  store: impl Store,
+  mut source: impl Read,
+  mut target: W,
+  before: TodoDate,
+) -> Result<(), Box<dyn Error>> {
+  let mut log = String::new();
+  source.read_to_string(&mut log)?;
+  let removed = {
+    let app = try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Sink, _>::new(log.as_bytes(), sink()),
+    )?;
+    removed_todos(app.get_store(), before)
+  };
+  // The original log is replayed alongside the pruned one to know which todos correspond
+  let mut original = try_new(
+    MemStore::new(),
+    ReadWriteLogger::<_, Sink, _>::new(empty(), sink()),
+  )?;
+  // Nothing is written to `target` if a line fails
+  let mut out = Vec::new();
+  {
+    let mut pruned = try_new(
+      store,
+      ReadWriteLogger::<_, Vec<u8>, _>::new(empty(), &mut out),
+    )?;
+    let mut todos = HashMap::new();
+    for line in log.lines() {
+      prune_line(line, &mut original, &mut pruned, &mut todos, &removed)
+        .map_err(|e| e.to_string() + "\nLine content: " + line)?;
+    }
+  }
+  target.write_all(&out)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::prune;
+  use crate::engine::{try_new, Allesatt, MemStore, ReadWriteLogger, Store, TaskId};
+  use std::fmt::Write;
+  use time::format_description::FormatItem;
+  use time::macros::{datetime, format_description};
+  use time::Duration;
+
+  const DATE_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+
+  #[test]
+  fn keeps_interval_when_pruning_long_history() {
+    let mut log = String::from(
+      "create_task2: [\"Sweep\", \"7days\", 1, 1]\ncreate_task2: [\"Descale kettle\", \"90days\", 2, 2]\n",
+    );
+    let (mut sweep, mut descale, mut next) = (1, 2, 3);
+    let start = datetime!(2021-01-04 10:00 UTC);
+    for week in 0..160 {
+      let date = (start + Duration::weeks(week))
+        .format(&DATE_FORMAT)
+        .unwrap();
+      writeln!(log, "complete_todo1: [{sweep}, \"{date}\"]").unwrap();
+      sweep = next;
+      next += 1;
+      if week % 13 == 0 && week < 40 {
+        writeln!(log, "complete_todo1: [{descale}, \"{date}\"]").unwrap();
+        descale = next;
+        next += 1;
+      }
+    }
+
+    let mut pruned = Vec::new();
+    prune(
+      MemStore::new(),
+      log.as_bytes(),
+      &mut pruned,
+      datetime!(2023-01-01 00:00 UTC),
+    )
+    .unwrap();
+    let pruned = String::from_utf8(pruned).unwrap();
+    // 104 weekly completions are before 2023, while all four of the rarely done task are kept
+    let completions = |log: &str| log.matches("complete_todo1").count();
+    assert_eq!(completions(&log), 164);
+    assert_eq!(completions(&pruned), 160 - 104 + 4);
+
+    let replay = |log: &str| {
+      let app = try_new(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+      )
+      .unwrap();
+      [TaskId::new(1), TaskId::new(2)].map(|task_id| {
+        (
+          app.task_stats(&task_id).unwrap().interval,
+          app.get_store().find_open_todo(&task_id).unwrap().due,
+        )
+      })
+    };
+    assert_eq!(replay(&pruned), replay(&log));
+  }
+
+  #[test]
+  fn renumbers_tasks_created_after_pruned_completions() {
+    let mut log = String::from("create_task2: [\"Sweep\", \"7days\", 1, 1]\n");
+    let start = datetime!(2020-01-06 10:00 UTC);
+    for week in 0..30 {
+      let date = (start + Duration::weeks(week))
+        .format(&DATE_FORMAT)
+        .unwrap();
+      writeln!(log, "complete_todo1: [{}, \"{date}\"]", week + 1).unwrap();
+    }
+    log.push_str("create_task2: [\"Mop\", \"7days\", 2, 32]\n");
+    log.push_str("complete_todo1: [32, \"2020-09-01T10:00:00\"]\n");
+
+    let mut pruned = Vec::new();
+    prune(
+      MemStore::new(),
+      log.as_bytes(),
+      &mut pruned,
+      datetime!(2021-01-01 00:00 UTC),
+    )
+    .unwrap();
+    let pruned = String::from_utf8(pruned).unwrap();
+    assert!(pruned.matches("complete_todo1").count() < 31);
+
+    let replay = |log: &str| {
+      let app = try_new(
+        MemStore::new(),
+        ReadWriteLogger::<_, Vec<u8>, _>::new(log.as_bytes(), Vec::new()),
+      )
+      .unwrap();
+      [TaskId::new(1), TaskId::new(2)].map(|task_id| {
+        let todo = app.get_store().find_open_todo(&task_id).unwrap();
+        (
+          todo.due,
+          app.get_store().get_task(&task_id).unwrap().title.clone(),
+        )
+      })
+    };
+    assert_eq!(replay(&pruned), replay(&log));
+  }
+}
@@ -0,0 +1,267 @@
+use serde_json::{from_str as from_json, from_value, to_string as to_json, to_value, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{empty, BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+use super::logger::{parse_line, replayed_interval, to_v1};
+use super::{try_new, Allesatt, LogDate, LogDuration, ReadWriteLogger, Store, TaskId, TodoId};
+
+/// Maps the ids of a log to the ones assigned while replaying it
+#[derive(Debug, Default)]
+struct IdMap {
+  tasks: HashMap<TaskId, TaskId>,
+  todos: HashMap<u64, TodoId>,
+  /// Original ids of the todos created so far, guessed for todos created implicitly
+  original_todos: HashMap<TodoId, u64>,
+  /// Largest known difference between original and new todo ids
+  offset: u64,
+}
+
+impl IdMap {
+  fn add_task(&mut self, original: (TaskId, TodoId), new: (TaskId, TodoId)) {
+    self.tasks.insert(original.0, new.0);
+    self.add_todo(original.1.get(), new.1);
+  }
+
+  fn add_todo(&mut self, original: u64, id: TodoId) {
+    self.offset = self.offset.max(original.saturating_sub(id.get()));
+    self.todos.insert(original, id.clone());
+    self.original_todos.insert(id, original);
+  }
+
+  /// Records a todo that was created as a side effect, e.g. by completing another one
+  fn add_implicit_todo(&mut self, id: &TodoId) {
+    if !self.original_todos.contains_key(id) {
+      self.add_todo(id.get() + self.offset, id.clone());
+    }
+  }
+
+  fn task(&self, original: &TaskId) -> Result<TaskId, Box<dyn Error>> {
+    Ok(
+      self
+        .tasks
+        .get(original)
+        .ok_or_else(|| format!("Task {original} not found"))?
+        .clone(),
+    )
+  }
+
+  fn todo(&mut self, original: u64, store: &impl Store) -> Result<TodoId, Box<dyn Error>> {
+    if let Some(id) = self.todos.get(&original) {
+      return Ok(id.clone());
+    }
+    // The original ids of implicitly created todos are only known up to the number of
+    // todos created by removed lines, so take the most recent open todo that fits.
+    let id = store
+      .open_todos()
+      .into_iter()
+      .map(|(todo, _)| &todo.id)
+      .filter(|id| self.original_todos.get(id) < Some(&original))
+      .max_by_key(|id| id.get())
+      .ok_or_else(|| format!("Todo {original} not found"))?
+      .clone();
+    if let Some(guess) = self.original_todos.get(&id) {
+      self.todos.remove(guess);
+    }
+    self.add_todo(original, id.clone());
+    Ok(id)
+  }
+}
+
+/// Whether records of this kind (including the colon) have a todo id as their first argument
+pub(super) fn refers_to_todo(kind: &str) -> bool {
+  matches!(
+    kind,
+    "complete_todo1:"
+      | "amend_completion1:"
+      | "reattach_todo1:"
+      | "todo_later1:"
+      | "undo_todo_later1:"
+      | "postpone_todo1:"
+      | "set_todo_due1:"
+      | "discard_todo1:"
+      | "set_todo_progress1:"
+  )
+}
+
+/// Task and todo ids of a created task
+type CreatedIds = (TaskId, TodoId);
+
+/// Replays a record of kind `kind` (including the colon) creating a task without checking the ids
+/// it logged
+///
+/// Returns the logged ids and the ones assigned now, or `None` for records of other kinds.
+/// `task` maps the logged id of a task to clone to the one to clone now.
+pub(super) fn create_task_unchecked(
+  kind: &str,
+  v: &str,
+  app: &mut impl Allesatt,
+  task: impl Fn(&TaskId) -> Result<TaskId, Box<dyn Error>>,
+) -> Result<Option<(CreatedIds, CreatedIds)>, Box<dyn Error>> {
+  Ok(Some(match kind {
+    "create_task1:" => {
+      let (title, due_every, task_id, todo_id): (_, Option<Duration>, _, _) = from_json(v)?;
+      let due_every = due_every.map(replayed_interval);
+      ((task_id, todo_id), app.create_task(title, due_every, None)?)
+    }
+    "create_task2:" => {
+      let (title, due_every, task_id, todo_id): (_, Option<LogDuration>, _, _) = from_json(v)?;
+      let due_every = due_every.map(|LogDuration(due_every)| replayed_interval(due_every));
+      ((task_id, todo_id), app.create_task(title, due_every, None)?)
+    }
+    "create_task3:" => {
+      let (title, due_every, LogDate(created), task_id, todo_id): (
+        _,
+        Option<LogDuration>,
+        _,
+        _,
+        _,
+      ) = from_json(v)?;
+      let due_every = due_every.map(|LogDuration(due_every)| replayed_interval(due_every));
+      (
+        (task_id, todo_id),
+        app.create_task(title, due_every, Some(created))?,
+      )
+    }
+    "clone_task1:" => {
+      let (task_id, title, new_task_id, todo_id): (TaskId, _, _, _) = from_json(v)?;
+      (
+        (new_task_id, todo_id),
+        app.clone_task(&task(&task_id)?, title, None)?,
+      )
+    }
+    "clone_task2:" => {
+      let (task_id, title, LogDate(created), new_task_id, todo_id): (TaskId, _, _, _, _) =
+        from_json(v)?;
+      (
+        (new_task_id, todo_id),
+        app.clone_task(&task(&task_id)?, title, Some(created))?,
+      )
+    }
+    _ => return Ok(None),
+  }))
+}
+
+fn renumber_line(
+  line: &str,
+  app: &mut impl Allesatt,
+  ids: &mut IdMap,
+) -> Result<(), Box<dyn Error>> {
+  let line = &*to_v1(line)?;
+  let (kind, v) = line.split_at(line.find(':').ok_or_else(|| String::from("Invalid line"))? + 1);
+  if let Some((original, new)) = create_task_unchecked(kind, v, app, |id| ids.task(id))? {
+    ids.add_task(original, new);
+    return Ok(());
+  }
+  let task_id = match kind {
+    "swap_tasks1:" => {
+      let (a, b): (TaskId, TaskId) = from_json(v)?;
+      app.swap_tasks(&ids.task(&a)?, &ids.task(&b)?)?;
+      return Ok(());
+    }
+    "reattach_todo1:" => {
+      let (todo_id, task_id): (u64, TaskId) = from_json(v)?;
+      let todo_id = ids.todo(todo_id, app.get_store())?;
+      let task_id = ids.task(&task_id)?;
+      app.reattach_todo(&todo_id, &task_id)?;
+      task_id
+    }
+    "set_task_after1:" => {
+      let (task_id, anchor): (TaskId, Option<TaskId>) = from_json(v)?;
+      let task_id = ids.task(&task_id)?;
+      app.set_task_after(
+        &task_id,
+        anchor.map(|anchor| ids.task(&anchor)).transpose()?,
+      )?;
+      task_id
+    }
+    _ => {
+      let mut args: Vec<Value> = from_json(v)?;
+      let id = args.first_mut().ok_or("Invalid line")?;
+      let task_id = if refers_to_todo(kind) {
+        let todo_id = ids.todo(from_value(id.take())?, app.get_store())?;
+        *id = to_value(&todo_id)?;
+        app
+          .get_store()
+          .get_todo(&todo_id)
+          .ok_or("Todo not found")?
+          .task
+          .clone()
+      } else {
+        let task_id = ids.task(&from_value(id.take())?)?;
+        *id = to_value(&task_id)?;
+        task_id
+      };
+      parse_line(&format!("{kind} {}", to_json(&args)?), app)?;
+      task_id
+    }
+  };
+  if let Some(todo) = app.get_store().find_open_todo(&task_id) {
+    ids.add_implicit_todo(&todo.id);
+  }
+  Ok(())
+}
+
+/// Replays the log in `source` and writes it to `target` with sequential ids
+///
+/// This repairs logs that had lines removed. Since todo ids are only recorded when creating
+/// tasks, a todo referenced after the removed lines is matched to the most recent open todo
+/// that could have had its id.
+pub fn renumber<W: Write>(
+  store: impl Store,
+  source: impl Read,
+  mut target: W,
+) -> Result<(), Box<dyn Error>> {
+  let mut app = try_new(store, ReadWriteLogger::<_, W, _>::new(empty(), &mut target))?;
+  let mut ids = IdMap::default();
+  for line_result in BufReader::new(source).lines() {
+    let line = line_result?;
+    renumber_line(&line, &mut app, &mut ids)
+      .map_err(|e| e.to_string() + "\nLine content: " + &line)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::renumber;
+  use crate::engine::{try_new, MemStore, ReadWriteLogger};
+
+  #[test]
+  fn renumbers_log_with_gap() {
+    let log = r#"create_task2: ["Task 1", "1day", 1, 1]
+create_task2: ["Task 2", "1day", 2, 2]
+create_task2: ["Task 3", "1day", 3, 3]
+complete_todo1: [3, "2024-01-01T00:00:00"]
+complete_todo1: [1, "2024-01-01T00:00:00"]
+create_task2: ["Task 4", "1day", 4, 6]
+complete_todo1: [5, "2024-01-02T00:00:00"]
+rename_task1: [4, "Task four"]
+complete_todo1: [6, "2024-01-03T00:00:00"]
+"#;
+    let with_gap: String = log
+      .lines()
+      .filter(|line| !line.contains("Task 2") && !line.starts_with("complete_todo1: [3,"))
+      .map(|line| line.to_owned() + "\n")
+      .collect();
+    let mut renumbered = Vec::new();
+    renumber(MemStore::new(), with_gap.as_bytes(), &mut renumbered).unwrap();
+    assert_eq!(
+      String::from_utf8(renumbered.clone()).unwrap(),
+      r#"create_task2: ["Task 1", "1day", 1, 1]
+create_task2: ["Task 3", "1day", 2, 2]
+complete_todo1: [1, "2024-01-01T00:00:00.0"]
+create_task2: ["Task 4", "1day", 3, 4]
+complete_todo1: [3, "2024-01-02T00:00:00.0"]
+rename_task2: [3, "Task 4", "Task four"]
+complete_todo1: [4, "2024-01-03T00:00:00.0"]
+"#
+    );
+    assert!(try_new(
+      MemStore::new(),
+      ReadWriteLogger::<_, Vec<u8>, _>::new(renumbered.as_slice(), Vec::new())
+    )
+    .is_ok());
+  }
+}
@@ -1,25 +1,218 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Duration;
 
 use super::{Task, TaskId, Todo, TodoCompleted, TodoDate, TodoId};
 
+/// A broken invariant found by `Store::validate`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Inconsistency {
+  /// A todo belongs to a task that doesn't exist
+  OrphanTodo(TodoId, TaskId),
+  /// A task has more than one open todo
+  SeveralOpenTodos(TaskId, Vec<TodoId>),
+  /// `find_open_todo` doesn't return the only open todo of a task
+  WrongOpenTodo(TaskId, Option<TodoId>),
+}
+
+impl Display for Inconsistency {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::OrphanTodo(todo, task) => write!(f, "Todo {todo} belongs to missing task {task}"),
+      Self::SeveralOpenTodos(task, todos) => {
+        let todos: Vec<_> = todos.iter().map(ToString::to_string).collect();
+        write!(
+          f,
+          "Task {task} has several open todos: {}",
+          todos.join(", ")
+        )
+      }
+      Self::WrongOpenTodo(task, todo) => match todo {
+        Some(todo) => write!(
+          f,
+          "Task {task} is not open, but todo {todo} is found for it"
+        ),
+        None => write!(f, "Task {task} has an open todo that isn't found"),
+      },
+    }
+  }
+}
+
+/// Why a task has no open todo, see `Store::get_open_todo`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OpenTodoError {
+  /// The task doesn't exist
+  TaskNotFound(TaskId),
+  /// The task exists, but is paused
+  Paused(TaskId),
+}
+
+impl Display for OpenTodoError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::TaskNotFound(_) => write!(f, "Task not found"),
+      Self::Paused(_) => write!(f, "Task is paused, unpause it first"),
+    }
+  }
+}
+
+impl Error for OpenTodoError {}
+
 pub trait Store {
-  fn create_task(&mut self, title: String) -> TaskId;
+  fn create_task(&mut self, title: String, created: TodoDate) -> TaskId;
+  /// Creates an open todo for `task`
+  ///
+  /// Logs written by older versions can open a second todo for a task, so this doesn't fail if
+  /// the task already has one. `validate` reports that instead.
   fn create_todo(&mut self, task: &TaskId, due: TodoDate) -> TodoId;
+  fn set_task_title(&mut self, task: &TaskId, title: String) -> Result<(), Box<dyn Error>>;
+  fn set_task_effort(&mut self, task: &TaskId, effort: Duration) -> Result<(), Box<dyn Error>>;
+  fn set_task_lead_time(
+    &mut self,
+    task: &TaskId,
+    lead_time: Duration,
+  ) -> Result<(), Box<dyn Error>>;
+  fn set_task_url(&mut self, task: &TaskId, url: Option<String>) -> Result<(), Box<dyn Error>>;
+  fn set_task_created(&mut self, task: &TaskId, created: TodoDate) -> Result<(), Box<dyn Error>>;
+  fn set_task_blocked(
+    &mut self,
+    task: &TaskId,
+    blocked: Option<String>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn set_task_max_completions(
+    &mut self,
+    task: &TaskId,
+    max_completions: Option<u32>,
+  ) -> Result<(), Box<dyn Error>>;
+  fn add_task_alias(&mut self, task: &TaskId, alias: String) -> Result<(), Box<dyn Error>>;
+  /// Exchanges the ids of two tasks, moving their todos along
+  fn swap_task_ids(&mut self, a: &TaskId, b: &TaskId) -> Result<(), Box<dyn Error>>;
   fn set_todo_completed(
     &mut self,
     todo: &TodoId,
     completed: Option<TodoCompleted>,
   ) -> Result<(), Box<dyn Error>>;
   fn set_todo_due(&mut self, todo: &TodoId, due: TodoDate) -> Result<(), Box<dyn Error>>;
+  /// Moves a completed todo to another task
+  fn set_todo_task(&mut self, todo: &TodoId, task: &TaskId) -> Result<(), Box<dyn Error>>;
+  fn set_todo_progress(
+    &mut self,
+    todo: &TodoId,
+    progress: Option<(u32, u32)>,
+  ) -> Result<(), Box<dyn Error>>;
   fn delete_todo(&mut self, todo: &TodoId) -> Result<(), Box<dyn Error>>;
 
   fn get_task(&self, task: &TaskId) -> Option<&Task>;
   fn get_tasks(&self) -> Vec<&Task>;
+  fn find_task_by_alias(&self, alias: &str) -> Option<&Task> {
+    self
+      .get_tasks()
+      .into_iter()
+      .find(|task| task.aliases.iter().any(|a| a == alias))
+  }
+  fn find_task_by_title(&self, title: &str) -> Option<&Task> {
+    self
+      .get_tasks()
+      .into_iter()
+      .find(|task| task.title == title)
+  }
   fn get_todo(&self, todo: &TodoId) -> Option<&Todo>;
+  fn iter_todos<'a>(
+    &'a self,
+    task_id_filter: Option<&TaskId>,
+    completed_filter: Option<bool>,
+  ) -> impl Iterator<Item = &'a Todo> + 'a;
   fn get_todos(
     &self,
     task_id_filter: Option<&TaskId>,
     completed_filter: Option<bool>,
-  ) -> Vec<&Todo>;
+  ) -> Vec<&Todo> {
+    self.iter_todos(task_id_filter, completed_filter).collect()
+  }
+  /// Returns the todos completed between `from` and `to`, both inclusive and optional
+  ///
+  /// Stores that can look up todos by completion date should do so here rather than filtering
+  /// all completed todos.
+  fn iter_completed_todos<'a>(
+    &'a self,
+    task_id_filter: Option<&TaskId>,
+    from: Option<TodoDate>,
+    to: Option<TodoDate>,
+  ) -> impl Iterator<Item = &'a Todo> + 'a {
+    self
+      .iter_todos(task_id_filter, Some(true))
+      .filter(move |todo| {
+        todo.completed.as_ref().is_some_and(|completed| {
+          from.is_none_or(|from| completed.date >= from) && to.is_none_or(|to| completed.date <= to)
+        })
+      })
+  }
+  /// Returns whether the given task was completed at or after `since`
+  fn completed_since(&self, task: &TaskId, since: TodoDate) -> bool {
+    self
+      .iter_completed_todos(Some(task), Some(since), None)
+      .next()
+      .is_some()
+  }
   fn find_open_todo(&self, task: &TaskId) -> Option<&Todo>;
+  /// Like `find_open_todo`, but tells apart missing and paused tasks
+  fn get_open_todo(&self, task: &TaskId) -> Result<&Todo, OpenTodoError> {
+    self.find_open_todo(task).ok_or_else(|| {
+      if self.get_task(task).is_some() {
+        OpenTodoError::Paused(task.clone())
+      } else {
+        OpenTodoError::TaskNotFound(task.clone())
+      }
+    })
+  }
+  /// Returns all open todos with their tasks, ordered by task id
+  fn open_todos(&self) -> Vec<(&Todo, &Task)> {
+    self
+      .get_tasks()
+      .into_iter()
+      .filter_map(|task| Some((self.find_open_todo(&task.id)?, task)))
+      .collect()
+  }
+
+  /// Checks the invariants the engine relies on, returning all broken ones
+  fn validate(&self) -> Result<(), Vec<Inconsistency>> {
+    let mut problems = vec![];
+    let mut open: HashMap<&TaskId, Vec<TodoId>> = HashMap::new();
+    for todo in self.iter_todos(None, None) {
+      if self.get_task(&todo.task).is_none() {
+        problems.push(Inconsistency::OrphanTodo(
+          todo.id.clone(),
+          todo.task.clone(),
+        ));
+      } else if todo.completed.is_none() {
+        open.entry(&todo.task).or_default().push(todo.id.clone());
+      }
+    }
+    for task in self.get_tasks() {
+      let found = self.find_open_todo(&task.id).map(|todo| todo.id.clone());
+      match open.remove(&task.id).unwrap_or_default() {
+        mut todos if todos.len() > 1 => {
+          todos.sort_by_key(TodoId::get);
+          problems.push(Inconsistency::SeveralOpenTodos(task.id.clone(), todos));
+        }
+        todos if todos.first() != found.as_ref() => {
+          problems.push(Inconsistency::WrongOpenTodo(task.id.clone(), found));
+        }
+        _ => (),
+      }
+    }
+    if problems.is_empty() {
+      Ok(())
+    } else {
+      Err(problems)
+    }
+  }
+
+  /// Starts a group of changes that should be applied atomically
+  fn begin(&mut self) {}
+  /// Finishes a group of changes started with `begin`
+  fn commit(&mut self) {}
+  /// Reverts all changes since the last `begin`, if the store supports it
+  fn rollback(&mut self) {}
 }
@@ -18,6 +18,7 @@
 // Fix these for an actually usable crate
 #![allow(
   clippy::cargo_common_metadata,
+  clippy::multiple_crate_versions,
   unreachable_pub,
   rustdoc::all,
   missing_docs,